@@ -91,6 +91,35 @@ fn impl_address(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
                 fn is_page_aligned<P: Page>(&self) -> bool {
                     self.0 % P::SIZE as #repr == 0 as #repr
                 }
+
+                fn checked_add(&self, bytes: usize) -> Option<Self> {
+                    use ::core::#repr;
+                    if bytes as u64 > #repr::MAX as u64 {
+                        return None;
+                    }
+                    self.0.checked_add(bytes as #repr).map(#name)
+                }
+
+                fn checked_sub(&self, bytes: usize) -> Option<Self> {
+                    use ::core::#repr;
+                    if bytes as u64 > #repr::MAX as u64 {
+                        return None;
+                    }
+                    self.0.checked_sub(bytes as #repr).map(#name)
+                }
+
+                fn distance(&self, other: Self) -> usize {
+                    let bytes = if self.0 > other.0 {
+                        self.0 - other.0
+                    } else {
+                        other.0 - self.0
+                    };
+                    if bytes as u64 > ::core::usize::MAX as u64 {
+                        ::core::usize::MAX
+                    } else {
+                        bytes as usize
+                    }
+                }
             }
         },
         quote! {
@@ -133,22 +162,19 @@ fn impl_address(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
 
                 /// Adds one to this step, returning the result
                 fn add_one(&self) -> Self {
-                    Self::from( self.0 + 1 )
+                    Address::checked_add(self, 1)
+                        .expect("address overflowed the address space")
                 }
 
                 /// Subtracts one to this step, returning the result
                 fn sub_one(&self) -> Self {
-                    Self::from( self.0 - 1 )
+                    Address::checked_sub(self, 1)
+                        .expect("address underflowed the address space")
                 }
 
                 /// Add an usize, returning None on overflow
                 fn add_usize(&self, n: usize) -> Option<Self> {
-                    use ::core::#repr;
-                    if n > (#repr::MAX as usize) {
-                        None
-                    } else {
-                        Some(Self::from( self.0 + (n as #repr)))
-                    }
+                    Address::checked_add(self, n)
                 }
             }
         },
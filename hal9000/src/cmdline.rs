@@ -0,0 +1,575 @@
+//
+// SOS: the Stupid Operating System
+//  by Eliza Weisman (eliza@elizas.website), and the SOS contributors.
+//
+//  Copyright (c) 2018 Eliza Weisman
+//  Released under the terms of the MIT license. See `LICENSE` in the root
+//  directory of this repository for more information.
+//
+//! Kernel command-line parsing.
+//!
+//! The command line is a whitespace-separated list of parameters, each of
+//! which is either a flag (`quiet`) or a `key=value` pair (`console=ttyS0`).
+//! Double quotes may be used to include whitespace in a value
+//! (`init="/bin/sh -l"`). Everything after a lone `--` is passed on to init,
+//! and is not treated as kernel parameters.
+//!
+//! Keys may be repeated. The typed getters use the last occurrence, so that
+//! parameters appended by the user override those set by the bootloader;
+//! `CommandLine::get_all` returns every occurrence.
+use core::str;
+
+/// A kernel command line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CommandLine<'a> {
+    src: &'a str,
+}
+
+/// A single parameter on the command line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Param<'a> {
+    /// The parameter's name.
+    pub key: &'a str,
+    /// The parameter's value, without surrounding quotes, or `None` if it is
+    /// a flag.
+    pub value: Option<&'a str>,
+}
+
+/// An iterator over the parameters on a command line.
+#[derive(Clone, Debug)]
+pub struct Params<'a> {
+    words: Words<'a>,
+}
+
+/// An iterator over the arguments after `--`, which are meant for init.
+#[derive(Clone, Debug)]
+pub struct InitArgs<'a> {
+    words: Words<'a>,
+}
+
+/// An iterator over the values given for a repeated key.
+#[derive(Clone, Debug)]
+pub struct GetAll<'a, 'k> {
+    params: Params<'a>,
+    key: &'k str,
+}
+
+/// A parameter that the kernel knows about.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Known {
+    /// The parameter's name.
+    ///
+    /// A name ending in `.` matches every key with that prefix, which is
+    /// useful for per-subsystem parameters such as `pci.`.
+    pub name: &'static str,
+    /// The kind of value the parameter takes.
+    pub kind: Kind,
+}
+
+/// The kind of value a known parameter takes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// A flag, which takes no value.
+    Flag,
+    /// Any string.
+    Str,
+    /// An integer; see `CommandLine::get_u64`.
+    Int,
+    /// A size with an optional suffix; see `CommandLine::get_size`.
+    Size,
+    /// A boolean; see `CommandLine::get_bool`.
+    Bool,
+    /// An address; see `CommandLine::get_addr`.
+    Addr,
+}
+
+/// An iterator over the parameters which don't match a list of known
+/// parameters.
+///
+/// This is returned by `CommandLine::check`.
+#[derive(Clone, Debug)]
+pub struct Check<'a, 'k> {
+    params: Params<'a>,
+    known: &'k [Known],
+}
+
+/// Errors returned when reading a parameter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The parameter was given as a flag, but needs a value.
+    MissingValue,
+    /// The parameter is a flag, but was given a value.
+    UnexpectedValue,
+    /// The value is not a valid number.
+    InvalidNumber,
+    /// The value is too large for the requested type.
+    Overflow,
+    /// The value is not a valid boolean.
+    InvalidBool,
+    /// The parameter is not in the list of known parameters.
+    Unknown,
+}
+
+/// Splits a command line into whitespace-separated words, keeping quoted
+/// whitespace.
+#[derive(Clone, Debug)]
+struct Words<'a> {
+    rest: &'a str,
+}
+
+// ===== impl CommandLine =====
+
+impl<'a> CommandLine<'a> {
+    /// Returns a parser for the command line `src`.
+    pub fn new(src: &'a str) -> Self {
+        CommandLine { src }
+    }
+
+    /// Returns the raw command line.
+    #[inline]
+    pub fn as_str(&self) -> &'a str {
+        self.src
+    }
+
+    /// Returns every kernel parameter, in order.
+    pub fn params(&self) -> Params<'a> {
+        Params {
+            words: Words { rest: self.src },
+        }
+    }
+
+    /// Returns the arguments after `--`, which are meant for init.
+    pub fn init_args(&self) -> InitArgs<'a> {
+        let mut words = Words { rest: self.src };
+        for word in words.by_ref() {
+            if word == "--" {
+                break;
+            }
+        }
+        InitArgs { words }
+    }
+
+    /// Returns the last occurrence of `key`, if it is present.
+    pub fn get(&self, key: &str) -> Option<Param<'a>> {
+        self.params().filter(|param| param.key == key).last()
+    }
+
+    /// Returns the value of every occurrence of `key`, in order.
+    ///
+    /// Occurrences given as flags are skipped.
+    pub fn get_all<'k>(&self, key: &'k str) -> GetAll<'a, 'k> {
+        GetAll {
+            params: self.params(),
+            key,
+        }
+    }
+
+    /// Returns true if `key` is present, with or without a value.
+    pub fn has(&self, key: &str) -> bool {
+        self.params().any(|param| param.key == key)
+    }
+
+    /// Returns the value of `key`.
+    pub fn get_str(&self, key: &str) -> Result<Option<&'a str>, Error> {
+        match self.get(key) {
+            Some(param) => param.value.ok_or(Error::MissingValue).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the value of `key` as an unsigned integer.
+    ///
+    /// Integers are decimal unless prefixed with `0x` (hexadecimal), `0o`
+    /// (octal) or `0b` (binary).
+    pub fn get_u64(&self, key: &str) -> Result<Option<u64>, Error> {
+        self.parse(key, parse_u64)
+    }
+
+    /// Returns the value of `key` as a signed integer.
+    pub fn get_i64(&self, key: &str) -> Result<Option<i64>, Error> {
+        self.parse(key, parse_i64)
+    }
+
+    /// Returns the value of `key` as a size in bytes.
+    ///
+    /// The size is an integer, optionally followed by one of the suffixes
+    /// `K`, `M`, `G` or `T` (in either case), which multiply it by the
+    /// corresponding power of 1024.
+    pub fn get_size(&self, key: &str) -> Result<Option<u64>, Error> {
+        self.parse(key, parse_size)
+    }
+
+    /// Returns the value of `key` as a boolean.
+    ///
+    /// A flag with no value is `true`. Otherwise, `1`, `y`, `yes`, `on` and
+    /// `true` are `true`, and `0`, `n`, `no`, `off` and `false` are `false`,
+    /// ignoring case.
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, Error> {
+        match self.get(key) {
+            Some(Param { value: None, .. }) => Ok(Some(true)),
+            Some(Param { value: Some(v), .. }) => parse_bool(v).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the value of `key` as an address.
+    ///
+    /// Addresses are integers, as for `get_u64`, but are usually given in
+    /// hexadecimal.
+    pub fn get_addr<A>(&self, key: &str) -> Result<Option<A>, Error>
+    where
+        A: From<usize>,
+    {
+        self.parse(key, parse_usize).map(|addr| addr.map(A::from))
+    }
+
+    /// Returns the parameters which are not in `known`, or whose values
+    /// don't match the kind `known` declares for them, with the reason each
+    /// was rejected.
+    ///
+    /// This is meant for warning about typos, not for rejecting the command
+    /// line outright.
+    pub fn check<'k>(&self, known: &'k [Known]) -> Check<'a, 'k> {
+        Check {
+            params: self.params(),
+            known,
+        }
+    }
+
+    fn parse<T>(
+        &self,
+        key: &str,
+        parse: fn(&str) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        match self.get_str(key)? {
+            Some(value) => parse(value).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// ===== impl Words =====
+
+impl<'a> Iterator for Words<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+        let mut quoted = false;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c.is_whitespace() && !quoted
+            })
+            .map(|(i, _)| i)
+            .unwrap_or_else(|| rest.len());
+        self.rest = &rest[end..];
+        Some(&rest[..end])
+    }
+}
+
+/// Removes one pair of double quotes from around `s`, and an unmatched
+/// opening quote, if there is one.
+fn unquote(s: &str) -> &str {
+    let s = if s.starts_with('"') { &s[1..] } else { s };
+    if s.ends_with('"') {
+        &s[..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+// ===== impl Params =====
+
+impl<'a> Iterator for Params<'a> {
+    type Item = Param<'a>;
+
+    fn next(&mut self) -> Option<Param<'a>> {
+        let word = self.words.next()?;
+        if word == "--" {
+            // Everything after this belongs to init.
+            self.words.rest = "";
+            return None;
+        }
+        // The whole parameter may be quoted (`"key=a b"`), or just its
+        // value (`key="a b"`).
+        let word = unquote(word);
+        Some(match word.find('=') {
+            Some(i) => Param {
+                key: &word[..i],
+                value: Some(unquote(&word[i + 1..])),
+            },
+            None => Param {
+                key: word,
+                value: None,
+            },
+        })
+    }
+}
+
+impl<'a> Iterator for InitArgs<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.words.next().map(unquote)
+    }
+}
+
+impl<'a, 'k> Iterator for GetAll<'a, 'k> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let key = self.key;
+        self.params
+            .by_ref()
+            .filter(|param| param.key == key)
+            .filter_map(|param| param.value)
+            .next()
+    }
+}
+
+// ===== impl Known =====
+
+impl Known {
+    /// Returns true if this declaration covers `key`.
+    pub fn matches(&self, key: &str) -> bool {
+        if self.name.ends_with('.') {
+            key.starts_with(self.name)
+        } else {
+            key == self.name
+        }
+    }
+
+    /// Checks that `param` has the kind of value this parameter takes.
+    pub fn validate(&self, param: &Param) -> Result<(), Error> {
+        let value = match (self.kind, param.value) {
+            (Kind::Flag, None) | (Kind::Bool, None) => return Ok(()),
+            (Kind::Flag, Some(_)) => return Err(Error::UnexpectedValue),
+            (_, None) => return Err(Error::MissingValue),
+            (_, Some(value)) => value,
+        };
+        match self.kind {
+            Kind::Int => parse_u64(value).map(|_| ()),
+            Kind::Size => parse_size(value).map(|_| ()),
+            Kind::Bool => parse_bool(value).map(|_| ()),
+            Kind::Addr => parse_usize(value).map(|_| ()),
+            Kind::Str | Kind::Flag => Ok(()),
+        }
+    }
+}
+
+impl<'a, 'k> Iterator for Check<'a, 'k> {
+    type Item = (Param<'a>, Error);
+
+    fn next(&mut self) -> Option<(Param<'a>, Error)> {
+        let known = self.known;
+        self.params
+            .by_ref()
+            .filter_map(|param| {
+                let result = match known.iter().find(|k| k.matches(param.key)) {
+                    Some(known) => known.validate(&param),
+                    None => Err(Error::Unknown),
+                };
+                result.err().map(|err| (param, err))
+            })
+            .next()
+    }
+}
+
+// ===== parsing =====
+
+fn parse_u64(s: &str) -> Result<u64, Error> {
+    let (digits, radix) = if s.starts_with("0x") || s.starts_with("0X") {
+        (&s[2..], 16)
+    } else if s.starts_with("0o") || s.starts_with("0O") {
+        (&s[2..], 8)
+    } else if s.starts_with("0b") || s.starts_with("0B") {
+        (&s[2..], 2)
+    } else {
+        (s, 10)
+    };
+    if digits.is_empty() {
+        return Err(Error::InvalidNumber);
+    }
+    digits.chars().try_fold(0u64, |n, c| {
+        let digit = c.to_digit(radix).ok_or(Error::InvalidNumber)?;
+        n.checked_mul(u64::from(radix))
+            .and_then(|n| n.checked_add(u64::from(digit)))
+            .ok_or(Error::Overflow)
+    })
+}
+
+fn parse_i64(s: &str) -> Result<i64, Error> {
+    if s.starts_with('-') {
+        let n = parse_u64(&s[1..])?;
+        if n > ::core::i64::MAX as u64 + 1 {
+            return Err(Error::Overflow);
+        }
+        Ok((n as i64).wrapping_neg())
+    } else {
+        let n = parse_u64(s)?;
+        if n > ::core::i64::MAX as u64 {
+            return Err(Error::Overflow);
+        }
+        Ok(n as i64)
+    }
+}
+
+fn parse_usize(s: &str) -> Result<usize, Error> {
+    let n = parse_u64(s)?;
+    if n > ::core::usize::MAX as u64 {
+        return Err(Error::Overflow);
+    }
+    Ok(n as usize)
+}
+
+fn parse_size(s: &str) -> Result<u64, Error> {
+    let shift = match s.chars().last() {
+        Some('k') | Some('K') => 10,
+        Some('m') | Some('M') => 20,
+        Some('g') | Some('G') => 30,
+        Some('t') | Some('T') => 40,
+        _ => 0,
+    };
+    let digits = if shift == 0 { s } else { &s[..s.len() - 1] };
+    let n = parse_u64(digits)?;
+    if n.leading_zeros() < shift {
+        return Err(Error::Overflow);
+    }
+    Ok(n << shift)
+}
+
+fn parse_bool(s: &str) -> Result<bool, Error> {
+    const TRUE: [&str; 5] = ["1", "y", "yes", "on", "true"];
+    const FALSE: [&str; 5] = ["0", "n", "no", "off", "false"];
+    if TRUE.iter().any(|t| t.eq_ignore_ascii_case(s)) {
+        Ok(true)
+    } else if FALSE.iter().any(|f| f.eq_ignore_ascii_case(s)) {
+        Ok(false)
+    } else {
+        Err(Error::InvalidBool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::VAddr;
+
+    const CMDLINE: &str = "root=/dev/sda1 ro quiet console=tty0 \
+                           console=ttyS0,115200 init=\"/bin/sh -l\" \
+                           \"acpi=off now\" mem=512M debug=off -- single x=1";
+
+    #[test]
+    fn splits_params() {
+        let cmdline = CommandLine::new(CMDLINE);
+        let mut params = cmdline.params();
+        let kv = |key, value| Param {
+            key,
+            value: Some(value),
+        };
+        assert_eq!(params.next(), Some(kv("root", "/dev/sda1")));
+        assert_eq!(
+            params.next(),
+            Some(Param {
+                key: "ro",
+                value: None
+            })
+        );
+        assert_eq!(params.nth(3), Some(kv("init", "/bin/sh -l")));
+        assert_eq!(params.next(), Some(kv("acpi", "off now")));
+        assert_eq!(params.nth(1), Some(kv("debug", "off")));
+        assert_eq!(params.next(), None);
+
+        let mut init = cmdline.init_args();
+        assert_eq!(init.next(), Some("single"));
+        assert_eq!(init.next(), Some("x=1"));
+        assert_eq!(init.next(), None);
+        assert!(!cmdline.has("single"));
+    }
+
+    #[test]
+    fn repeated_keys() {
+        let cmdline = CommandLine::new(CMDLINE);
+        assert_eq!(cmdline.get_str("console"), Ok(Some("ttyS0,115200")));
+        let mut all = cmdline.get_all("console");
+        assert_eq!(all.next(), Some("tty0"));
+        assert_eq!(all.next(), Some("ttyS0,115200"));
+        assert_eq!(all.next(), None);
+    }
+
+    #[test]
+    fn typed_getters() {
+        let cmdline = CommandLine::new(
+            "a=42 b=0x2a c=-0b101010 mem=512M big=16E huge=99999999999999999999 \
+             lowmem=0x1000k base=0xffff8000 quiet debug=Off",
+        );
+        assert_eq!(cmdline.get_u64("a"), Ok(Some(42)));
+        assert_eq!(cmdline.get_u64("b"), Ok(Some(42)));
+        assert_eq!(cmdline.get_i64("c"), Ok(Some(-42)));
+        assert_eq!(cmdline.get_u64("c"), Err(Error::InvalidNumber));
+        assert_eq!(cmdline.get_u64("huge"), Err(Error::Overflow));
+        assert_eq!(cmdline.get_u64("missing"), Ok(None));
+        assert_eq!(cmdline.get_u64("quiet"), Err(Error::MissingValue));
+
+        assert_eq!(cmdline.get_size("mem"), Ok(Some(512 << 20)));
+        assert_eq!(cmdline.get_size("lowmem"), Ok(Some(0x1000 << 10)));
+        assert_eq!(cmdline.get_size("big"), Err(Error::InvalidNumber));
+
+        assert_eq!(cmdline.get_bool("quiet"), Ok(Some(true)));
+        assert_eq!(cmdline.get_bool("debug"), Ok(Some(false)));
+        assert_eq!(cmdline.get_bool("a"), Err(Error::InvalidBool));
+
+        assert_eq!(
+            cmdline.get_addr::<VAddr>("base"),
+            Ok(Some(VAddr(0xffff_8000)))
+        );
+    }
+
+    #[test]
+    fn reports_unknown_params() {
+        const KNOWN: &[Known] = &[
+            Known {
+                name: "root",
+                kind: Kind::Str,
+            },
+            Known {
+                name: "quiet",
+                kind: Kind::Flag,
+            },
+            Known {
+                name: "mem",
+                kind: Kind::Size,
+            },
+            Known {
+                name: "pci.",
+                kind: Kind::Bool,
+            },
+        ];
+        let cmdline = CommandLine::new(
+            "root=/dev/sda1 quiet=1 mem=lots pci.noacpi rooot=/dev/sda2",
+        );
+        let mut problems = cmdline.check(KNOWN);
+        let param = |key, value| Param { key, value };
+        assert_eq!(
+            problems.next(),
+            Some((param("quiet", Some("1")), Error::UnexpectedValue))
+        );
+        assert_eq!(
+            problems.next(),
+            Some((param("mem", Some("lots")), Error::InvalidNumber))
+        );
+        assert_eq!(
+            problems.next(),
+            Some((param("rooot", Some("/dev/sda2")), Error::Unknown))
+        );
+        assert_eq!(problems.next(), None);
+    }
+}
@@ -0,0 +1,867 @@
+//
+// SOS: the Stupid Operating System
+//  by Eliza Weisman (eliza@elizas.website), and the SOS contributors.
+//
+//  Copyright (c) 2018 Eliza Weisman
+//  Released under the terms of the MIT license. See `LICENSE` in the root
+//  directory of this repository for more information.
+//
+//! Flattened device tree (FDT) parsing.
+//!
+//! Platforms without a BIOS or UEFI memory map, such as QEMU's `virt`
+//! machines and most ARM and RISC-V boards, describe their memory in a
+//! device tree blob (DTB) handed to the kernel by the bootloader. This module
+//! walks a DTB in place, without allocating.
+//!
+//! A DTB consists of a header, a memory reservation block, a structure block
+//! of tokens describing the tree's nodes and their properties, and a strings
+//! block holding property names. All integers are big-endian.
+use crate::mem::{
+    map::{imp::GenericRegion, RegionKind},
+    Address,
+};
+use core::{convert::TryFrom, marker::PhantomData, str};
+
+const MAGIC: u32 = 0xd00d_feed;
+const HEADER_SIZE: usize = 40;
+/// The version of the format this parser reads.
+///
+/// Version 17 added the size of the structure block to the header, which we
+/// rely on.
+const VERSION: u32 = 17;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// The deepest level of the tree whose cell sizes `Nodes` keeps track of.
+///
+/// Nodes nested deeper than this are still returned, but assume the default
+/// cell sizes.
+const MAX_DEPTH: usize = 16;
+
+/// A flattened device tree.
+#[derive(Copy, Clone, Debug)]
+pub struct Fdt<'a> {
+    bytes: &'a [u8],
+    structs: &'a [u8],
+    strings: &'a [u8],
+    rsvmap: usize,
+}
+
+/// Errors returned when parsing a device tree.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The blob is shorter than the header, or than the size in the header.
+    TooShort,
+    /// The blob does not start with the FDT magic number.
+    BadMagic,
+    /// The blob's format version is not compatible with this parser.
+    BadVersion,
+    /// One of the blocks described by the header lies outside the blob.
+    BadOffset,
+    /// The structure block does not start with the root node.
+    BadStructure,
+}
+
+/// A node in the device tree.
+#[derive(Copy, Clone, Debug)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// Offset into the structure block of the node's first property.
+    offset: usize,
+    address_cells: u32,
+    size_cells: u32,
+}
+
+/// A property of a device tree node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Property<'a> {
+    name: &'a str,
+    value: &'a [u8],
+}
+
+/// An iterator over the properties of a node.
+#[derive(Clone, Debug)]
+pub struct Properties<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+}
+
+/// An iterator over the children of a node.
+#[derive(Clone, Debug)]
+pub struct Children<'a> {
+    parent: Node<'a>,
+    offset: Option<usize>,
+}
+
+/// An iterator over every node in the tree, in depth-first order.
+#[derive(Clone, Debug)]
+pub struct Nodes<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    /// The cell sizes in effect at each level of the tree being walked, up
+    /// to a fixed depth.
+    cells: [(u32, u32); MAX_DEPTH],
+    depth: usize,
+}
+
+/// An entry in the memory reservation block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Reservation {
+    /// The physical address of the reserved memory.
+    pub address: u64,
+    /// The size of the reserved memory in bytes.
+    pub size: u64,
+}
+
+/// An iterator over the memory reservation block.
+#[derive(Clone, Debug)]
+pub struct Reservations<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+/// An iterator over the `(address, size)` pairs in a `reg` property.
+#[derive(Clone, Debug)]
+pub struct Reg<'a> {
+    value: &'a [u8],
+    address_cells: usize,
+    size_cells: usize,
+}
+
+/// An iterator over the memory regions described by a device tree.
+///
+/// Regions whose address or size doesn't fit in a `usize`, such as those
+/// above 4 GiB on a 32-bit target, are skipped.
+pub struct Regions<'a, A> {
+    reservations: Option<Reservations<'a>>,
+    nodes: Option<Children<'a>>,
+    reg: Option<Reg<'a>>,
+    kind: RegionKind,
+    _addr: PhantomData<A>,
+}
+
+/// The contents of the `/chosen` node, which holds parameters chosen by the
+/// bootloader rather than describing hardware.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Chosen<'a> {
+    /// The kernel command line.
+    pub bootargs: Option<&'a str>,
+    /// The physical address where the initrd starts.
+    pub initrd_start: Option<u64>,
+    /// The physical address where the initrd ends.
+    pub initrd_end: Option<u64>,
+}
+
+enum Token<'a> {
+    BeginNode(&'a str),
+    EndNode,
+    Prop(Property<'a>),
+    End,
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(
+        u32::from(b[0]) << 24
+            | u32::from(b[1]) << 16
+            | u32::from(b[2]) << 8
+            | u32::from(b[3]),
+    )
+}
+
+fn be_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let hi = be_u32(bytes, offset)?;
+    let lo = be_u32(bytes, offset.checked_add(4)?)?;
+    Some(u64::from(hi) << 32 | u64::from(lo))
+}
+
+/// Reads a big-endian number of `cells` 32-bit cells, if it fits in a `u64`.
+fn be_cells(bytes: &[u8], cells: usize) -> Option<u64> {
+    match cells {
+        1 => be_u32(bytes, 0).map(u64::from),
+        2 => be_u64(bytes, 0),
+        _ => None,
+    }
+}
+
+/// Returns the NUL-terminated string at `offset` in `bytes`, if it is valid
+/// UTF-8.
+fn c_str(bytes: &[u8], offset: usize) -> Option<&str> {
+    let bytes = bytes.get(offset..)?;
+    let len = bytes.iter().position(|&b| b == 0)?;
+    str::from_utf8(&bytes[..len]).ok()
+}
+
+/// Rounds `offset` up to the next 4-byte boundary.
+fn align4(offset: usize) -> Option<usize> {
+    Some(offset.checked_add(3)? & !3)
+}
+
+/// Returns true if `name` is `want`, or is `want` followed by a unit
+/// address (such as `memory@40000000` for `memory`).
+fn name_matches(name: &str, want: &str) -> bool {
+    name == want
+        || (name.starts_with(want) && name[want.len()..].starts_with('@'))
+}
+
+// ===== impl Fdt =====
+
+impl<'a> Fdt<'a> {
+    /// Parses the device tree blob at the start of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        let header =
+            |field: usize| be_u32(bytes, field * 4).map(|n| n as usize);
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::TooShort);
+        }
+        if be_u32(bytes, 0) != Some(MAGIC) {
+            return Err(Error::BadMagic);
+        }
+        let total_size = header(1).ok_or(Error::TooShort)?;
+        if total_size > bytes.len() {
+            return Err(Error::TooShort);
+        }
+        let bytes = &bytes[..total_size];
+        let version = be_u32(bytes, 20).ok_or(Error::TooShort)?;
+        let last_compatible = be_u32(bytes, 24).ok_or(Error::TooShort)?;
+        if version < VERSION || last_compatible > VERSION {
+            return Err(Error::BadVersion);
+        }
+
+        let block = |offset: usize, size: usize| {
+            bytes.get(offset..offset.checked_add(size)?)
+        };
+        let (off_struct, off_strings) =
+            (header(2).unwrap(), header(3).unwrap());
+        let rsvmap = header(4).unwrap();
+        let (size_strings, size_struct) =
+            (header(8).unwrap(), header(9).unwrap());
+        let structs = block(off_struct, size_struct).ok_or(Error::BadOffset)?;
+        let strings =
+            block(off_strings, size_strings).ok_or(Error::BadOffset)?;
+        if rsvmap >= total_size {
+            return Err(Error::BadOffset);
+        }
+
+        let fdt = Fdt {
+            bytes,
+            structs,
+            strings,
+            rsvmap,
+        };
+        match fdt.token(0) {
+            Some((Token::BeginNode(_), _)) => Ok(fdt),
+            _ => Err(Error::BadStructure),
+        }
+    }
+
+    /// Parses the device tree blob at `addr`.
+    ///
+    /// # Unsafety
+    ///
+    /// `addr` must point to a valid device tree blob, such as the one whose
+    /// address the bootloader passes to the kernel, and the blob must not be
+    /// overwritten while the returned `Fdt` exists.
+    pub unsafe fn from_addr(addr: usize) -> Result<Self, Error> {
+        let header = ::core::slice::from_raw_parts(addr as *const u8, 8);
+        let size = be_u32(header, 4).unwrap() as usize;
+        Self::new(::core::slice::from_raw_parts(addr as *const u8, size))
+    }
+
+    /// Returns the size of the device tree blob in bytes.
+    #[inline]
+    pub fn total_size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns the ID of the CPU the system was booted on.
+    pub fn boot_cpuid(&self) -> u32 {
+        be_u32(self.bytes, 28).unwrap_or(0)
+    }
+
+    /// Returns the root node of the tree.
+    pub fn root(&self) -> Node<'a> {
+        let name = match self.token(0) {
+            Some((Token::BeginNode(name), _)) => name,
+            _ => unreachable!("checked in Fdt::new"),
+        };
+        // The root's cell sizes are its own `#address-cells` and
+        // `#size-cells`, which default to 2 and 1.
+        let mut root = Node {
+            fdt: *self,
+            name,
+            offset: align4(4 + name.len() + 1).unwrap(),
+            address_cells: 2,
+            size_cells: 1,
+        };
+        let (address_cells, size_cells) = root.child_cells();
+        root.address_cells = address_cells;
+        root.size_cells = size_cells;
+        root
+    }
+
+    /// Returns every node in the tree, in depth-first order.
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes {
+            fdt: *self,
+            offset: 0,
+            cells: [(0, 0); MAX_DEPTH],
+            depth: 0,
+        }
+    }
+
+    /// Returns the node at `path`, such as `/chosen` or `/reserved-memory`.
+    ///
+    /// Each component of the path may omit the node's unit address, in which
+    /// case the first node with a matching name is returned.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(self.root(), |node, component| node.child(component))
+    }
+
+    /// Returns the entries in the memory reservation block, which lists
+    /// memory that the kernel must not use.
+    pub fn reservations(&self) -> Reservations<'a> {
+        Reservations {
+            bytes: self.bytes,
+            offset: self.rsvmap,
+        }
+    }
+
+    /// Returns the contents of the `/chosen` node.
+    pub fn chosen(&self) -> Chosen<'a> {
+        let chosen = match self.find_node("/chosen") {
+            Some(chosen) => chosen,
+            None => {
+                return Chosen {
+                    bootargs: None,
+                    initrd_start: None,
+                    initrd_end: None,
+                }
+            },
+        };
+        // The initrd properties are a single number of either one or two
+        // cells, regardless of `#address-cells`.
+        let number = |name| {
+            let value = chosen.property(name)?.value();
+            be_cells(value, value.len() / 4)
+        };
+        Chosen {
+            bootargs: chosen.property("bootargs").and_then(|p| p.as_str()),
+            initrd_start: number("linux,initrd-start"),
+            initrd_end: number("linux,initrd-end"),
+        }
+    }
+
+    /// Returns the memory regions described by the `reg` properties of the
+    /// tree's memory nodes, as `Usable` regions.
+    ///
+    /// Memory nodes are the root's children with a `device_type` of
+    /// `"memory"`.
+    pub fn memory<A>(&self) -> Regions<'a, A>
+    where
+        A: Address + From<usize>,
+    {
+        Regions {
+            reservations: None,
+            nodes: Some(self.root().children()),
+            reg: None,
+            kind: RegionKind::Usable,
+            _addr: PhantomData,
+        }
+    }
+
+    /// Returns the memory which must not be used as general-purpose RAM, as
+    /// `Unusable` regions.
+    ///
+    /// This is the memory reservation block, followed by the `reg`
+    /// properties of the children of `/reserved-memory`.
+    pub fn reserved_memory<A>(&self) -> Regions<'a, A>
+    where
+        A: Address + From<usize>,
+    {
+        Regions {
+            reservations: Some(self.reservations()),
+            nodes: self
+                .find_node("/reserved-memory")
+                .map(|node| node.children()),
+            reg: None,
+            kind: RegionKind::Unusable,
+            _addr: PhantomData,
+        }
+    }
+
+    /// Reads the token at `offset` in the structure block, skipping `NOP`s.
+    ///
+    /// Returns the token and the offset of the next token.
+    fn token(&self, mut offset: usize) -> Option<(Token<'a>, usize)> {
+        loop {
+            let next = offset.checked_add(4)?;
+            match be_u32(self.structs, offset)? {
+                FDT_BEGIN_NODE => {
+                    let name = c_str(self.structs, next)?;
+                    let end = align4(next + name.len() + 1)?;
+                    return Some((Token::BeginNode(name), end));
+                },
+                FDT_END_NODE => return Some((Token::EndNode, next)),
+                FDT_PROP => {
+                    let len = be_u32(self.structs, next)? as usize;
+                    let name_offset = be_u32(self.structs, next + 4)? as usize;
+                    let start = next + 8;
+                    let value =
+                        self.structs.get(start..start.checked_add(len)?)?;
+                    let name = c_str(self.strings, name_offset)?;
+                    let end = align4(start + len)?;
+                    return Some((Token::Prop(Property { name, value }), end));
+                },
+                FDT_NOP => offset = next,
+                FDT_END => return Some((Token::End, next)),
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns the offset just past the end of the node whose properties
+    /// start at `offset`.
+    fn skip_node(&self, mut offset: usize) -> Option<usize> {
+        let mut depth = 1usize;
+        loop {
+            let (token, next) = self.token(offset)?;
+            offset = next;
+            match token {
+                Token::BeginNode(_) => depth += 1,
+                Token::EndNode => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(offset);
+                    }
+                },
+                Token::Prop(_) => {},
+                Token::End => return None,
+            }
+        }
+    }
+}
+
+// ===== impl Node =====
+
+impl<'a> Node<'a> {
+    /// Returns the node's name, including its unit address.
+    ///
+    /// The root node's name is empty.
+    #[inline]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the node's properties.
+    pub fn properties(&self) -> Properties<'a> {
+        Properties {
+            fdt: self.fdt,
+            offset: self.offset,
+        }
+    }
+
+    /// Returns the property called `name`, if the node has one.
+    pub fn property(&self, name: &str) -> Option<Property<'a>> {
+        self.properties().find(|prop| prop.name == name)
+    }
+
+    /// Returns the node's children.
+    pub fn children(&self) -> Children<'a> {
+        Children {
+            parent: *self,
+            offset: Some(self.offset),
+        }
+    }
+
+    /// Returns the first child called `name`, which may omit the child's
+    /// unit address.
+    pub fn child(&self, name: &str) -> Option<Node<'a>> {
+        self.children().find(|child| name_matches(child.name, name))
+    }
+
+    /// Returns the pairs of addresses and sizes in the node's `reg`
+    /// property.
+    ///
+    /// These are interpreted according to the parent node's
+    /// `#address-cells` and `#size-cells`.
+    pub fn reg(&self) -> Option<Reg<'a>> {
+        let value = self.property("reg")?.value();
+        Some(Reg {
+            value,
+            address_cells: self.address_cells as usize,
+            size_cells: self.size_cells as usize,
+        })
+    }
+
+    /// Returns the `#address-cells` and `#size-cells` that this node's
+    /// children's `reg` properties use.
+    fn child_cells(&self) -> (u32, u32) {
+        let cells = |name| self.property(name).and_then(|prop| prop.as_u32());
+        (
+            cells("#address-cells").unwrap_or(2),
+            cells("#size-cells").unwrap_or(1),
+        )
+    }
+}
+
+impl<'a> Iterator for Properties<'a> {
+    type Item = Property<'a>;
+
+    fn next(&mut self) -> Option<Property<'a>> {
+        match self.fdt.token(self.offset)? {
+            (Token::Prop(prop), next) => {
+                self.offset = next;
+                Some(prop)
+            },
+            // Properties always come before a node's children.
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        let fdt = self.parent.fdt;
+        let mut offset = self.offset?;
+        loop {
+            match fdt.token(offset) {
+                Some((Token::Prop(_), next)) => offset = next,
+                Some((Token::BeginNode(name), next)) => {
+                    self.offset = fdt.skip_node(next);
+                    let (address_cells, size_cells) = self.parent.child_cells();
+                    return Some(Node {
+                        fdt,
+                        name,
+                        offset: next,
+                        address_cells,
+                        size_cells,
+                    });
+                },
+                _ => {
+                    self.offset = None;
+                    return None;
+                },
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        loop {
+            let (token, next) = self.fdt.token(self.offset)?;
+            match token {
+                Token::BeginNode(name) => {
+                    self.offset = next;
+                    let (address_cells, size_cells) =
+                        match self.depth.checked_sub(1) {
+                            Some(parent) if parent < MAX_DEPTH => {
+                                self.cells[parent]
+                            },
+                            _ => (2, 1),
+                        };
+                    let node = Node {
+                        fdt: self.fdt,
+                        name,
+                        offset: next,
+                        address_cells,
+                        size_cells,
+                    };
+                    if self.depth < MAX_DEPTH {
+                        self.cells[self.depth] = node.child_cells();
+                    }
+                    self.depth += 1;
+                    return Some(node);
+                },
+                Token::EndNode => {
+                    self.offset = next;
+                    self.depth = self.depth.saturating_sub(1);
+                },
+                Token::Prop(_) => self.offset = next,
+                Token::End => return None,
+            }
+        }
+    }
+}
+
+// ===== impl Property =====
+
+impl<'a> Property<'a> {
+    /// Returns the property's name.
+    #[inline]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the property's raw value.
+    #[inline]
+    pub fn value(&self) -> &'a [u8] {
+        self.value
+    }
+
+    /// Returns the property's value as a string, if it is a NUL-terminated
+    /// UTF-8 string.
+    pub fn as_str(&self) -> Option<&'a str> {
+        c_str(self.value, 0)
+    }
+
+    /// Returns the property's value as a `u32`, if it is one cell long.
+    pub fn as_u32(&self) -> Option<u32> {
+        if self.value.len() == 4 {
+            be_u32(self.value, 0)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the property's value as a `u64`, if it is two cells long.
+    pub fn as_u64(&self) -> Option<u64> {
+        if self.value.len() == 8 {
+            be_u64(self.value, 0)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the strings in a string list property, such as
+    /// `compatible`.
+    pub fn strings(&self) -> impl Iterator<Item = &'a str> {
+        self.value
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| str::from_utf8(s).ok())
+    }
+}
+
+// ===== impl Reservations =====
+
+impl<'a> Iterator for Reservations<'a> {
+    type Item = Reservation;
+
+    fn next(&mut self) -> Option<Reservation> {
+        let address = be_u64(self.bytes, self.offset)?;
+        let size = be_u64(self.bytes, self.offset + 8)?;
+        // The block is terminated by an entry with an address and size of 0.
+        if address == 0 && size == 0 {
+            return None;
+        }
+        self.offset += 16;
+        Some(Reservation { address, size })
+    }
+}
+
+// ===== impl Reg =====
+
+impl<'a> Iterator for Reg<'a> {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<(u64, u64)> {
+        let address_len = self.address_cells * 4;
+        let entry_len = address_len + self.size_cells * 4;
+        if entry_len == 0 || self.value.len() < entry_len {
+            return None;
+        }
+        let address = be_cells(self.value, self.address_cells)?;
+        let size = if self.size_cells == 0 {
+            0
+        } else {
+            be_cells(&self.value[address_len..], self.size_cells)?
+        };
+        self.value = &self.value[entry_len..];
+        Some((address, size))
+    }
+}
+
+// ===== impl Regions =====
+
+impl<'a, A> Iterator for Regions<'a, A>
+where
+    A: Address + From<usize>,
+{
+    type Item = GenericRegion<A>;
+
+    fn next(&mut self) -> Option<GenericRegion<A>> {
+        loop {
+            let (address, size) = if let Some(reservation) =
+                self.reservations.as_mut().and_then(Iterator::next)
+            {
+                (reservation.address, reservation.size)
+            } else if let Some(entry) =
+                self.reg.as_mut().and_then(Iterator::next)
+            {
+                entry
+            } else {
+                let node = self.nodes.as_mut()?.next()?;
+                let is_memory = self.kind != RegionKind::Usable
+                    || node
+                        .property("device_type")
+                        .and_then(|prop| prop.as_str())
+                        == Some("memory");
+                self.reg = if is_memory { node.reg() } else { None };
+                continue;
+            };
+            if let (Ok(address), Ok(size)) =
+                (usize::try_from(address), usize::try_from(size))
+            {
+                return Some(GenericRegion {
+                    base_address: A::from(address),
+                    size,
+                    kind: self.kind,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{map::Region, VAddr};
+
+    static QEMU_VIRT: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/qemu-virt.dtb"
+    ));
+    static TWO_BANKS: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/arm32-two-banks.dtb"
+    ));
+
+    fn regions<I>(iter: I) -> [(usize, usize); 4]
+    where
+        I: Iterator<Item = GenericRegion<VAddr>>,
+    {
+        let mut out = [(0, 0); 4];
+        for (slot, region) in out.iter_mut().zip(iter) {
+            *slot = (region.base_address().0, region.size());
+        }
+        out
+    }
+
+    #[test]
+    fn rejects_bad_blobs() {
+        assert_eq!(Fdt::new(&QEMU_VIRT[..8]).unwrap_err(), Error::TooShort);
+        assert_eq!(
+            Fdt::new(&QEMU_VIRT[..QEMU_VIRT.len() - 1]).unwrap_err(),
+            Error::TooShort
+        );
+        assert_eq!(Fdt::new(&QEMU_VIRT[4..]).unwrap_err(), Error::BadMagic);
+    }
+
+    #[test]
+    fn walks_nodes_and_properties() {
+        let fdt = Fdt::new(QEMU_VIRT).unwrap();
+        let mut names = [""; 8];
+        for (slot, node) in names.iter_mut().zip(fdt.nodes()) {
+            *slot = node.name();
+        }
+        assert_eq!(
+            names,
+            [
+                "",
+                "chosen",
+                "reserved-memory",
+                "secmon@4ff00000",
+                "memory@40000000",
+                "cpus",
+                "cpu@0",
+                "pl011@9000000",
+            ]
+        );
+
+        let uart = fdt.find_node("/pl011").unwrap();
+        let mut compatible = uart.property("compatible").unwrap().strings();
+        assert_eq!(compatible.next(), Some("arm,pl011"));
+        assert_eq!(compatible.next(), Some("arm,primecell"));
+        assert_eq!(compatible.next(), None);
+        assert_eq!(uart.reg().unwrap().next(), Some((0x900_0000, 0x1000)));
+
+        let cpu = fdt.find_node("/cpus/cpu@0").unwrap();
+        assert_eq!(cpu.reg().unwrap().next(), Some((0, 0)));
+        assert!(fdt.find_node("/cpus/cpu@1").is_none());
+    }
+
+    #[test]
+    fn memory_regions() {
+        let fdt = Fdt::new(QEMU_VIRT).unwrap();
+        assert_eq!(
+            regions(fdt.memory::<VAddr>()),
+            [(0x4000_0000, 0x1000_0000), (0, 0), (0, 0), (0, 0)]
+        );
+        assert!(fdt.memory::<VAddr>().all(|region| region.is_usable()));
+
+        let fdt = Fdt::new(TWO_BANKS).unwrap();
+        assert_eq!(
+            regions(fdt.memory::<VAddr>()),
+            [
+                (0x8000_0000, 0x800_0000),
+                (0xa000_0000, 0x400_0000),
+                (0, 0),
+                (0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn reserved_regions() {
+        let fdt = Fdt::new(QEMU_VIRT).unwrap();
+        let mut reservations = fdt.reservations();
+        assert_eq!(
+            reservations.next(),
+            Some(Reservation {
+                address: 0x4800_0000,
+                size: 0x20_0000,
+            })
+        );
+        assert_eq!(reservations.next(), None);
+
+        assert_eq!(
+            regions(fdt.reserved_memory::<VAddr>()),
+            [
+                (0x4800_0000, 0x20_0000),
+                (0x4ff0_0000, 0x10_0000),
+                (0, 0),
+                (0, 0)
+            ]
+        );
+        assert!(fdt.reserved_memory::<VAddr>().all(|r| r.is_reserved()));
+
+        let fdt = Fdt::new(TWO_BANKS).unwrap();
+        assert_eq!(fdt.reserved_memory::<VAddr>().count(), 0);
+    }
+
+    #[test]
+    fn chosen() {
+        let fdt = Fdt::new(QEMU_VIRT).unwrap();
+        assert_eq!(
+            fdt.chosen(),
+            Chosen {
+                bootargs: Some("console=ttyAMA0 root=/dev/vda rw"),
+                initrd_start: Some(0x4800_0000),
+                initrd_end: Some(0x4820_0000),
+            }
+        );
+
+        let fdt = Fdt::new(TWO_BANKS).unwrap();
+        assert_eq!(
+            fdt.chosen(),
+            Chosen {
+                bootargs: Some("earlycon"),
+                initrd_start: Some(0x8200_0000),
+                initrd_end: Some(0x8240_0000),
+            }
+        );
+    }
+}
@@ -14,7 +14,9 @@
 #[macro_use]
 extern crate hal9000_derive;
 
+pub mod cmdline;
 pub mod cpu;
+pub mod fdt;
 pub mod mem;
 pub mod params;
 pub mod util;
@@ -24,7 +26,7 @@ pub trait Architecture {
     type PAddr: mem::Address;
 
     /// This architecture's physical page type.
-    type Frame: mem::Page;
+    type Frame: mem::Page<Address = Self::PAddr>;
 
     /// The name of the architecture (for logging, etc).
     const NAME: &'static str;
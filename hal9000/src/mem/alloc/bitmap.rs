@@ -0,0 +1,476 @@
+//! A bitmap frame allocator.
+//!
+//! The bitmap stores one bit for every frame between the lowest and highest
+//! usable addresses in the memory map. A set bit means the frame is in use (or
+//! was never usable to begin with); a clear bit means it is free.
+use super::{split_around, usable_frames, Bump};
+use crate::{
+    mem::{
+        page::{FrameAllocator, Page, Range, RangeError},
+        Address, PhysicalAddress,
+    },
+    params::BootParams,
+    Architecture,
+};
+use core::{cmp, mem, slice};
+
+const WORD_BITS: usize = mem::size_of::<usize>() * 8;
+
+/// A frame allocator which tracks the state of every frame with a single bit.
+pub struct Bitmap<'a, F: Page> {
+    /// Address of the first frame tracked by the bitmap.
+    base: F::Address,
+    /// One bit for each frame. Set bits are in use.
+    words: &'a mut [usize],
+    /// The number of frames tracked by the bitmap.
+    frames: usize,
+    /// The number of frames which are currently free.
+    free: usize,
+    /// Index of the word to start searching for a free frame in.
+    next: usize,
+}
+
+/// Errors returned by a `Bitmap` allocator.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// There are no free frames left.
+    OutOfMemory,
+    /// The frame is not tracked by this allocator.
+    OutOfRange,
+    /// The frame being deallocated is already free.
+    DoubleFree,
+    /// The memory map contains no usable memory.
+    NoUsableMemory,
+    /// No usable region is large enough to hold the bitmap.
+    NoRoomForBitmap,
+}
+
+// ===== impl Bitmap =====
+
+impl<'a, F: Page> Bitmap<'a, F> {
+    /// Returns the number of `usize` words needed to track `frames` frames.
+    #[inline]
+    pub fn words_for(frames: usize) -> usize {
+        (frames + WORD_BITS - 1) / WORD_BITS
+    }
+
+    /// Returns a new bitmap tracking `frames` frames starting at `base`,
+    /// using `words` to store its state.
+    ///
+    /// Every frame starts out marked as in use; call `mark_free` with the
+    /// ranges that are actually available.
+    ///
+    /// # Panics
+    /// If `words` is shorter than `Bitmap::words_for(frames)`.
+    pub fn new(
+        base: F::Address,
+        frames: usize,
+        words: &'a mut [usize],
+    ) -> Self {
+        let len = Self::words_for(frames);
+        assert!(
+            words.len() >= len,
+            "bitmap storage too small for {} frames",
+            frames
+        );
+        let words = &mut words[..len];
+        for word in words.iter_mut() {
+            *word = !0;
+        }
+        Bitmap {
+            base,
+            words,
+            frames,
+            free: 0,
+            next: 0,
+        }
+    }
+
+    /// Returns the number of frames tracked by this allocator.
+    #[inline]
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Returns the number of frames which are currently free.
+    #[inline]
+    pub fn free_frames(&self) -> usize {
+        self.free
+    }
+
+    /// Returns `true` if `frame` is tracked and currently free.
+    pub fn is_free(&self, frame: &F) -> bool {
+        self.index_of(frame.base_address())
+            .map(|i| !self.get(i))
+            .unwrap_or(false)
+    }
+
+    /// Marks every tracked frame in `range` as free.
+    ///
+    /// Frames outside of the bitmap are ignored.
+    pub fn mark_free(&mut self, range: &Range<F>) {
+        let (start, end) = self.clamp(range);
+        for i in start..end {
+            if self.get(i) {
+                self.clear(i);
+            }
+        }
+        if start < end {
+            self.next = cmp::min(self.next, start / WORD_BITS);
+        }
+    }
+
+    /// Marks every tracked frame in `range` as in use.
+    ///
+    /// Frames outside of the bitmap are ignored.
+    pub fn mark_used(&mut self, range: &Range<F>) {
+        let (start, end) = self.clamp(range);
+        for i in start..end {
+            if !self.get(i) {
+                self.set(i);
+            }
+        }
+    }
+
+    /// Returns the index of the frame starting at `addr`, if it is tracked.
+    fn index_of(&self, addr: F::Address) -> Option<usize> {
+        if addr < self.base {
+            return None;
+        }
+        let i = self.base.distance(addr) / F::SIZE;
+        if i < self.frames {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the frame with index `i`.
+    #[inline]
+    fn frame(&self, i: usize) -> F {
+        F::from_addr_down(self.base.offset(i * F::SIZE))
+    }
+
+    /// Returns the indices of the tracked frames in `range`.
+    fn clamp(&self, range: &Range<F>) -> (usize, usize) {
+        let index = |addr: F::Address| {
+            if addr <= self.base {
+                0
+            } else {
+                cmp::min(self.base.distance(addr) / F::SIZE, self.frames)
+            }
+        };
+        (index(range.start_address()), index(range.end_address()))
+    }
+
+    #[inline]
+    fn get(&self, i: usize) -> bool {
+        self.words[i / WORD_BITS] & (1 << (i % WORD_BITS)) != 0
+    }
+
+    #[inline]
+    fn set(&mut self, i: usize) {
+        self.words[i / WORD_BITS] |= 1 << (i % WORD_BITS);
+        self.free -= 1;
+    }
+
+    #[inline]
+    fn clear(&mut self, i: usize) {
+        self.words[i / WORD_BITS] &= !(1 << (i % WORD_BITS));
+        self.free += 1;
+    }
+}
+
+impl<F> Bitmap<'static, F>
+where
+    F: Page,
+    F::Address: PhysicalAddress,
+{
+    /// Builds a bitmap allocator covering the usable memory described by
+    /// `params`.
+    ///
+    /// The bitmap is placed at the start of the first usable region that is
+    /// large enough to hold it and doesn't overlap the kernel. Every frame
+    /// outside of a usable region, every frame in `kernel_frames()` and the
+    /// frames holding the bitmap itself are marked as in use.
+    ///
+    /// # Unsafety
+    /// This function is unsafe because it writes to physical memory through
+    /// `PhysicalAddress::as_mut_ptr`. The caller must ensure that every
+    /// usable region in the memory map is actually unused, and is accessible
+    /// at the pointer returned by `as_mut_ptr`.
+    pub unsafe fn from_params<P>(params: &P) -> Result<Self, Error>
+    where
+        P: BootParams,
+        P::Arch: Architecture<Frame = F, PAddr = F::Address>,
+    {
+        let (base, frames) = usable_span(params)?;
+        let needed = storage_frames::<F>(frames);
+
+        // Find somewhere to put the bitmap.
+        let kernel = params.kernel_frames();
+        let storage = usable_frames(params)
+            .filter_map(|range| {
+                let (below, above) = split_around(&range, &kernel);
+                if below.len() >= needed {
+                    Some(below)
+                } else if above.len() >= needed {
+                    Some(above)
+                } else {
+                    None
+                }
+            })
+            .next()
+            .ok_or(Error::NoRoomForBitmap)?;
+        let (storage, _) = storage
+            .split_at(needed)
+            .expect("storage range has room for the bitmap");
+
+        let mut bitmap = Self::with_storage(base, frames, &storage);
+        for range in usable_frames(params) {
+            bitmap.mark_free(&range);
+        }
+        bitmap.mark_used(&kernel);
+        bitmap.mark_used(&storage);
+        Ok(bitmap)
+    }
+
+    /// Builds a bitmap allocator covering the usable memory described by
+    /// the boot parameters `bump` was created with, taking over from `bump`.
+    ///
+    /// The frames holding the bitmap are allocated from `bump`. Every frame
+    /// that `bump` has already handed out stays in use, and every frame it
+    /// has not becomes free.
+    ///
+    /// # Unsafety
+    /// This function is unsafe because it writes to physical memory through
+    /// `PhysicalAddress::as_mut_ptr`. The caller must ensure that the frames
+    /// returned by `bump` are accessible at the pointer returned by
+    /// `as_mut_ptr`.
+    pub unsafe fn from_bump<P>(mut bump: Bump<P>) -> Result<Self, Error>
+    where
+        P: BootParams,
+        P::Arch: Architecture<Frame = F, PAddr = F::Address>,
+    {
+        let (base, frames) = usable_span(bump.params())?;
+        let storage = bump
+            .alloc_range(storage_frames::<F>(frames))
+            .map_err(|_| Error::NoRoomForBitmap)?;
+
+        let mut bitmap = Self::with_storage(base, frames, &storage);
+        for range in bump.into_free_ranges() {
+            bitmap.mark_free(&range);
+        }
+        Ok(bitmap)
+    }
+
+    /// Returns a bitmap tracking `frames` frames starting at `base`, with its
+    /// state stored at the start of `storage`.
+    unsafe fn with_storage(
+        base: F::Address,
+        frames: usize,
+        storage: &Range<F>,
+    ) -> Self {
+        let words = slice::from_raw_parts_mut(
+            storage.start_address().as_mut_ptr::<usize>(),
+            Self::words_for(frames),
+        );
+        Bitmap::new(base, frames, words)
+    }
+}
+
+unsafe impl<'a, F: Page> FrameAllocator for Bitmap<'a, F> {
+    type Frame = F;
+    type Error = Error;
+
+    unsafe fn alloc(&mut self) -> Result<F, Error> {
+        if self.free == 0 {
+            return Err(Error::OutOfMemory);
+        }
+        let words = self.words.len();
+        for w in (self.next..words).chain(0..self.next) {
+            let word = self.words[w];
+            if word == !0 {
+                continue;
+            }
+            let i = w * WORD_BITS + (!word).trailing_zeros() as usize;
+            if i >= self.frames {
+                continue;
+            }
+            self.set(i);
+            self.next = w;
+            return Ok(self.frame(i));
+        }
+        Err(Error::OutOfMemory)
+    }
+
+    unsafe fn dealloc(&mut self, frame: F) -> Result<(), Error> {
+        let i = self
+            .index_of(frame.base_address())
+            .ok_or(Error::OutOfRange)?;
+        if !self.get(i) {
+            return Err(Error::DoubleFree);
+        }
+        self.clear(i);
+        self.next = cmp::min(self.next, i / WORD_BITS);
+        Ok(())
+    }
+
+    /// Returns a range of `n` free frames, starting at an address that is a
+    /// multiple of `align` bytes.
+    ///
+    /// Unlike the default implementation, this searches the bitmap for a
+    /// suitable run of free frames, so it will succeed whenever one exists.
+    unsafe fn alloc_aligned(
+        &mut self,
+        n: usize,
+        align: usize,
+    ) -> Result<Range<F>, RangeError<Error>> {
+        if !align.is_power_of_two() || align < F::SIZE {
+            return Err(RangeError::BadAlignment);
+        }
+        if n == 0 || n > self.frames {
+            return Err(RangeError::BadSize);
+        }
+        if n > self.free {
+            return Err(RangeError::Alloc(Error::OutOfMemory));
+        }
+
+        let step = align / F::SIZE;
+        let mut start = match self
+            .base
+            .checked_add(align - 1)
+            .and_then(|addr| self.index_of(addr.align_down(align)))
+        {
+            Some(start) => start,
+            None => return Err(RangeError::Alloc(Error::OutOfMemory)),
+        };
+        'search: while start + n <= self.frames {
+            for i in start..start + n {
+                if self.get(i) {
+                    // Skip to the next aligned frame after the used one.
+                    start += ((i - start) / step + 1) * step;
+                    continue 'search;
+                }
+            }
+            for i in start..start + n {
+                self.set(i);
+            }
+            let start = self.base.offset(start * F::SIZE);
+            return Ok(Range::within(start..start.offset(n * F::SIZE)));
+        }
+        Err(RangeError::Alloc(Error::OutOfMemory))
+    }
+
+    unsafe fn dealloc_range(&mut self, range: Range<F>) -> Result<(), Error> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let start = self
+            .index_of(range.start_address())
+            .ok_or(Error::OutOfRange)?;
+        let end = start + range.len();
+        if end > self.frames {
+            return Err(Error::OutOfRange);
+        }
+        if (start..end).any(|i| !self.get(i)) {
+            return Err(Error::DoubleFree);
+        }
+        for i in start..end {
+            self.clear(i);
+        }
+        self.next = cmp::min(self.next, start / WORD_BITS);
+        Ok(())
+    }
+}
+
+/// Returns the address of the lowest usable frame in `params`' memory map,
+/// and the number of frames from there to the end of the highest usable
+/// frame.
+fn usable_span<P>(
+    params: &P,
+) -> Result<(<P::Arch as Architecture>::PAddr, usize), Error>
+where
+    P: BootParams,
+{
+    let mut span = None;
+    for range in usable_frames(params) {
+        if range.is_empty() {
+            continue;
+        }
+        let (start, end) = (range.start_address(), range.end_address());
+        span = Some(match span {
+            Some((lo, hi)) => (cmp::min(lo, start), cmp::max(hi, end)),
+            None => (start, end),
+        });
+    }
+    let (base, end) = span.ok_or(Error::NoUsableMemory)?;
+    let frame_size = <<P::Arch as Architecture>::Frame as Page>::SIZE;
+    Ok((base, base.distance(end) / frame_size))
+}
+
+/// Returns the number of frames needed to hold a bitmap of `frames` bits.
+fn storage_frames<F: Page>(frames: usize) -> usize {
+    let bytes = Bitmap::<F>::words_for(frames) * mem::size_of::<usize>();
+    (bytes + F::SIZE - 1) / F::SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{page::tests::TestPage, VAddr};
+
+    fn range(start: usize, end: usize) -> Range<TestPage> {
+        Range::new(TestPage(VAddr(start)), TestPage(VAddr(end)))
+    }
+
+    #[test]
+    fn starts_out_used() {
+        let mut words = [0; 2];
+        let mut bitmap =
+            Bitmap::<TestPage>::new(VAddr(0x1000), 100, &mut words);
+        assert_eq!(bitmap.free_frames(), 0);
+        assert_eq!(unsafe { bitmap.alloc() }, Err(Error::OutOfMemory));
+    }
+
+    #[test]
+    fn alloc_and_dealloc() {
+        let mut words = [0; 2];
+        let mut bitmap = Bitmap::<TestPage>::new(VAddr(0), 100, &mut words);
+        bitmap.mark_free(&range(0x10000, 0x13000));
+        assert_eq!(bitmap.free_frames(), 3);
+
+        let a = unsafe { bitmap.alloc() }.unwrap();
+        let b = unsafe { bitmap.alloc() }.unwrap();
+        assert_eq!(a, TestPage(VAddr(0x10000)));
+        assert_eq!(b, TestPage(VAddr(0x11000)));
+        assert_eq!(bitmap.free_frames(), 1);
+
+        unsafe { bitmap.dealloc(a) }.unwrap();
+        assert!(bitmap.is_free(&TestPage(VAddr(0x10000))));
+        assert_eq!(
+            unsafe { bitmap.dealloc(TestPage(VAddr(0x10000))) },
+            Err(Error::DoubleFree)
+        );
+        assert_eq!(
+            unsafe { bitmap.dealloc(TestPage(VAddr(0x100000))) },
+            Err(Error::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn alloc_aligned_finds_free_run() {
+        let mut words = [0; 2];
+        let mut bitmap = Bitmap::<TestPage>::new(VAddr(0), 128, &mut words);
+        bitmap.mark_free(&range(0x1000, 0x80000));
+        bitmap.mark_used(&range(0x9000, 0xa000));
+
+        let r = unsafe { bitmap.alloc_aligned(4, 0x8000) }.unwrap();
+        assert_eq!(r, range(0x10000, 0x14000));
+        assert!(!bitmap.is_free(&TestPage(VAddr(0x13000))));
+        assert!(bitmap.is_free(&TestPage(VAddr(0x14000))));
+
+        unsafe { bitmap.dealloc_range(r) }.unwrap();
+        assert!(bitmap.is_free(&TestPage(VAddr(0x10000))));
+    }
+}
@@ -0,0 +1,470 @@
+//! A buddy-system frame allocator.
+//!
+//! Free memory is kept as blocks of `2^order` frames, for every order up to
+//! `MAX_ORDER`. Allocating a block of a given order splits the smallest free
+//! block that is large enough in half until it is the right size, and freeing
+//! a block merges it with its "buddy" (the other half of the block it was
+//! split from) whenever the buddy is also free.
+//!
+//! The allocator doesn't store anything inside the memory it manages. Instead,
+//! it keeps one bitmap per order in a caller-provided slice, with a set bit
+//! for every free block of that order.
+use crate::mem::{
+    map::Region,
+    page::{FrameAllocator, Page, Range, RangeError},
+    Address,
+};
+use core::{cmp, mem};
+
+/// The largest order of block tracked by the allocator.
+///
+/// Blocks of this order contain `2^MAX_ORDER` frames.
+pub const MAX_ORDER: usize = 10;
+
+const ORDERS: usize = MAX_ORDER + 1;
+
+const WORD_BITS: usize = mem::size_of::<usize>() * 8;
+
+/// A buddy-system frame allocator.
+pub struct Buddy<'a, F: Page> {
+    /// Address of the first frame tracked by the allocator.
+    ///
+    /// This is aligned to the size of a `MAX_ORDER` block, so blocks of every
+    /// order are naturally aligned.
+    base: F::Address,
+    /// The number of frames tracked by the allocator.
+    frames: usize,
+    /// The free-block bitmaps for every order, one after another.
+    words: &'a mut [usize],
+    /// The index in `words` where each order's bitmap starts.
+    offsets: [usize; ORDERS],
+    /// The number of free blocks of each order.
+    free: [usize; ORDERS],
+}
+
+/// Errors returned by a `Buddy` allocator.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// There is no free block large enough to satisfy the allocation.
+    OutOfMemory,
+    /// The frame is not tracked by this allocator.
+    OutOfRange,
+    /// The frame being deallocated is already free.
+    DoubleFree,
+}
+
+// ===== impl Buddy =====
+
+impl<'a, F: Page> Buddy<'a, F> {
+    /// Returns the number of `usize` words needed to track the frames in
+    /// `span`.
+    pub fn words_for(span: &Range<F>) -> usize {
+        let frames = Self::span_frames(span).1;
+        (0..ORDERS)
+            .map(|order| words_for_order(frames, order))
+            .sum()
+    }
+
+    /// Returns a new buddy allocator tracking the frames in `span`, using
+    /// `words` to store its free-block bitmaps.
+    ///
+    /// The allocator starts out with no free memory; use `add_regions` or
+    /// `add_range` to give it frames to allocate.
+    ///
+    /// # Panics
+    /// If `words` is shorter than `Buddy::words_for(span)`.
+    pub fn new(span: &Range<F>, words: &'a mut [usize]) -> Self {
+        let (base, frames) = Self::span_frames(span);
+        let mut offsets = [0; ORDERS];
+        let mut len = 0;
+        for (order, offset) in offsets.iter_mut().enumerate() {
+            *offset = len;
+            len += words_for_order(frames, order);
+        }
+        assert!(
+            words.len() >= len,
+            "buddy allocator storage too small for {} frames",
+            frames
+        );
+        let words = &mut words[..len];
+        for word in words.iter_mut() {
+            *word = 0;
+        }
+        Buddy {
+            base,
+            frames,
+            words,
+            offsets,
+            free: [0; ORDERS],
+        }
+    }
+
+    /// Adds the usable frames in each of `regions` to the allocator.
+    ///
+    /// Only frames which lie entirely inside a usable region are added.
+    /// Memory outside of the allocator's span is ignored.
+    pub fn add_regions<I>(&mut self, regions: I)
+    where
+        I: IntoIterator,
+        I::Item: Region<Addr = F::Address>,
+    {
+        for region in regions {
+            if region.is_usable() {
+                let base = region.base_address();
+                self.add_range(&Range::within(base..region.end_address()));
+            }
+        }
+    }
+
+    /// Marks every frame in `range` as free.
+    ///
+    /// Memory outside of the allocator's span is ignored. Frames in the range
+    /// must not already be free.
+    pub fn add_range(&mut self, range: &Range<F>) {
+        let (mut start, end) = self.clamp(range);
+        while start < end {
+            let order = block_order(start, end);
+            self.free_block(start, order);
+            start += 1 << order;
+        }
+    }
+
+    /// Removes every frame in `range` from the free lists, so that it will
+    /// never be allocated.
+    ///
+    /// This is used to carve out memory, such as the kernel image, which
+    /// lies inside a region that was added with `add_regions`.
+    pub fn reserve(&mut self, range: &Range<F>) {
+        let (start, end) = self.clamp(range);
+        for i in start..end {
+            self.take(i);
+        }
+    }
+
+    /// Returns the number of frames which are currently free.
+    pub fn free_frames(&self) -> usize {
+        self.free
+            .iter()
+            .enumerate()
+            .map(|(order, blocks)| blocks << order)
+            .sum()
+    }
+
+    /// Returns `true` if `frame` is tracked and currently free.
+    pub fn is_free(&self, frame: &F) -> bool {
+        self.index_of(frame.base_address())
+            .map(|i| self.free_order_of(i).is_some())
+            .unwrap_or(false)
+    }
+
+    /// Allocates a naturally-aligned block of `2^order` frames.
+    pub fn alloc_order(&mut self, order: usize) -> Result<Range<F>, Error> {
+        if order > MAX_ORDER {
+            return Err(Error::OutOfMemory);
+        }
+        let mut from = order;
+        while self.free[from] == 0 {
+            from += 1;
+            if from > MAX_ORDER {
+                return Err(Error::OutOfMemory);
+            }
+        }
+        let block = self
+            .first_free(from)
+            .expect("free count was non-zero, so there is a free block");
+        self.clear(from, block);
+        let start = block << from;
+
+        // Split the block in half until it is the requested size, freeing
+        // the upper half each time.
+        while from > order {
+            from -= 1;
+            self.set(from, (start >> from) + 1);
+        }
+        Ok(self.range(start, 1 << order))
+    }
+
+    /// Returns the aligned base of `span` and the number of frames from
+    /// there to the end of `span`.
+    fn span_frames(span: &Range<F>) -> (F::Address, usize) {
+        let base = span.start_address().align_down(F::SIZE << MAX_ORDER);
+        (base, base.distance(span.end_address()) / F::SIZE)
+    }
+
+    /// Frees the block of `2^order` frames starting at frame `start`, merging
+    /// it with its buddies.
+    fn free_block(&mut self, mut start: usize, mut order: usize) {
+        while order < MAX_ORDER {
+            let buddy = start ^ (1 << order);
+            if buddy + (1 << order) > self.frames
+                || !self.get(order, buddy >> order)
+            {
+                break;
+            }
+            self.clear(order, buddy >> order);
+            start = cmp::min(start, buddy);
+            order += 1;
+        }
+        self.set(order, start >> order);
+    }
+
+    /// Removes frame `i` from whichever free block contains it, returning
+    /// the rest of that block to the free lists.
+    fn take(&mut self, i: usize) {
+        let mut order = match self.free_order_of(i) {
+            Some(order) => order,
+            None => return,
+        };
+        self.clear(order, i >> order);
+        while order > 0 {
+            order -= 1;
+            // Free the half of the block which doesn't contain `i`. Its buddy
+            // is the half we're about to split, so there's nothing to merge.
+            self.set(order, (i >> order) ^ 1);
+        }
+    }
+
+    /// Returns the order of the free block containing frame `i`, if there is
+    /// one.
+    fn free_order_of(&self, i: usize) -> Option<usize> {
+        (0..ORDERS).find(|&order| {
+            let block = i >> order;
+            (block + 1) << order <= self.frames && self.get(order, block)
+        })
+    }
+
+    /// Returns the index of the first free block of the given order.
+    fn first_free(&self, order: usize) -> Option<usize> {
+        let start = self.offsets[order];
+        let len = words_for_order(self.frames, order);
+        self.words[start..start + len]
+            .iter()
+            .position(|&word| word != 0)
+            .map(|w| {
+                let word = self.words[start + w];
+                w * WORD_BITS + word.trailing_zeros() as usize
+            })
+    }
+
+    /// Returns the index of the frame starting at `addr`, if it is tracked.
+    fn index_of(&self, addr: F::Address) -> Option<usize> {
+        if addr < self.base {
+            return None;
+        }
+        let i = self.base.distance(addr) / F::SIZE;
+        if i < self.frames {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the indices of the tracked frames in `range`.
+    fn clamp(&self, range: &Range<F>) -> (usize, usize) {
+        let index = |addr: F::Address| {
+            if addr <= self.base {
+                0
+            } else {
+                cmp::min(self.base.distance(addr) / F::SIZE, self.frames)
+            }
+        };
+        (index(range.start_address()), index(range.end_address()))
+    }
+
+    /// Returns the range of `len` frames starting at frame `start`.
+    fn range(&self, start: usize, len: usize) -> Range<F> {
+        let start = self.base.offset(start * F::SIZE);
+        Range::within(start..start.offset(len * F::SIZE))
+    }
+
+    #[inline]
+    fn get(&self, order: usize, block: usize) -> bool {
+        let word = self.offsets[order] + block / WORD_BITS;
+        self.words[word] & (1 << (block % WORD_BITS)) != 0
+    }
+
+    #[inline]
+    fn set(&mut self, order: usize, block: usize) {
+        let word = self.offsets[order] + block / WORD_BITS;
+        self.words[word] |= 1 << (block % WORD_BITS);
+        self.free[order] += 1;
+    }
+
+    #[inline]
+    fn clear(&mut self, order: usize, block: usize) {
+        let word = self.offsets[order] + block / WORD_BITS;
+        self.words[word] &= !(1 << (block % WORD_BITS));
+        self.free[order] -= 1;
+    }
+}
+
+unsafe impl<'a, F: Page> FrameAllocator for Buddy<'a, F> {
+    type Frame = F;
+    type Error = Error;
+
+    unsafe fn alloc(&mut self) -> Result<F, Error> {
+        self.alloc_order(0)
+            .map(|range| F::from_addr_down(range.start_address()))
+    }
+
+    unsafe fn dealloc(&mut self, frame: F) -> Result<(), Error> {
+        let i = self
+            .index_of(frame.base_address())
+            .ok_or(Error::OutOfRange)?;
+        if self.free_order_of(i).is_some() {
+            return Err(Error::DoubleFree);
+        }
+        self.free_block(i, 0);
+        Ok(())
+    }
+
+    /// Returns a range of `n` contiguous frames, starting at an address that
+    /// is a multiple of `align` bytes.
+    ///
+    /// This allocates the smallest block that holds `n` frames and satisfies
+    /// the alignment, and returns any frames past the first `n` to the free
+    /// lists.
+    unsafe fn alloc_aligned(
+        &mut self,
+        n: usize,
+        align: usize,
+    ) -> Result<Range<F>, RangeError<Error>> {
+        if !align.is_power_of_two() || align < F::SIZE {
+            return Err(RangeError::BadAlignment);
+        }
+        let align_order = (align / F::SIZE).trailing_zeros() as usize;
+        if align_order > MAX_ORDER {
+            return Err(RangeError::BadAlignment);
+        }
+        let size_order = match n.checked_next_power_of_two() {
+            Some(0) | None => return Err(RangeError::BadSize),
+            Some(size) => size.trailing_zeros() as usize,
+        };
+        if size_order > MAX_ORDER {
+            return Err(RangeError::BadSize);
+        }
+
+        let order = cmp::max(size_order, align_order);
+        let block = self.alloc_order(order).map_err(RangeError::Alloc)?;
+        let (range, rest) =
+            block.split_at(n).expect("block holds at least `n` frames");
+        self.add_range(&rest);
+        Ok(range)
+    }
+
+    unsafe fn dealloc_range(&mut self, range: Range<F>) -> Result<(), Error> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let start = self
+            .index_of(range.start_address())
+            .ok_or(Error::OutOfRange)?;
+        if start + range.len() > self.frames {
+            return Err(Error::OutOfRange);
+        }
+        if (start..start + range.len()).any(|i| self.free_order_of(i).is_some())
+        {
+            return Err(Error::DoubleFree);
+        }
+        self.add_range(&range);
+        Ok(())
+    }
+}
+
+/// Returns the number of words in the bitmap for blocks of the given order.
+#[inline]
+fn words_for_order(frames: usize, order: usize) -> usize {
+    ((frames >> order) + WORD_BITS - 1) / WORD_BITS
+}
+
+/// Returns the order of the largest naturally-aligned block that starts at
+/// frame `start` and doesn't extend past frame `end`.
+fn block_order(start: usize, end: usize) -> usize {
+    let mut order = 0;
+    while order < MAX_ORDER
+        && start & ((1 << (order + 1)) - 1) == 0
+        && start + (1 << (order + 1)) <= end
+    {
+        order += 1;
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{page::tests::TestPage, VAddr};
+
+    fn range(start: usize, end: usize) -> Range<TestPage> {
+        Range::new(TestPage(VAddr(start)), TestPage(VAddr(end)))
+    }
+
+    #[test]
+    fn add_range_merges_blocks() {
+        let span = range(0, 0x100000);
+        let mut words = [0; 16];
+        let mut buddy = Buddy::new(&span, &mut words);
+        buddy.add_range(&range(0x1000, 0x20000));
+        assert_eq!(buddy.free_frames(), 31);
+        // 31 frames starting at an odd frame: 1 + 2 + 4 + 8 + 16.
+        assert_eq!(&buddy.free[..6], &[1, 1, 1, 1, 1, 0]);
+
+        let mut frame = unsafe { buddy.alloc() }.unwrap();
+        assert_eq!(frame, TestPage(VAddr(0x1000)));
+        unsafe { buddy.dealloc(frame) }.unwrap();
+        frame = TestPage(VAddr(0x1000));
+        assert_eq!(unsafe { buddy.dealloc(frame) }, Err(Error::DoubleFree));
+    }
+
+    #[test]
+    fn alloc_order_splits_and_free_merges() {
+        let span = range(0, 0x40000);
+        let mut words = [0; 16];
+        let mut buddy = Buddy::new(&span, &mut words);
+        buddy.add_range(&span);
+        assert_eq!(&buddy.free[..7], &[0, 0, 0, 0, 0, 0, 1]);
+
+        let block = buddy.alloc_order(2).unwrap();
+        assert_eq!(block, range(0, 0x4000));
+        assert_eq!(&buddy.free[..7], &[0, 0, 1, 1, 1, 1, 0]);
+
+        unsafe { buddy.dealloc_range(block) }.unwrap();
+        assert_eq!(&buddy.free[..7], &[0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(buddy.free_frames(), 64);
+    }
+
+    #[test]
+    fn alloc_aligned_returns_excess() {
+        let span = range(0, 0x40000);
+        let mut words = [0; 16];
+        let mut buddy = Buddy::new(&span, &mut words);
+        buddy.add_range(&range(0x1000, 0x40000));
+
+        let r = unsafe { buddy.alloc_aligned(3, 0x8000) }.unwrap();
+        assert_eq!(r, range(0x8000, 0xb000));
+        assert_eq!(buddy.free_frames(), 63 - 3);
+        assert!(buddy.is_free(&TestPage(VAddr(0xb000))));
+        assert!(!buddy.is_free(&TestPage(VAddr(0xa000))));
+    }
+
+    #[test]
+    fn reserve_carves_out_frames() {
+        let span = range(0, 0x40000);
+        let mut words = [0; 16];
+        let mut buddy = Buddy::new(&span, &mut words);
+        buddy.add_range(&span);
+        buddy.reserve(&range(0x3000, 0x5000));
+        assert_eq!(buddy.free_frames(), 62);
+        assert!(!buddy.is_free(&TestPage(VAddr(0x3000))));
+        assert!(!buddy.is_free(&TestPage(VAddr(0x4000))));
+        assert!(buddy.is_free(&TestPage(VAddr(0x2000))));
+        assert!(buddy.is_free(&TestPage(VAddr(0x5000))));
+
+        for _ in 0..62 {
+            let frame = unsafe { buddy.alloc() }.unwrap();
+            assert_ne!(frame, TestPage(VAddr(0x3000)));
+            assert_ne!(frame, TestPage(VAddr(0x4000)));
+        }
+        assert_eq!(unsafe { buddy.alloc() }, Err(Error::OutOfMemory));
+    }
+}
@@ -0,0 +1,409 @@
+//! An early-boot bump allocator.
+//!
+//! The bump allocator walks the memory map in order, handing out the frames
+//! in each usable region one after another and skipping over the kernel
+//! image. It stores nothing but its position in the memory map, so it can be
+//! used before there is anywhere to put allocator metadata, such as when
+//! `MemCtrl::init_paging` needs frames for the initial page tables.
+//!
+//! Frames can't generally be returned to a bump allocator. Instead, once the
+//! kernel is ready to set up a real allocator, `Bump::into_free_ranges`
+//! returns every frame the bump allocator has *not* handed out, so that they
+//! can be given to the new allocator.
+use super::split_around;
+use crate::{
+    mem::{
+        map::Region,
+        page::{FrameAllocator, Page, Range, RangeError},
+        Address,
+    },
+    params::BootParams,
+    Architecture,
+};
+use core::mem;
+
+type Frame<P> = <<P as BootParams>::Arch as Architecture>::Frame;
+type PAddr<P> = <<P as BootParams>::Arch as Architecture>::PAddr;
+
+/// A frame allocator which hands out the usable frames in a memory map in
+/// order.
+pub struct Bump<'p, P: BootParams> {
+    params: &'p P,
+    /// The regions in the memory map which haven't been reached yet.
+    regions: P::MemMap,
+    /// The frames occupied by the kernel, which are never handed out.
+    kernel: Range<Frame<P>>,
+    /// The frames currently being handed out.
+    current: Range<Frame<P>>,
+    /// Where `current` started, so that frames handed out from it can be
+    /// given back.
+    current_base: PAddr<P>,
+    /// The part of the current region above the kernel, if the kernel split
+    /// it in two.
+    pending: Range<Frame<P>>,
+    /// Frames which were skipped over to satisfy a contiguous allocation,
+    /// and have not been handed out.
+    skipped: Range<Frame<P>>,
+}
+
+/// Errors returned by a `Bump` allocator.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// There are no usable frames left in the memory map.
+    OutOfMemory,
+    /// Only the most recently allocated frames can be returned to a bump
+    /// allocator.
+    NotMostRecent,
+}
+
+// ===== impl Bump =====
+
+impl<'p, P: BootParams> Bump<'p, P> {
+    /// Returns a new bump allocator which hands out the usable frames in
+    /// `params`' memory map, except for those in `params.kernel_frames()`.
+    pub fn new(params: &'p P) -> Self {
+        let kernel = params.kernel_frames();
+        let start = kernel.start_address();
+        Bump {
+            params,
+            regions: params.mem_map(),
+            kernel,
+            current: Range::within(start..start),
+            current_base: start,
+            pending: Range::within(start..start),
+            skipped: Range::within(start..start),
+        }
+    }
+
+    /// Returns the boot parameters this allocator was created from.
+    #[inline]
+    pub fn params(&self) -> &'p P {
+        self.params
+    }
+
+    /// Consumes the allocator, returning an iterator over every usable frame
+    /// that it has not handed out.
+    ///
+    /// The returned ranges don't overlap, and don't include the kernel.
+    pub fn into_free_ranges(self) -> IntoFreeRanges<'p, P> {
+        IntoFreeRanges(self)
+    }
+
+    /// Moves on to the next run of usable frames in the memory map, returning
+    /// `false` if there are none left.
+    fn next_run(&mut self) -> bool {
+        if !self.pending.is_empty() {
+            self.current = self.empty();
+            mem::swap(&mut self.current, &mut self.pending);
+            self.current_base = self.current.start_address();
+            return true;
+        }
+
+        while let Some(region) = self.regions.next() {
+            if !region.is_usable() {
+                continue;
+            }
+            let range =
+                Range::within(region.base_address()..region.end_address());
+            let (below, above) = split_around(&range, &self.kernel);
+            if below.is_empty() {
+                self.current = above;
+                self.pending = self.empty();
+            } else {
+                self.current = below;
+                self.pending = above;
+            }
+            if !self.current.is_empty() {
+                self.current_base = self.current.start_address();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Remembers the frames from `start` to `end` as skipped, so that they
+    /// can be handed out later.
+    ///
+    /// Returns `false` if a different range has already been skipped and
+    /// the two can't be combined.
+    fn skip(&mut self, start: PAddr<P>, end: PAddr<P>) -> bool {
+        if start == end {
+            return true;
+        }
+        if self.skipped.is_empty() {
+            self.skipped = Range::within(start..end);
+        } else if self.skipped.end_address() == start {
+            self.skipped = Range::within(self.skipped.start_address()..end);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn empty(&self) -> Range<Frame<P>> {
+        let start = self.kernel.start_address();
+        Range::within(start..start)
+    }
+}
+
+unsafe impl<'p, P: BootParams> FrameAllocator for Bump<'p, P> {
+    type Frame = Frame<P>;
+    type Error = Error;
+
+    unsafe fn alloc(&mut self) -> Result<Self::Frame, Error> {
+        if let Some(frame) = pop_front(&mut self.skipped) {
+            return Ok(frame);
+        }
+        loop {
+            if let Some(frame) = pop_front(&mut self.current) {
+                return Ok(frame);
+            }
+            if !self.next_run() {
+                return Err(Error::OutOfMemory);
+            }
+        }
+    }
+
+    /// Returns `frame` to the allocator.
+    ///
+    /// This only succeeds if `frame` is the most recently allocated frame
+    /// which hasn't yet been returned.
+    unsafe fn dealloc(&mut self, frame: Self::Frame) -> Result<(), Error> {
+        let range = Range::within(frame.base_address()..frame.end_address());
+        self.dealloc_range(range)
+    }
+
+    /// Returns `n` contiguous frames, starting at an address that is a
+    /// multiple of `align` bytes.
+    ///
+    /// Frames that are passed over to find a suitable run are remembered and
+    /// handed out by later calls to `alloc`, but only one run of skipped
+    /// frames can be remembered at a time. If satisfying the request would
+    /// require skipping a second, non-adjacent run, this returns
+    /// `RangeError::NotContiguous` rather than leaking frames.
+    unsafe fn alloc_aligned(
+        &mut self,
+        n: usize,
+        align: usize,
+    ) -> Result<Range<Self::Frame>, RangeError<Error>> {
+        let frame_size = <Self::Frame as Page>::SIZE;
+        if !align.is_power_of_two() || align < frame_size {
+            return Err(RangeError::BadAlignment);
+        }
+        let bytes = match n.checked_mul(frame_size) {
+            Some(0) | None => return Err(RangeError::BadSize),
+            Some(bytes) => bytes,
+        };
+
+        loop {
+            let (current, end) =
+                (self.current.start_address(), self.current.end_address());
+            let start = current.align_up(align);
+            let fits = start
+                .checked_add(bytes)
+                .map(|range_end| current < end && range_end <= end)
+                .unwrap_or(false);
+            if fits {
+                if !self.skip(current, start) {
+                    return Err(RangeError::NotContiguous);
+                }
+                let range_end = start.offset(bytes);
+                self.current = Range::within(range_end..end);
+                return Ok(Range::within(start..range_end));
+            }
+
+            if !self.skip(current, end) {
+                return Err(RangeError::NotContiguous);
+            }
+            self.current = self.empty();
+            if !self.next_run() {
+                return Err(RangeError::Alloc(Error::OutOfMemory));
+            }
+        }
+    }
+
+    /// Returns the frames in `range` to the allocator.
+    ///
+    /// This only succeeds if `range` ends at the most recently allocated
+    /// frame which hasn't yet been returned, and lies within the run of
+    /// frames that is currently being handed out.
+    unsafe fn dealloc_range(
+        &mut self,
+        range: Range<Self::Frame>,
+    ) -> Result<(), Error> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        if range.end_address() != self.current.start_address()
+            || range.start_address() < self.current_base
+        {
+            return Err(Error::NotMostRecent);
+        }
+        self.current =
+            Range::within(range.start_address()..self.current.end_address());
+        Ok(())
+    }
+}
+
+/// An iterator over the frames which a `Bump` allocator has not handed out.
+///
+/// This is returned by `Bump::into_free_ranges`.
+pub struct IntoFreeRanges<'p, P: BootParams>(Bump<'p, P>);
+
+impl<'p, P: BootParams> Iterator for IntoFreeRanges<'p, P> {
+    type Item = Range<Frame<P>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bump = &mut self.0;
+        loop {
+            if !bump.skipped.is_empty() {
+                let empty = bump.empty();
+                return Some(mem::replace(&mut bump.skipped, empty));
+            }
+            if !bump.current.is_empty() {
+                let empty = bump.empty();
+                return Some(mem::replace(&mut bump.current, empty));
+            }
+            if !bump.next_run() {
+                return None;
+            }
+        }
+    }
+}
+
+/// Removes the first frame from `range`, returning it.
+fn pop_front<F: Page>(range: &mut Range<F>) -> Option<F> {
+    if range.is_empty() {
+        return None;
+    }
+    let start = range.start_address();
+    *range = Range::within(start.offset(F::SIZE)..range.end_address());
+    Some(F::from_addr_down(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{
+        map::imp::{GenericRegion, RegionKind},
+        page::tests::TestPage,
+        VAddr,
+    };
+
+    struct TestArch;
+
+    impl Architecture for TestArch {
+        type PAddr = VAddr;
+        type Frame = TestPage;
+        const NAME: &'static str = "test";
+        const BITS: &'static str = "64";
+    }
+
+    struct TestParams {
+        map: &'static [GenericRegion<VAddr>],
+        kernel: (usize, usize),
+    }
+
+    impl BootParams for TestParams {
+        type Arch = TestArch;
+        type MemRegion = GenericRegion<VAddr>;
+        type MemMap = ::core::iter::Cloned<
+            ::core::slice::Iter<'static, GenericRegion<VAddr>>,
+        >;
+
+        fn kernel_base(&self) -> VAddr {
+            VAddr(self.kernel.0)
+        }
+
+        fn kernel_end(&self) -> VAddr {
+            VAddr(self.kernel.1)
+        }
+
+        fn mem_map(&self) -> Self::MemMap {
+            self.map.iter().cloned()
+        }
+
+        fn kernel_frames(&self) -> Range<TestPage> {
+            Range::containing(self.kernel_base()..self.kernel_end())
+        }
+    }
+
+    static MAP: [GenericRegion<VAddr>; 3] = [
+        GenericRegion {
+            base_address: VAddr(0x1000),
+            size: 0x5000,
+            kind: RegionKind::Usable,
+        },
+        GenericRegion {
+            base_address: VAddr(0x6000),
+            size: 0x2000,
+            kind: RegionKind::Unusable,
+        },
+        GenericRegion {
+            base_address: VAddr(0x8800),
+            size: 0x2800,
+            kind: RegionKind::Usable,
+        },
+    ];
+
+    fn params() -> TestParams {
+        TestParams {
+            map: &MAP,
+            kernel: (0x2000, 0x3800),
+        }
+    }
+
+    fn page(addr: usize) -> TestPage {
+        TestPage(VAddr(addr))
+    }
+
+    #[test]
+    fn skips_kernel_and_unusable_memory() {
+        let params = params();
+        let mut bump = Bump::new(&params);
+        let mut frames = [0; 4];
+        for frame in frames.iter_mut() {
+            *frame = unsafe { bump.alloc() }.unwrap().0.as_usize();
+        }
+        assert_eq!(frames, [0x1000, 0x4000, 0x5000, 0x9000]);
+        assert_eq!(unsafe { bump.alloc() }, Ok(page(0xa000)));
+        assert_eq!(unsafe { bump.alloc() }, Err(Error::OutOfMemory));
+    }
+
+    #[test]
+    fn only_most_recent_frame_can_be_returned() {
+        let params = params();
+        let mut bump = Bump::new(&params);
+        let first = unsafe { bump.alloc() }.unwrap();
+        let second = unsafe { bump.alloc() }.unwrap();
+        // The first frame is in a different run, below the kernel.
+        assert_eq!(unsafe { bump.dealloc(first) }, Err(Error::NotMostRecent));
+        assert_eq!(unsafe { bump.dealloc(second) }, Ok(()));
+        assert_eq!(unsafe { bump.alloc() }, Ok(page(0x4000)));
+    }
+
+    #[test]
+    fn alloc_range_remembers_skipped_frames() {
+        let params = params();
+        let mut bump = Bump::new(&params);
+        let range = unsafe { bump.alloc_range(2) }.unwrap();
+        assert_eq!(range, Range::new(page(0x4000), page(0x6000)));
+        // The frame below the kernel was skipped, not leaked.
+        assert_eq!(unsafe { bump.alloc() }, Ok(page(0x1000)));
+    }
+
+    #[test]
+    fn free_ranges_exclude_allocated_frames() {
+        let params = params();
+        let mut bump = Bump::new(&params);
+        unsafe {
+            bump.alloc().unwrap();
+            bump.alloc().unwrap();
+        }
+        let mut free = bump.into_free_ranges();
+        assert_eq!(free.next(), Some(Range::new(page(0x5000), page(0x6000))));
+        assert_eq!(free.next(), Some(Range::new(page(0x9000), page(0xb000))));
+        assert_eq!(free.next(), None);
+    }
+}
@@ -0,0 +1,50 @@
+//
+// SOS: the Stupid Operating System
+//  by Eliza Weisman (eliza@elizas.website), and the SOS contributors.
+//
+//  Copyright (c) 2018 Eliza Weisman
+//  Released under the terms of the MIT license. See `LICENSE` in the root
+//  directory of this repository for more information.
+//
+//! Architecture-independent implementations of `FrameAllocator`.
+use super::{
+    map::Region,
+    page::{Page, Range},
+};
+use crate::{params::BootParams, Architecture};
+use core::cmp;
+
+pub mod bitmap;
+pub mod buddy;
+pub mod bump;
+
+pub use self::{bitmap::Bitmap, buddy::Buddy, bump::Bump};
+
+/// Returns the frames which lie entirely inside each usable region in
+/// `params`' memory map.
+pub(crate) fn usable_frames<P>(
+    params: &P,
+) -> impl Iterator<Item = Range<<P::Arch as Architecture>::Frame>>
+where
+    P: BootParams,
+{
+    params
+        .mem_map()
+        .filter(|region| region.is_usable())
+        .map(|region| {
+            Range::within(region.base_address()..region.end_address())
+        })
+}
+
+/// Returns the parts of `range` that lie below and above `hole`.
+///
+/// Either part may be empty.
+pub(crate) fn split_around<P: Page>(
+    range: &Range<P>,
+    hole: &Range<P>,
+) -> (Range<P>, Range<P>) {
+    let (start, end) = (range.start_address(), range.end_address());
+    let below = cmp::max(start, cmp::min(hole.start_address(), end));
+    let above = cmp::min(end, cmp::max(hole.end_address(), start));
+    (Range::within(start..below), Range::within(above..end))
+}
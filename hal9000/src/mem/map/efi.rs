@@ -0,0 +1,293 @@
+//! UEFI memory maps.
+//!
+//! `GetMemoryMap()` returns an array of `EFI_MEMORY_DESCRIPTOR`s. The
+//! firmware reports the size of each descriptor separately, and it may be
+//! larger than the structure defined by the specification, so the array must
+//! always be walked using that size rather than `size_of::<Descriptor>()`.
+use super::{Region, RegionKind};
+use crate::mem::Address;
+use core::{marker::PhantomData, mem, ptr};
+
+/// The size of a UEFI page, which is always 4 KiB regardless of the
+/// architecture's page size.
+pub const PAGE_SIZE: usize = 4096;
+
+// EFI_MEMORY_TYPE values.
+/// Memory which is not usable.
+pub const RESERVED: u32 = 0;
+/// Code of the loaded UEFI application.
+pub const LOADER_CODE: u32 = 1;
+/// Data allocated by the loaded UEFI application.
+pub const LOADER_DATA: u32 = 2;
+/// Code of boot services drivers.
+pub const BOOT_SERVICES_CODE: u32 = 3;
+/// Data allocated by boot services drivers.
+pub const BOOT_SERVICES_DATA: u32 = 4;
+/// Code of runtime services drivers.
+pub const RUNTIME_SERVICES_CODE: u32 = 5;
+/// Data allocated by runtime services drivers.
+pub const RUNTIME_SERVICES_DATA: u32 = 6;
+/// Free memory.
+pub const CONVENTIONAL: u32 = 7;
+/// Memory in which errors have been detected.
+pub const UNUSABLE: u32 = 8;
+/// ACPI tables.
+pub const ACPI_RECLAIM: u32 = 9;
+/// ACPI non-volatile storage.
+pub const ACPI_NVS: u32 = 10;
+/// Memory-mapped I/O.
+pub const MMIO: u32 = 11;
+/// Memory-mapped I/O port space.
+pub const MMIO_PORT_SPACE: u32 = 12;
+/// Firmware code on Itanium.
+pub const PAL_CODE: u32 = 13;
+/// Byte-addressable non-volatile memory.
+pub const PERSISTENT: u32 = 14;
+
+/// Attribute bit indicating that a region must be mapped by the OS for use by
+/// runtime services.
+pub const MEMORY_RUNTIME: u64 = 1 << 63;
+
+/// An `EFI_MEMORY_DESCRIPTOR`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct Descriptor<A> {
+    /// The `EFI_MEMORY_TYPE` of the region.
+    pub kind: u32,
+    _pad: u32,
+    /// The physical address of the start of the region.
+    pub phys_start: u64,
+    /// The virtual address of the start of the region, if runtime services
+    /// have been remapped by `SetVirtualAddressMap()`.
+    pub virt_start: u64,
+    /// The number of 4 KiB pages in the region.
+    pub num_pages: u64,
+    /// The region's attribute bits.
+    pub attribute: u64,
+    _addr: PhantomData<A>,
+}
+
+/// An iterator over an array of `EFI_MEMORY_DESCRIPTOR`s.
+#[derive(Clone, Debug)]
+pub struct Descriptors<'a, A> {
+    bytes: &'a [u8],
+    stride: usize,
+    _addr: PhantomData<A>,
+}
+
+/// Errors returned when reading a UEFI memory map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The descriptor size was smaller than an `EFI_MEMORY_DESCRIPTOR`.
+    BadDescriptorSize,
+    /// The memory map's length was not a multiple of the descriptor size.
+    BadLength,
+}
+
+// ===== impl Descriptor =====
+
+impl<A> Descriptor<A> {
+    /// Returns a descriptor for `num_pages` pages of memory of type `kind`,
+    /// starting at `phys_start`.
+    pub fn new(kind: u32, phys_start: u64, num_pages: u64) -> Self {
+        Descriptor {
+            kind,
+            _pad: 0,
+            phys_start,
+            virt_start: 0,
+            num_pages,
+            attribute: 0,
+            _addr: PhantomData,
+        }
+    }
+
+    /// Returns true if this region is used by runtime services, either
+    /// because of its type or because it has the `EFI_MEMORY_RUNTIME`
+    /// attribute.
+    ///
+    /// Runtime services may be called after the kernel has taken over, so
+    /// these regions must never be handed out as free memory.
+    pub fn is_runtime(&self) -> bool {
+        self.attribute & MEMORY_RUNTIME != 0
+            || self.kind == RUNTIME_SERVICES_CODE
+            || self.kind == RUNTIME_SERVICES_DATA
+    }
+}
+
+impl<A> Region for Descriptor<A>
+where
+    A: Address + From<usize>,
+{
+    type Addr = A;
+
+    /// Returns the base address of the memory region.
+    fn base_address(&self) -> A {
+        A::from(self.phys_start as usize)
+    }
+
+    /// Returns the size in bytes of the memory region.
+    fn size(&self) -> usize {
+        (self.num_pages as usize).saturating_mul(PAGE_SIZE)
+    }
+
+    /// Returns the kind of memory in this region.
+    ///
+    /// Memory used by the loader and by boot services is reclaimable once
+    /// the kernel has called `ExitBootServices()` and is done with whatever
+    /// the loader left there. Runtime services memory is always reserved.
+    fn kind(&self) -> RegionKind {
+        if self.is_runtime() {
+            return RegionKind::Unusable;
+        }
+        match self.kind {
+            CONVENTIONAL => RegionKind::Usable,
+            LOADER_CODE | LOADER_DATA | BOOT_SERVICES_CODE
+            | BOOT_SERVICES_DATA => RegionKind::BootloaderReclaimable,
+            ACPI_RECLAIM => RegionKind::AcpiReclaimable,
+            ACPI_NVS => RegionKind::AcpiNvs,
+            UNUSABLE => RegionKind::BadMemory,
+            MMIO | MMIO_PORT_SPACE => RegionKind::Mmio,
+            _ => RegionKind::Unusable,
+        }
+    }
+}
+
+// ===== impl Descriptors =====
+
+impl<'a, A> Descriptors<'a, A> {
+    /// Reads descriptors from the memory map in `bytes`, which are
+    /// `descriptor_size` bytes apart.
+    ///
+    /// `descriptor_size` is the value returned by `GetMemoryMap()` in
+    /// `DescriptorSize`.
+    pub fn new(bytes: &'a [u8], descriptor_size: usize) -> Result<Self, Error> {
+        if descriptor_size < mem::size_of::<Descriptor<A>>() {
+            return Err(Error::BadDescriptorSize);
+        }
+        if bytes.len() % descriptor_size != 0 {
+            return Err(Error::BadLength);
+        }
+        Ok(Descriptors {
+            bytes,
+            stride: descriptor_size,
+            _addr: PhantomData,
+        })
+    }
+}
+
+impl<'a, A> Iterator for Descriptors<'a, A> {
+    type Item = Descriptor<A>;
+
+    fn next(&mut self) -> Option<Descriptor<A>> {
+        if self.bytes.len() < self.stride {
+            return None;
+        }
+        // Safe because `new` checked that the stride is at least the size of
+        // a descriptor, and we just checked that there is a whole stride
+        // left. The firmware only guarantees 8-byte alignment, which
+        // `read_unaligned` doesn't rely on.
+        let descriptor = unsafe {
+            ptr::read_unaligned(self.bytes.as_ptr() as *const Descriptor<A>)
+        };
+        self.bytes = &self.bytes[self.stride..];
+        Some(descriptor)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.bytes.len() / self.stride;
+        (len, Some(len))
+    }
+}
+
+impl<'a, A> ExactSizeIterator for Descriptors<'a, A> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::VAddr;
+
+    type Desc = Descriptor<VAddr>;
+
+    /// Most firmware uses 48-byte descriptors, 8 bytes larger than the
+    /// structure in the specification.
+    const STRIDE: usize = 48;
+
+    fn write(buf: &mut [u8], index: usize, desc: Desc) {
+        let fields = [
+            (0, u64::from(desc.kind), 4),
+            (8, desc.phys_start, 8),
+            (16, desc.virt_start, 8),
+            (24, desc.num_pages, 8),
+            (32, desc.attribute, 8),
+        ];
+        for &(offset, value, width) in &fields {
+            for byte in 0..width {
+                buf[index * STRIDE + offset + byte] =
+                    (value >> (byte * 8)) as u8;
+            }
+        }
+    }
+
+    #[test]
+    fn walks_descriptor_stride() {
+        let mut runtime = Desc::new(CONVENTIONAL, 0x30_0000, 2);
+        runtime.attribute = MEMORY_RUNTIME;
+        let descs = [
+            Desc::new(CONVENTIONAL, 0x1000, 0x9e),
+            Desc::new(LOADER_DATA, 0x10_0000, 0x100),
+            Desc::new(BOOT_SERVICES_CODE, 0x20_0000, 0x10),
+            Desc::new(RUNTIME_SERVICES_DATA, 0x21_0000, 0x4),
+            runtime,
+            Desc::new(ACPI_RECLAIM, 0x40_0000, 1),
+            Desc::new(ACPI_NVS, 0x40_1000, 1),
+            Desc::new(UNUSABLE, 0x50_0000, 1),
+            Desc::new(MMIO, 0xfee0_0000, 1),
+        ];
+        let mut buf = [0xa5u8; STRIDE * 9];
+        for (i, &desc) in descs.iter().enumerate() {
+            write(&mut buf, i, desc);
+        }
+
+        let regions = Descriptors::<VAddr>::new(&buf, STRIDE).unwrap();
+        assert_eq!(regions.len(), 9);
+        let expected = [
+            (0x1000, 0x9_e000, RegionKind::Usable),
+            (0x10_0000, 0x10_0000, RegionKind::BootloaderReclaimable),
+            (0x20_0000, 0x1_0000, RegionKind::BootloaderReclaimable),
+            (0x21_0000, 0x4000, RegionKind::Unusable),
+            (0x30_0000, 0x2000, RegionKind::Unusable),
+            (0x40_0000, 0x1000, RegionKind::AcpiReclaimable),
+            (0x40_1000, 0x1000, RegionKind::AcpiNvs),
+            (0x50_0000, 0x1000, RegionKind::BadMemory),
+            (0xfee0_0000, 0x1000, RegionKind::Mmio),
+        ];
+        for (region, &(base, size, kind)) in regions.zip(expected.iter()) {
+            assert_eq!(region.base_address(), VAddr(base));
+            assert_eq!(region.size(), size);
+            assert_eq!(region.kind(), kind);
+        }
+    }
+
+    #[test]
+    fn runtime_services_are_never_usable() {
+        let mut desc = Desc::new(CONVENTIONAL, 0x1000, 1);
+        assert!(desc.is_usable());
+        desc.attribute |= MEMORY_RUNTIME;
+        assert!(desc.is_runtime());
+        assert!(!desc.is_usable());
+        assert!(desc.is_reserved());
+    }
+
+    #[test]
+    fn rejects_malformed_maps() {
+        let buf = [0u8; 96];
+        assert_eq!(
+            Descriptors::<VAddr>::new(&buf, 32).unwrap_err(),
+            Error::BadDescriptorSize
+        );
+        assert_eq!(
+            Descriptors::<VAddr>::new(&buf[..90], STRIDE).unwrap_err(),
+            Error::BadLength
+        );
+    }
+}
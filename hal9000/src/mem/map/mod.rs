@@ -0,0 +1,634 @@
+//
+// SOS: the Stupid Operating System
+//  by Eliza Weisman (eliza@elizas.website), and the SOS contributors.
+//
+//  Copyright (c) 2018 Eliza Weisman
+//  Released under the terms of the MIT license. See `LICENSE` in the root
+//  directory of this repository for more information.
+//
+//! Architecture-independent representation of platform-specific memory maps.
+pub use self::imp::RegionKind;
+
+pub mod efi;
+
+use self::imp::GenericRegion;
+use super::{page, Address, Page};
+use crate::{params::BootParams, Architecture};
+
+use core::{cmp, ops};
+
+/// A memory region.
+///
+/// This represents a region of memory with a base address and a length,
+/// such as those given to us by multiboot2 or other bootloaders. It is
+/// not necessarily page-aligned; a given `Region` will likely encompass
+/// several frames.
+pub trait Region {
+    type Addr: Address;
+
+    /// Returns the base address of the memory region.
+    fn base_address(&self) -> Self::Addr;
+
+    /// Returns the size in bytes of the memory region.
+    fn size(&self) -> usize;
+
+    /// Returns the end  address of the memory region.
+    ///
+    /// A region which ends at the very top of the address space has no
+    /// representable end address, so this saturates at the highest address
+    /// instead. The last byte of such a region is then treated as outside
+    /// it, which only ever loses a partial page.
+    fn end_address(&self) -> Self::Addr {
+        self.base_address().saturating_add(self.size())
+    }
+
+    /// Returns the number of frames contained in a given region.
+    fn num_frames<P: Page>(&self) -> usize {
+        self.size() / P::SIZE
+    }
+
+    /// Returns the kind of memory in this region.
+    fn kind(&self) -> RegionKind;
+
+    /// Returns true if this region is usable.
+    fn is_usable(&self) -> bool {
+        self.kind() == RegionKind::Usable
+    }
+
+    /// Returns true if this region is in use.
+    fn is_used(&self) -> bool {
+        self.kind() == RegionKind::InUse
+    }
+
+    /// Returns true if this region is used by the kernel.
+    fn is_kernel(&self) -> bool {
+        self.kind() == RegionKind::Kernel
+    }
+
+    /// Returns true if this region may be reused as general-purpose memory
+    /// once the kernel is done with its contents.
+    ///
+    /// See `RegionKind::is_reclaimable`.
+    fn is_reclaimable(&self) -> bool {
+        self.kind().is_reclaimable()
+    }
+
+    /// Returns true if this region belongs to the firmware or to hardware,
+    /// and must never be allocated or overwritten.
+    ///
+    /// See `RegionKind::is_reserved`.
+    fn is_reserved(&self) -> bool {
+        self.kind().is_reserved()
+    }
+}
+
+/// A normalized memory map, stored in a caller-provided buffer.
+///
+/// Bootloaders may hand us memory maps which are unsorted, which contain
+/// overlapping or zero-length regions, or which split a single region into
+/// several adjacent entries. A `MemoryMap` is always sorted by base address,
+/// contains no overlapping or empty regions, and merges adjacent regions of
+/// the same kind.
+///
+/// When two regions overlap, the overlapping part takes the kind of whichever
+/// region is most restrictive, so memory is only ever considered usable if
+/// nothing else claims it.
+#[derive(Debug)]
+pub struct MemoryMap<'a, A: 'a> {
+    regions: &'a mut [GenericRegion<A>],
+    len: usize,
+}
+
+/// Errors returned when building a `MemoryMap`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The buffer is too small to hold the normalized memory map.
+    Full,
+    /// The region extends past the top of the address space.
+    Overflow,
+}
+
+// ===== impl MemoryMap =====
+
+impl<'a, A: Address> MemoryMap<'a, A> {
+    /// Returns a new, empty memory map which stores its regions in `buf`.
+    pub fn new(buf: &'a mut [GenericRegion<A>]) -> Self {
+        MemoryMap {
+            regions: buf,
+            len: 0,
+        }
+    }
+
+    /// Returns a normalized memory map containing `regions`, stored in `buf`.
+    ///
+    /// Regions which extend past the end of the address space are ignored.
+    pub fn from_regions<I>(
+        buf: &'a mut [GenericRegion<A>],
+        regions: I,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator,
+        I::Item: Region<Addr = A>,
+    {
+        let mut map = Self::new(buf);
+        for region in regions {
+            match map.insert_region(&region) {
+                Ok(()) | Err(Error::Overflow) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Returns a normalized version of `params`' memory map, stored in `buf`,
+    /// with the kernel image removed from the usable memory.
+    pub fn from_params<P>(
+        buf: &'a mut [GenericRegion<A>],
+        params: &P,
+    ) -> Result<Self, Error>
+    where
+        P: BootParams,
+        P::Arch: Architecture<PAddr = A>,
+    {
+        let mut map = Self::from_regions(buf, params.mem_map())?;
+        map.reserve_kernel(params.kernel_base()..params.kernel_end())?;
+        Ok(map)
+    }
+
+    /// Adds `region` to the memory map.
+    pub fn insert_region<R>(&mut self, region: &R) -> Result<(), Error>
+    where
+        R: Region<Addr = A>,
+    {
+        self.insert(GenericRegion {
+            base_address: region.base_address(),
+            size: region.size(),
+            kind: region.kind(),
+        })
+    }
+
+    /// Adds `region` to the memory map.
+    ///
+    /// Any part of `region` which overlaps an existing region takes the kind
+    /// of whichever of the two is more restrictive.
+    ///
+    /// If this returns `Error::Full`, the map is still normalized, but only
+    /// part of `region` may have been added.
+    pub fn insert(&mut self, region: GenericRegion<A>) -> Result<(), Error> {
+        if region.size == 0 {
+            return Ok(());
+        }
+        // A region may end exactly at the top of the address space, in which
+        // case its end saturates and its last byte is dropped.
+        region
+            .base_address
+            .checked_add(region.size - 1)
+            .ok_or(Error::Overflow)?;
+        let result = self.overlay(
+            region.base_address,
+            region.end_address(),
+            region.kind,
+        );
+        // Coalesce even if the map filled up, as part of `region` may have
+        // been added next to a region of the same kind.
+        self.coalesce();
+        result
+    }
+
+    /// Adds the memory from `pos` to `end` as `kind`, where it is more
+    /// restrictive than the existing regions, without merging adjacent
+    /// regions.
+    fn overlay(
+        &mut self,
+        mut pos: A,
+        end: A,
+        kind: RegionKind,
+    ) -> Result<(), Error> {
+        let mut i = self
+            .regions()
+            .iter()
+            .position(|r| r.end_address() > pos)
+            .unwrap_or(self.len);
+
+        while pos < end {
+            if i == self.len || self.regions[i].base_address >= end {
+                self.insert_at(i, span(pos, end, kind))?;
+                break;
+            }
+
+            let existing = self.regions[i];
+            let (existing_start, existing_end) =
+                (existing.base_address, existing.end_address());
+            if existing_start > pos {
+                // Fill the gap before the next existing region.
+                self.insert_at(i, span(pos, existing_start, kind))?;
+                i += 1;
+                pos = existing_start;
+                continue;
+            }
+
+            let overlap_end = cmp::min(existing_end, end);
+            if priority(kind) > priority(existing.kind) {
+                // The new region wins, so split the existing region around
+                // the overlapping part.
+                let head = existing_start < pos;
+                let tail = overlap_end < existing_end;
+                if self.len + head as usize + tail as usize > self.regions.len()
+                {
+                    return Err(Error::Full);
+                }
+                if head {
+                    self.regions[i] = span(existing_start, pos, existing.kind);
+                    i += 1;
+                    self.insert_at(i, span(pos, overlap_end, kind))?;
+                } else {
+                    self.regions[i] = span(pos, overlap_end, kind);
+                }
+                if tail {
+                    let tail = span(overlap_end, existing_end, existing.kind);
+                    self.insert_at(i + 1, tail)?;
+                }
+            }
+            i += 1;
+            pos = overlap_end;
+        }
+        Ok(())
+    }
+
+    /// Marks the memory from `kernel.start` to `kernel.end` as occupied by
+    /// the kernel image, so that it is no longer usable.
+    pub fn reserve_kernel(
+        &mut self,
+        kernel: ops::Range<A>,
+    ) -> Result<(), Error> {
+        if kernel.end <= kernel.start {
+            return Ok(());
+        }
+        self.insert(span(kernel.start, kernel.end, RegionKind::Kernel))
+    }
+
+    /// Returns the regions in the memory map, sorted by base address.
+    #[inline]
+    pub fn regions(&self) -> &[GenericRegion<A>] {
+        &self.regions[..self.len]
+    }
+
+    /// Returns the number of regions in the memory map.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the memory map contains no regions.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the page-aligned ranges of usable memory in the map.
+    ///
+    /// Partial pages at the start and end of each usable region are
+    /// excluded, as are regions too small to contain a whole page.
+    pub fn usable_frames<'b, P>(
+        &'b self,
+    ) -> impl Iterator<Item = page::Range<P>> + 'b
+    where
+        P: Page<Address = A> + 'b,
+    {
+        self.regions()
+            .iter()
+            .filter(|region| region.is_usable())
+            .map(|region| {
+                page::Range::within(region.base_address..region.end_address())
+            })
+            .filter(|range| !range.is_empty())
+    }
+
+    /// Inserts `region` at index `i`, shifting every region after it up.
+    fn insert_at(
+        &mut self,
+        i: usize,
+        region: GenericRegion<A>,
+    ) -> Result<(), Error> {
+        if self.len == self.regions.len() {
+            return Err(Error::Full);
+        }
+        let mut j = self.len;
+        while j > i {
+            self.regions[j] = self.regions[j - 1];
+            j -= 1;
+        }
+        self.regions[i] = region;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Merges adjacent regions of the same kind.
+    fn coalesce(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        let mut last = 0;
+        for i in 1..self.len {
+            let region = self.regions[i];
+            let prev = self.regions[last];
+            if prev.kind == region.kind
+                && prev.end_address() == region.base_address
+            {
+                self.regions[last].size += region.size;
+            } else {
+                last += 1;
+                self.regions[last] = region;
+            }
+        }
+        self.len = last + 1;
+    }
+}
+
+/// Returns how restrictive a region kind is.
+///
+/// When two regions overlap, the more restrictive kind wins.
+fn priority(kind: RegionKind) -> u8 {
+    match kind {
+        RegionKind::Usable => 0,
+        RegionKind::BootloaderReclaimable => 1,
+        RegionKind::AcpiReclaimable => 2,
+        RegionKind::InUse => 3,
+        RegionKind::Module => 4,
+        RegionKind::Kernel => 5,
+        RegionKind::Framebuffer => 6,
+        RegionKind::Mmio => 7,
+        RegionKind::AcpiNvs => 8,
+        RegionKind::Unusable | RegionKind::__Nonexhaustive => 9,
+        RegionKind::BadMemory => 10,
+    }
+}
+
+/// Returns a region of the given `kind` from `start` to `end`.
+#[inline]
+fn span<A: Address>(start: A, end: A, kind: RegionKind) -> GenericRegion<A> {
+    GenericRegion {
+        base_address: start,
+        size: start.distance(end),
+        kind,
+    }
+}
+
+pub mod imp {
+    use super::*;
+
+    use core::cmp;
+
+    #[derive(Copy, Clone, Debug, Eq)]
+    pub struct GenericRegion<A> {
+        pub base_address: A,
+
+        /// The size in bytes of the memory region.
+        pub size: usize,
+
+        /// Whether this memory region is usable.
+        pub kind: RegionKind,
+    }
+
+    /// Marker for the type of a memory region.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    #[repr(C)]
+    pub enum RegionKind {
+        /// Usable RAM
+        Usable,
+        /// RAM in use by the kernel.
+        InUse,
+        /// Memory reserved by the firmware or hardware, for reasons not
+        /// described by a more specific variant.
+        Unusable,
+        /// The kernel image.
+        Kernel,
+        /// RAM holding ACPI tables, which may be reused once the tables have
+        /// been parsed.
+        AcpiReclaimable,
+        /// ACPI non-volatile storage, which the firmware expects to be
+        /// preserved across sleep states.
+        AcpiNvs,
+        /// RAM which the firmware has detected to be defective.
+        BadMemory,
+        /// RAM used by the bootloader, such as its page tables or the boot
+        /// information structures, which may be reused once the kernel is
+        /// done with them.
+        BootloaderReclaimable,
+        /// Boot modules, such as an initrd, loaded by the bootloader.
+        Module,
+        /// A framebuffer set up by the firmware or the bootloader.
+        Framebuffer,
+        /// Memory-mapped I/O registers.
+        Mmio,
+        /// Additional region types may be added.
+        __Nonexhaustive,
+    }
+
+    // ===== impl Region =====
+
+    impl<A: Address + Copy> Region for GenericRegion<A> {
+        type Addr = A;
+
+        /// Returns the base address of the memory region.
+        fn base_address(&self) -> Self::Addr {
+            self.base_address
+        }
+
+        /// Returns the size in bytes of the memory region.
+        fn size(&self) -> usize {
+            self.size
+        }
+
+        /// Returns the kind of memory in this region.
+        fn kind(&self) -> RegionKind {
+            self.kind
+        }
+    }
+
+    // ===== impl RegionKind =====
+
+    impl RegionKind {
+        /// Returns true if memory of this kind may be reused as
+        /// general-purpose memory once the kernel is done with its contents.
+        ///
+        /// This is the case for ACPI tables and for memory used by the
+        /// bootloader. Boot modules are not included, since the kernel
+        /// decides for itself when it is done with them.
+        pub fn is_reclaimable(&self) -> bool {
+            match *self {
+                RegionKind::AcpiReclaimable
+                | RegionKind::BootloaderReclaimable => true,
+                _ => false,
+            }
+        }
+
+        /// Returns true if memory of this kind belongs to the firmware or to
+        /// hardware, and must never be allocated or overwritten.
+        pub fn is_reserved(&self) -> bool {
+            match *self {
+                RegionKind::Unusable
+                | RegionKind::AcpiNvs
+                | RegionKind::BadMemory
+                | RegionKind::Framebuffer
+                | RegionKind::Mmio
+                | RegionKind::__Nonexhaustive => true,
+                _ => false,
+            }
+        }
+    }
+
+    impl<A> cmp::PartialEq for GenericRegion<A>
+    where
+        A: cmp::PartialEq,
+    {
+        fn eq(&self, other: &Self) -> bool {
+            self.base_address == other.base_address
+                && self.size == other.size
+                && self.kind == other.kind
+        }
+    }
+
+    impl<A> cmp::PartialOrd for GenericRegion<A>
+    where
+        A: cmp::PartialOrd,
+    {
+        fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+            self.base_address.partial_cmp(&other.base_address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{page::tests::TestPage, VAddr};
+
+    fn region(
+        base: usize,
+        size: usize,
+        kind: RegionKind,
+    ) -> GenericRegion<VAddr> {
+        GenericRegion {
+            base_address: VAddr(base),
+            size,
+            kind,
+        }
+    }
+
+    #[test]
+    fn sorts_and_merges_adjacent_regions() {
+        let input = [
+            region(0x3000, 0x1000, RegionKind::Usable),
+            region(0x1000, 0x2000, RegionKind::Usable),
+            region(0x8000, 0, RegionKind::Unusable),
+            region(0x4000, 0x1000, RegionKind::Unusable),
+        ];
+        let mut buf = [region(0, 0, RegionKind::Usable); 8];
+        let map =
+            MemoryMap::from_regions(&mut buf, input.iter().cloned()).unwrap();
+        assert_eq!(
+            map.regions(),
+            &[
+                region(0x1000, 0x3000, RegionKind::Usable),
+                region(0x4000, 0x1000, RegionKind::Unusable),
+            ]
+        );
+    }
+
+    #[test]
+    fn most_restrictive_kind_wins() {
+        let input = [
+            region(0x0000, 0x10000, RegionKind::Usable),
+            region(0x2000, 0x1000, RegionKind::Unusable),
+            region(0x8000, 0x10000, RegionKind::InUse),
+            // Less restrictive than what's already there, so it changes
+            // nothing.
+            region(0x1800, 0x2000, RegionKind::Usable),
+        ];
+        let mut buf = [region(0, 0, RegionKind::Usable); 8];
+        let map =
+            MemoryMap::from_regions(&mut buf, input.iter().cloned()).unwrap();
+        assert_eq!(
+            map.regions(),
+            &[
+                region(0x0000, 0x2000, RegionKind::Usable),
+                region(0x2000, 0x1000, RegionKind::Unusable),
+                region(0x3000, 0x5000, RegionKind::Usable),
+                region(0x8000, 0x10000, RegionKind::InUse),
+            ]
+        );
+    }
+
+    #[test]
+    fn reserve_kernel_and_usable_frames() {
+        let input = [region(0x0800, 0x9800, RegionKind::Usable)];
+        let mut buf = [region(0, 0, RegionKind::Usable); 4];
+        let mut map =
+            MemoryMap::from_regions(&mut buf, input.iter().cloned()).unwrap();
+        map.reserve_kernel(VAddr(0x4000)..VAddr(0x5800)).unwrap();
+
+        let mut usable = map.usable_frames::<TestPage>();
+        let range = |start, end| {
+            page::Range::new(TestPage(VAddr(start)), TestPage(VAddr(end)))
+        };
+        assert_eq!(usable.next(), Some(range(0x1000, 0x4000)));
+        assert_eq!(usable.next(), Some(range(0x6000, 0xa000)));
+        assert_eq!(usable.next(), None);
+    }
+
+    #[test]
+    fn full_buffer() {
+        let input = [
+            region(0x1000, 0x1000, RegionKind::Usable),
+            region(0x3000, 0x1000, RegionKind::Usable),
+        ];
+        let mut buf = [region(0, 0, RegionKind::Usable); 1];
+        let result = MemoryMap::from_regions(&mut buf, input.iter().cloned());
+        assert_eq!(result.unwrap_err(), Error::Full);
+    }
+
+    #[test]
+    fn normalized_after_full() {
+        let mut buf = [region(0, 0, RegionKind::Usable); 2];
+        let mut map = MemoryMap::new(&mut buf);
+        map.insert(region(0x0000, 0x1000, RegionKind::Kernel))
+            .unwrap();
+        map.insert(region(0x1000, 0x1000, RegionKind::Usable))
+            .unwrap();
+        // The first half replaces the usable region next to the kernel, but
+        // there's no room for the second half.
+        let result = map.insert(region(0x1000, 0x2000, RegionKind::Kernel));
+        assert_eq!(result, Err(Error::Full));
+        assert_eq!(map.regions(), &[region(0, 0x2000, RegionKind::Kernel)]);
+    }
+
+    #[test]
+    fn region_kind_queries() {
+        let acpi = region(0x1000, 0x1000, RegionKind::AcpiReclaimable);
+        assert!(acpi.is_reclaimable());
+        assert!(!acpi.is_usable());
+        assert!(!acpi.is_reserved());
+
+        let nvs = region(0x2000, 0x1000, RegionKind::AcpiNvs);
+        assert!(!nvs.is_reclaimable());
+        assert!(nvs.is_reserved());
+
+        assert!(!region(0, 0x1000, RegionKind::Module).is_reclaimable());
+        assert!(region(0, 0x1000, RegionKind::BadMemory).is_reserved());
+    }
+
+    #[test]
+    fn region_at_top_of_address_space() {
+        let top =
+            region(::core::usize::MAX - 0x1fff, 0x2000, RegionKind::Usable);
+        assert_eq!(top.end_address(), VAddr(::core::usize::MAX));
+
+        let input = [top, region(0x1000, 0x1000, RegionKind::Usable)];
+        let mut buf = [region(0, 0, RegionKind::Usable); 4];
+        let map =
+            MemoryMap::from_regions(&mut buf, input.iter().cloned()).unwrap();
+        assert_eq!(map.regions().len(), 2);
+        assert_eq!(map.regions()[1].base_address, top.base_address);
+    }
+}
@@ -12,11 +12,12 @@ pub use self::page::Page;
 use crate::{params::BootParams, util::Align, Architecture};
 use core::{fmt, ops};
 
+pub mod alloc;
 pub mod map;
 pub mod page;
 
 /// Trait representing an address, whether physical or virtual.
-pub trait Address: ops::Add + Sized {
+pub trait Address: ops::Add + Copy + Ord + Sized {
     /// The primitive numeric type used to represent this address.
     type Repr: Align;
 
@@ -28,6 +29,52 @@ pub trait Address: ops::Add + Sized {
 
     /// Returns true if this address is aligned on a page boundary.
     fn is_page_aligned<P: Page>(&self) -> bool;
+
+    /// Returns this address advanced by `bytes`, or `None` if the result
+    /// would overflow the address space.
+    fn checked_add(&self, bytes: usize) -> Option<Self>;
+
+    /// Returns this address moved back by `bytes`, or `None` if the result
+    /// would underflow the address space.
+    fn checked_sub(&self, bytes: usize) -> Option<Self>;
+
+    /// Returns the number of bytes between this address and `other`,
+    /// regardless of which of the two is higher.
+    ///
+    /// If the distance cannot be represented as a `usize`, this saturates at
+    /// `usize::MAX`.
+    fn distance(&self, other: Self) -> usize;
+
+    /// Returns this address advanced by `bytes`.
+    ///
+    /// # Panics
+    /// If the resulting address would overflow the address space. Use
+    /// `checked_add` when the offset is not known to be in bounds.
+    #[inline]
+    fn offset(&self, bytes: usize) -> Self {
+        self.checked_add(bytes)
+            .expect("address offset overflowed the address space")
+    }
+
+    /// Returns this address advanced by `bytes`, or the highest address in
+    /// the address space if that would overflow.
+    fn saturating_add(&self, bytes: usize) -> Self {
+        if let Some(addr) = self.checked_add(bytes) {
+            return addr;
+        }
+        // Without a constant for the highest address, approach it by
+        // halving the step whenever it would overflow. The room left is
+        // always less than twice the step, so each step size is taken at
+        // most once and this is logarithmic in `bytes`.
+        let (mut addr, mut step) = (*self, bytes / 2);
+        while step > 0 {
+            match addr.checked_add(step) {
+                Some(next) => addr = next,
+                None => step /= 2,
+            }
+        }
+        addr
+    }
 }
 
 /// A physical address.
@@ -105,6 +152,25 @@ impl Address for VAddr {
     fn is_page_aligned<P: Page>(&self) -> bool {
         self.0 % P::SIZE as usize == 0 as usize
     }
+
+    #[inline]
+    fn checked_add(&self, bytes: usize) -> Option<Self> {
+        self.0.checked_add(bytes).map(VAddr)
+    }
+
+    #[inline]
+    fn checked_sub(&self, bytes: usize) -> Option<Self> {
+        self.0.checked_sub(bytes).map(VAddr)
+    }
+
+    #[inline]
+    fn distance(&self, other: Self) -> usize {
+        if self.0 > other.0 {
+            self.0 - other.0
+        } else {
+            other.0 - self.0
+        }
+    }
 }
 
 impl Into<usize> for VAddr {
@@ -125,3 +191,40 @@ impl VAddr {
         self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vaddr_checked_arithmetic() {
+        let top = VAddr(::core::usize::MAX - 0xfff);
+        assert_eq!(top.checked_add(0xfff), Some(VAddr(::core::usize::MAX)));
+        assert_eq!(top.checked_add(0x1000), None);
+        assert_eq!(VAddr(0x1000).checked_sub(0x1000), Some(VAddr(0)));
+        assert_eq!(VAddr(0x1000).checked_sub(0x1001), None);
+        assert_eq!(VAddr(0x1000).offset(0x234), VAddr(0x1234));
+    }
+
+    #[test]
+    fn vaddr_saturating_add() {
+        let top = VAddr(::core::usize::MAX - 0xfff);
+        assert_eq!(top.saturating_add(0xfff), VAddr(::core::usize::MAX));
+        assert_eq!(top.saturating_add(0x1000), VAddr(::core::usize::MAX));
+        assert_eq!(top.saturating_add(0x1234_5678), VAddr(::core::usize::MAX));
+        assert_eq!(VAddr(0x1000).saturating_add(0x234), VAddr(0x1234));
+    }
+
+    #[test]
+    fn vaddr_distance() {
+        assert_eq!(VAddr(0x1000).distance(VAddr(0x3000)), 0x2000);
+        assert_eq!(VAddr(0x3000).distance(VAddr(0x1000)), 0x2000);
+        assert_eq!(VAddr(0x1000).distance(VAddr(0x1000)), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn vaddr_offset_overflow_panics() {
+        VAddr(::core::usize::MAX).offset(1);
+    }
+}
@@ -2,6 +2,7 @@ use crate::{
     mem::{Address, VAddr},
     Architecture,
 };
+use core::{cmp, marker::PhantomData, ops};
 
 /// A physical or virtual page.
 pub trait Page {
@@ -58,8 +59,106 @@ pub unsafe trait FrameAllocator {
     unsafe fn dealloc(&mut self, frame: Self::Frame)
         -> Result<(), Self::Error>;
 
-    // TODO: alloc_range/dealloc_range; requires an architecture-independent
-    //       way of representing frame ranges.
+    /// Returns a range of `n` physically contiguous frames.
+    ///
+    /// This is equivalent to `alloc_aligned(n, Self::FRAME_SIZE)`.
+    unsafe fn alloc_range(
+        &mut self,
+        n: usize,
+    ) -> Result<Range<Self::Frame>, RangeError<Self::Error>> {
+        self.alloc_aligned(n, Self::FRAME_SIZE)
+    }
+
+    /// Returns a range of `n` physically contiguous frames, starting at an
+    /// address that is a multiple of `align` bytes.
+    ///
+    /// `align` must be a power of two no smaller than `FRAME_SIZE`.
+    ///
+    /// The default implementation builds the range out of repeated calls to
+    /// `alloc`, so it will only succeed for allocators which hand out
+    /// adjacent frames in ascending order. Frames that were skipped to reach
+    /// the requested alignment are returned with `dealloc`; if that fails,
+    /// they are leaked, but the range is still returned. If `alloc` returns
+    /// a frame which does not follow the previous one, every frame allocated
+    /// so far is returned and `RangeError::NotContiguous` is returned.
+    /// Allocators which can do better should override this method.
+    unsafe fn alloc_aligned(
+        &mut self,
+        n: usize,
+        align: usize,
+    ) -> Result<Range<Self::Frame>, RangeError<Self::Error>> {
+        if !align.is_power_of_two() || align < Self::FRAME_SIZE {
+            return Err(RangeError::BadAlignment);
+        }
+        let bytes = match n.checked_mul(Self::FRAME_SIZE) {
+            Some(0) | None => return Err(RangeError::BadSize),
+            Some(bytes) => bytes,
+        };
+
+        let first = self.alloc().map_err(RangeError::Alloc)?;
+        let run_start = first.base_address();
+        let mut run_end = first.end_address();
+        loop {
+            let start = run_start.align_up(align);
+            if start < run_end && start.distance(run_end) >= bytes {
+                // Return any frames we skipped over to reach the alignment.
+                let skipped = Range::within(run_start..start);
+                if !skipped.is_empty() {
+                    let _ = self.dealloc_range(skipped);
+                }
+                let end = start.offset(bytes);
+                return Ok(Range::within(start..end));
+            }
+
+            let frame = match self.alloc() {
+                Ok(frame) => frame,
+                Err(e) => {
+                    let _ =
+                        self.dealloc_range(Range::within(run_start..run_end));
+                    return Err(RangeError::Alloc(e));
+                },
+            };
+            if frame.base_address() != run_end {
+                let _ = self.dealloc(frame);
+                let _ = self.dealloc_range(Range::within(run_start..run_end));
+                return Err(RangeError::NotContiguous);
+            }
+            run_end = frame.end_address();
+        }
+    }
+
+    /// Deallocate every frame in `range`.
+    ///
+    /// The default implementation calls `dealloc` once for each frame,
+    /// stopping at the first error.
+    ///
+    /// # Unsafety
+    /// This function is unsafe because undefined behaviour may result if the
+    /// frames in `range` were not originally allocated by this `Allocator`.
+    unsafe fn dealloc_range(
+        &mut self,
+        range: Range<Self::Frame>,
+    ) -> Result<(), Self::Error> {
+        for frame in range {
+            self.dealloc(frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors returned when allocating a contiguous range of frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RangeError<E> {
+    /// The allocator returned an error.
+    Alloc(E),
+    /// The allocator could not provide enough physically contiguous frames.
+    NotContiguous,
+    /// The requested alignment was not a power of two, or was smaller than
+    /// a frame.
+    BadAlignment,
+    /// Zero frames were requested, or the requested range would be larger
+    /// than the address space.
+    BadSize,
 }
 
 /// On some architectures, such as x86, page table changes must be flushed
@@ -85,7 +184,9 @@ pub trait Mapper {
     type Virtual: Page<Address = VAddr>;
 
     /// Architecture-dependent flags that configure a virtual page.
-    type Flags;
+    ///
+    /// These can be built from architecture-independent `Permissions`.
+    type Flags: From<Permissions>;
     /// The type returned by a page table update.
     ///
     /// This must be committed for the update to have an effect.
@@ -156,7 +257,9 @@ pub trait Mapper {
 
     /// Unmaps the given `VirtualPage`.
     ///
-    /// All freed frames are returned to the given `FrameAllocator`.
+    /// Frames which the mapper allocated itself, in `map_to_any`, are
+    /// returned to the given `FrameAllocator`. Frames passed to `map` or
+    /// `identity_map` belong to the caller, and are not freed.
     fn unmap<A>(
         &mut self,
         page: Self::Virtual,
@@ -173,16 +276,514 @@ pub trait Mapper {
     ) -> Result<Self::Update, Self::Error>;
 }
 
+/// Architecture-independent permissions and attributes for a mapping.
+///
+/// Portable code can describe the mapping it wants with `Permissions`, and
+/// convert it into the `Flags` of whichever `Mapper` it is using. The
+/// constants cover the common cases; other combinations can be built with
+/// struct update syntax:
+///
+/// ```
+/// use hal9000::mem::page::{CachePolicy, Permissions};
+///
+/// let mmio = Permissions {
+///     cache: CachePolicy::Uncached,
+///     ..Permissions::READ_WRITE
+/// };
+/// assert!(mmio.write && !mmio.execute);
+/// ```
+///
+/// An architecture which can't express a combination exactly grants the
+/// closest set of permissions that includes everything requested. For
+/// example, every mapped page is readable on x86_64. Pages which must not be
+/// accessed at all, such as guard pages, should be left unmapped rather than
+/// mapped with no permissions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    /// The page may be read.
+    pub read: bool,
+    /// The page may be written to.
+    pub write: bool,
+    /// Instructions may be fetched from the page.
+    pub execute: bool,
+    /// The page may be accessed from user mode, as well as by the kernel.
+    pub user: bool,
+    /// The mapping is the same in every address space, so it need not be
+    /// flushed from the TLB when switching between them.
+    pub global: bool,
+    /// How accesses to the page are cached.
+    pub cache: CachePolicy,
+}
+
+/// How accesses to a page are cached.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Reads and writes are cached. This is the right choice for RAM.
+    WriteBack,
+    /// Reads are cached, and writes go straight to memory.
+    WriteThrough,
+    /// Writes are combined in a buffer before going to memory, and reads are
+    /// not cached. This is the right choice for framebuffers.
+    WriteCombining,
+    /// Nothing is cached. This is the right choice for MMIO registers.
+    Uncached,
+}
+
 /// Represents a contiguous range of pages.
+///
+/// Like `core::ops::Range`, a `Range` is half-open: it contains every page
+/// from `start` up to, but not including, `end`.
 #[derive(Copy, Clone, Debug)]
 pub struct Range<P> {
     start: P,
     end: P,
 }
 
+/// An iterator over the pages in a `Range`.
+pub struct Iter<P: Page> {
+    next: P::Address,
+    end: P::Address,
+    _page: PhantomData<fn() -> P>,
+}
+
 impl TableUpdate for () {
     type Item = ();
     unsafe fn commit(self) -> Self::Item {
         // do nothing.
     }
 }
+
+// ===== impl Permissions =====
+
+impl Permissions {
+    /// A read-only, non-executable kernel page.
+    pub const READ: Permissions = Permissions {
+        read: true,
+        write: false,
+        execute: false,
+        user: false,
+        global: false,
+        cache: CachePolicy::WriteBack,
+    };
+
+    /// A writable, non-executable kernel page.
+    pub const READ_WRITE: Permissions = Permissions {
+        write: true,
+        ..Permissions::READ
+    };
+
+    /// A read-only, executable kernel page.
+    pub const READ_EXECUTE: Permissions = Permissions {
+        execute: true,
+        ..Permissions::READ
+    };
+}
+
+// ===== impl Range =====
+
+impl<P: Page> Range<P> {
+    /// Returns a new range from `start` up to, but not including, `end`.
+    ///
+    /// If `end` is lower than `start`, the range is empty.
+    pub fn new(start: P, end: P) -> Self {
+        if end.base_address() < start.base_address() {
+            let end = P::from_addr_down(start.base_address());
+            return Range { start, end };
+        }
+        Range { start, end }
+    }
+
+    /// Returns the smallest range of pages that contains every address in
+    /// `addrs`.
+    ///
+    /// The start address is rounded down and the end address is rounded up,
+    /// so the range may include bytes outside of `addrs`.
+    pub fn containing(addrs: ops::Range<P::Address>) -> Self {
+        Self::new(P::from_addr_down(addrs.start), P::from_addr_up(addrs.end))
+    }
+
+    /// Returns the largest range of pages that lies entirely inside `addrs`.
+    ///
+    /// The start address is rounded up and the end address is rounded down,
+    /// so partial pages at either end are excluded. This is the rounding to
+    /// use when handing out memory from a region that may not be
+    /// page-aligned.
+    pub fn within(addrs: ops::Range<P::Address>) -> Self {
+        Self::new(P::from_addr_up(addrs.start), P::from_addr_down(addrs.end))
+    }
+
+    /// Returns the first page in the range.
+    #[inline]
+    pub fn start(&self) -> &P {
+        &self.start
+    }
+
+    /// Returns the page one past the end of the range.
+    #[inline]
+    pub fn end(&self) -> &P {
+        &self.end
+    }
+
+    /// Returns the base address of the first page in the range.
+    #[inline]
+    pub fn start_address(&self) -> P::Address {
+        self.start.base_address()
+    }
+
+    /// Returns the address one past the last byte in the range.
+    #[inline]
+    pub fn end_address(&self) -> P::Address {
+        self.end.base_address()
+    }
+
+    /// Returns the number of pages in the range.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.start_address().distance(self.end_address()) / P::SIZE
+    }
+
+    /// Returns `true` if the range contains no pages.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.start_address() == self.end_address()
+    }
+
+    /// Returns `true` if `page` is inside this range.
+    pub fn contains(&self, page: &P) -> bool {
+        self.contains_addr(page.base_address())
+    }
+
+    /// Returns `true` if `addr` falls inside one of the pages in this range.
+    pub fn contains_addr(&self, addr: P::Address) -> bool {
+        addr >= self.start_address() && addr < self.end_address()
+    }
+
+    /// Divides the range into two at the page with index `mid`.
+    ///
+    /// The first range will contain the first `mid` pages, and the second
+    /// range will contain the rest. Returns `None` if `mid > self.len()`.
+    pub fn split_at(&self, mid: usize) -> Option<(Self, Self)> {
+        if mid > self.len() {
+            return None;
+        }
+        let mid = self.start_address().offset(mid * P::SIZE);
+        let head = Range {
+            start: P::from_addr_down(self.start_address()),
+            end: P::from_addr_down(mid),
+        };
+        let tail = Range {
+            start: P::from_addr_down(mid),
+            end: P::from_addr_down(self.end_address()),
+        };
+        Some((head, tail))
+    }
+
+    /// Returns the range of pages contained in both `self` and `other`, or
+    /// `None` if the two ranges don't overlap.
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let start = cmp::max(self.start_address(), other.start_address());
+        let end = cmp::min(self.end_address(), other.end_address());
+        if start >= end {
+            return None;
+        }
+        Some(Range {
+            start: P::from_addr_down(start),
+            end: P::from_addr_down(end),
+        })
+    }
+
+    /// Returns the range of addresses covered by the pages in this range.
+    pub fn to_addr_range(&self) -> ops::Range<P::Address> {
+        self.start_address()..self.end_address()
+    }
+
+    /// Returns an iterator over the pages in this range.
+    pub fn iter(&self) -> Iter<P> {
+        Iter {
+            next: self.start_address(),
+            end: self.end_address(),
+            _page: PhantomData,
+        }
+    }
+}
+
+impl<P: Page> IntoIterator for Range<P> {
+    type Item = P;
+    type IntoIter = Iter<P>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, P: Page> IntoIterator for &'a Range<P> {
+    type Item = P;
+    type IntoIter = Iter<P>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<P: Page> PartialEq for Range<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.start_address() == other.start_address()
+            && self.end_address() == other.end_address()
+    }
+}
+
+impl<P: Page> Eq for Range<P> {}
+
+// ===== impl Iter =====
+
+impl<P: Page> Clone for Iter<P> {
+    fn clone(&self) -> Self {
+        Iter {
+            next: self.next,
+            end: self.end,
+            _page: PhantomData,
+        }
+    }
+}
+
+impl<P: Page> Iterator for Iter<P> {
+    type Item = P;
+
+    fn next(&mut self) -> Option<P> {
+        if self.next >= self.end {
+            return None;
+        }
+        let page = P::from_addr_down(self.next);
+        self.next = self.next.offset(P::SIZE);
+        Some(page)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<P: Page> DoubleEndedIterator for Iter<P> {
+    fn next_back(&mut self) -> Option<P> {
+        if self.next >= self.end {
+            return None;
+        }
+        self.end = self.end.checked_sub(P::SIZE)?;
+        Some(P::from_addr_down(self.end))
+    }
+}
+
+impl<P: Page> ExactSizeIterator for Iter<P> {
+    #[inline]
+    fn len(&self) -> usize {
+        if self.next >= self.end {
+            return 0;
+        }
+        self.next.distance(self.end) / P::SIZE
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A 4K page of virtual memory, for testing code that is generic over
+    /// `Page`.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct TestPage(pub(crate) VAddr);
+
+    impl Page for TestPage {
+        const SHIFT: usize = 12;
+        const SIZE: usize = 4096;
+        type Address = VAddr;
+
+        fn from_addr_up(addr: VAddr) -> Self {
+            TestPage(addr.align_up(Self::SIZE))
+        }
+
+        fn from_addr_down(addr: VAddr) -> Self {
+            TestPage(addr.align_down(Self::SIZE))
+        }
+
+        fn base_address(&self) -> VAddr {
+            self.0
+        }
+
+        fn end_address(&self) -> VAddr {
+            self.0.offset(Self::SIZE)
+        }
+
+        fn number(&self) -> usize {
+            self.0.as_usize() >> Self::SHIFT
+        }
+    }
+
+    /// Hands out frames in ascending order from `next`, and never reuses
+    /// freed frames.
+    struct Sequential {
+        next: usize,
+        freed: usize,
+    }
+
+    unsafe impl FrameAllocator for Sequential {
+        type Frame = TestPage;
+        type Error = ();
+
+        unsafe fn alloc(&mut self) -> Result<TestPage, ()> {
+            let page = TestPage(VAddr(self.next));
+            self.next += TestPage::SIZE;
+            Ok(page)
+        }
+
+        unsafe fn dealloc(&mut self, _: TestPage) -> Result<(), ()> {
+            self.freed += 1;
+            Ok(())
+        }
+    }
+
+    /// Hands out every other frame.
+    struct Sparse(usize);
+
+    unsafe impl FrameAllocator for Sparse {
+        type Frame = TestPage;
+        type Error = ();
+
+        unsafe fn alloc(&mut self) -> Result<TestPage, ()> {
+            let page = TestPage(VAddr(self.0));
+            self.0 += 2 * TestPage::SIZE;
+            Ok(page)
+        }
+
+        unsafe fn dealloc(&mut self, _: TestPage) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// Hands out frames in ascending order, like `Sequential`, but can't
+    /// take any back.
+    struct NoDealloc(usize);
+
+    unsafe impl FrameAllocator for NoDealloc {
+        type Frame = TestPage;
+        type Error = ();
+
+        unsafe fn alloc(&mut self) -> Result<TestPage, ()> {
+            let page = TestPage(VAddr(self.0));
+            self.0 += TestPage::SIZE;
+            Ok(page)
+        }
+
+        unsafe fn dealloc(&mut self, _: TestPage) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    fn range(start: usize, end: usize) -> Range<TestPage> {
+        Range::new(TestPage(VAddr(start)), TestPage(VAddr(end)))
+    }
+
+    #[test]
+    fn range_rounding() {
+        let addrs = VAddr(0x1800)..VAddr(0x4800);
+        assert_eq!(
+            Range::<TestPage>::containing(addrs.clone()),
+            range(0x1000, 0x5000)
+        );
+        assert_eq!(Range::<TestPage>::within(addrs), range(0x2000, 0x4000));
+
+        let tiny = VAddr(0x1800)..VAddr(0x1900);
+        assert!(Range::<TestPage>::within(tiny).is_empty());
+    }
+
+    #[test]
+    fn range_len_and_iter() {
+        let r = range(0x1000, 0x4000);
+        assert_eq!(r.len(), 3);
+        let pages: [usize; 3] = [1, 2, 3];
+        assert!(r.iter().map(|p| p.number()).eq(pages.iter().cloned()));
+        assert!(r
+            .iter()
+            .rev()
+            .map(|p| p.number())
+            .eq(pages.iter().rev().cloned()));
+        assert_eq!(r.iter().len(), 3);
+        assert_eq!(range(0x4000, 0x1000).len(), 0);
+    }
+
+    #[test]
+    fn range_contains() {
+        let r = range(0x1000, 0x4000);
+        assert!(r.contains(&TestPage(VAddr(0x1000))));
+        assert!(r.contains(&TestPage(VAddr(0x3000))));
+        assert!(!r.contains(&TestPage(VAddr(0x4000))));
+        assert!(r.contains_addr(VAddr(0x3fff)));
+        assert!(!r.contains_addr(VAddr(0xfff)));
+    }
+
+    #[test]
+    fn range_split_at() {
+        let r = range(0x1000, 0x5000);
+        let (head, tail) = r.split_at(1).unwrap();
+        assert_eq!(head, range(0x1000, 0x2000));
+        assert_eq!(tail, range(0x2000, 0x5000));
+        assert!(r.split_at(4).unwrap().1.is_empty());
+        assert!(r.split_at(5).is_none());
+    }
+
+    #[test]
+    fn range_intersect() {
+        let a = range(0x1000, 0x5000);
+        let b = range(0x3000, 0x8000);
+        assert_eq!(a.intersect(&b), Some(range(0x3000, 0x5000)));
+        assert_eq!(a.intersect(&range(0x5000, 0x6000)), None);
+        assert_eq!(a.to_addr_range(), VAddr(0x1000)..VAddr(0x5000));
+    }
+
+    #[test]
+    fn default_alloc_range() {
+        let mut alloc = Sequential {
+            next: 0x1000,
+            freed: 0,
+        };
+        let r = unsafe { alloc.alloc_range(3) }.unwrap();
+        assert_eq!(r, range(0x1000, 0x4000));
+        assert_eq!(alloc.freed, 0);
+        assert_eq!(unsafe { alloc.alloc_range(0) }, Err(RangeError::BadSize));
+    }
+
+    #[test]
+    fn default_alloc_aligned_frees_skipped_frames() {
+        let mut alloc = Sequential {
+            next: 0x1000,
+            freed: 0,
+        };
+        let r = unsafe { alloc.alloc_aligned(2, 0x4000) }.unwrap();
+        assert_eq!(r, range(0x4000, 0x6000));
+        assert_eq!(alloc.freed, 3);
+        assert_eq!(
+            unsafe { alloc.alloc_aligned(1, 0x3000) },
+            Err(RangeError::BadAlignment)
+        );
+    }
+
+    #[test]
+    fn default_alloc_aligned_keeps_range_if_dealloc_fails() {
+        let mut alloc = NoDealloc(0x1000);
+        let r = unsafe { alloc.alloc_aligned(2, 0x4000) }.unwrap();
+        assert_eq!(r, range(0x4000, 0x6000));
+    }
+
+    #[test]
+    fn default_alloc_range_not_contiguous() {
+        let mut alloc = Sparse(0x1000);
+        assert_eq!(unsafe { alloc.alloc_range(1) }, Ok(range(0x1000, 0x2000)));
+        assert_eq!(
+            unsafe { alloc.alloc_range(2) },
+            Err(RangeError::NotContiguous)
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{mem, Architecture};
-use core::ops::Range;
+use core::ops;
 
 pub trait BootParams {
     /// This architecture's physical address type.
@@ -23,9 +23,162 @@ pub trait BootParams {
     /// Returns the range of frames containing the kernel binary.
     ///
     /// The kernel _should_ start on the first address in the frame range,
-    fn kernel_frames(&self) -> Range<<Self::Arch as Architecture>::Frame>;
+    fn kernel_frames(
+        &self,
+    ) -> mem::page::Range<<Self::Arch as Architecture>::Frame>;
+
+    /// Returns the kernel command line, if the bootloader provided one.
+    fn command_line(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the boot module at `index`, if there is one.
+    ///
+    /// Modules are numbered from 0 in the order the bootloader reports them,
+    /// with no gaps.
+    fn module(
+        &self,
+        _index: usize,
+    ) -> Option<Module<'_, <Self::Arch as Architecture>::PAddr>> {
+        None
+    }
+
+    /// Returns an iterator over the boot modules.
+    fn modules(&self) -> Modules<'_, Self> {
+        Modules {
+            params: self,
+            index: 0,
+        }
+    }
+
+    /// Returns the physical memory occupied by the initrd, if there is one.
+    ///
+    /// By default, this is the first boot module, which is where bootloaders
+    /// that don't distinguish the initrd from other modules put it by
+    /// convention.
+    fn initrd(
+        &self,
+    ) -> Option<ops::Range<<Self::Arch as Architecture>::PAddr>> {
+        self.module(0).map(|module| module.range)
+    }
+
+    /// Returns the framebuffer set up by the bootloader or firmware, if there
+    /// is one.
+    fn framebuffer(
+        &self,
+    ) -> Option<Framebuffer<<Self::Arch as Architecture>::PAddr>> {
+        None
+    }
+
+    /// Returns the physical address of the ACPI RSDP, if the bootloader
+    /// found one.
+    fn rsdp(&self) -> Option<<Self::Arch as Architecture>::PAddr> {
+        None
+    }
+
+    /// Returns the physical address of the flattened device tree, if the
+    /// bootloader provided one.
+    fn device_tree(&self) -> Option<<Self::Arch as Architecture>::PAddr> {
+        None
+    }
+
+    /// Returns information about the bootloader, if it identified itself.
+    fn bootloader(&self) -> Option<&dyn BootloaderInfo> {
+        None
+    }
 }
 
 pub trait BootloaderInfo {
-    fn name(&self) -> &'static str;
+    /// Returns the bootloader's name.
+    fn name(&self) -> &str;
+
+    /// Returns the bootloader's version, if it reported one.
+    fn version(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// A module loaded into memory by the bootloader, such as an initrd.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Module<'a, A> {
+    /// The physical memory occupied by the module.
+    pub range: ops::Range<A>,
+    /// The module's command line, which may be empty.
+    pub cmdline: &'a str,
+}
+
+/// An iterator over the boot modules.
+///
+/// This is returned by `BootParams::modules`.
+#[derive(Debug)]
+pub struct Modules<'a, P: ?Sized + 'a> {
+    params: &'a P,
+    index: usize,
+}
+
+/// A linear framebuffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Framebuffer<A> {
+    /// The physical address of the framebuffer.
+    pub address: A,
+    /// The width of the framebuffer in pixels (or characters, in text mode).
+    pub width: u32,
+    /// The height of the framebuffer in pixels (or characters, in text mode).
+    pub height: u32,
+    /// The number of bytes in each row.
+    pub pitch: u32,
+    /// The number of bits in each pixel.
+    pub bpp: u8,
+    /// The layout of each pixel.
+    pub format: PixelFormat,
+}
+
+/// The layout of the pixels in a framebuffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Each pixel holds a red, green and blue component.
+    Rgb {
+        red: ColorMask,
+        green: ColorMask,
+        blue: ColorMask,
+    },
+    /// Each pixel is an index into a palette.
+    Indexed,
+    /// The framebuffer is an EGA-style text buffer.
+    Text,
+    /// The pixel layout wasn't reported, or isn't one we understand.
+    Unknown,
+}
+
+/// The position of a color component within a pixel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ColorMask {
+    /// The number of bits in the component.
+    pub size: u8,
+    /// The position of the component's lowest bit.
+    pub shift: u8,
+}
+
+// ===== impl Modules =====
+
+impl<'a, P> Iterator for Modules<'a, P>
+where
+    P: BootParams + ?Sized,
+{
+    type Item = Module<'a, <P::Arch as Architecture>::PAddr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let module = self.params.module(self.index)?;
+        self.index += 1;
+        Some(module)
+    }
+}
+
+impl<'a, P: ?Sized> Clone for Modules<'a, P> {
+    fn clone(&self) -> Self {
+        Modules {
+            params: self.params,
+            index: self.index,
+        }
+    }
 }
@@ -0,0 +1,163 @@
+//! A frame allocator over the usable memory in a `MockParams`.
+use crate::{Frame, MockParams, PAddr};
+use hal9000::{
+    mem::{
+        map::{imp::GenericRegion, MemoryMap, RegionKind},
+        page::FrameAllocator,
+    },
+    params::BootParams,
+};
+use std::collections::BTreeSet;
+
+/// A `FrameAllocator` which hands out the lowest free frame first.
+///
+/// Unlike an allocator a kernel would use, this one remembers which frames it
+/// has handed out, so that freeing a frame twice, or freeing a frame it
+/// never allocated, returns `Error::NotAllocated` rather than corrupting its
+/// state.
+#[derive(Clone, Debug, Default)]
+pub struct Allocator {
+    free: BTreeSet<Frame>,
+    allocated: BTreeSet<Frame>,
+}
+
+/// Errors returned by an `Allocator`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// There are no free frames left.
+    OutOfMemory,
+    /// The frame being freed is not currently allocated.
+    NotAllocated,
+}
+
+impl Allocator {
+    /// Returns an allocator with no free frames.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an allocator which owns every usable frame in `params`'
+    /// memory map, except for the frames occupied by the kernel image.
+    pub fn from_params(params: &MockParams) -> Self {
+        // Normalizing can at most split every region in two, and the kernel
+        // adds one more region, plus one for each region it splits.
+        let len = params.mem_map().count() * 2 + 3;
+        let mut buf = vec![
+            GenericRegion {
+                base_address: PAddr(0),
+                size: 0,
+                kind: RegionKind::Unusable,
+            };
+            len
+        ];
+        let map = MemoryMap::from_params(&mut buf[..], params)
+            .expect("memory map buffer is large enough");
+        let mut alloc = Self::new();
+        for range in map.usable_frames::<Frame>() {
+            alloc.free.extend(range);
+        }
+        alloc
+    }
+
+    /// Adds `frame` to the free frames.
+    pub fn add_frame(&mut self, frame: Frame) {
+        self.free.insert(frame);
+    }
+
+    /// Returns the number of free frames.
+    pub fn free_frames(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Returns the number of frames currently allocated.
+    pub fn allocated_frames(&self) -> usize {
+        self.allocated.len()
+    }
+
+    /// Returns `true` if `frame` is currently allocated.
+    pub fn is_allocated(&self, frame: &Frame) -> bool {
+        self.allocated.contains(frame)
+    }
+}
+
+unsafe impl FrameAllocator for Allocator {
+    type Frame = Frame;
+    type Error = Error;
+
+    unsafe fn alloc(&mut self) -> Result<Frame, Error> {
+        let frame = *self.free.iter().next().ok_or(Error::OutOfMemory)?;
+        self.free.remove(&frame);
+        self.allocated.insert(frame);
+        Ok(frame)
+    }
+
+    unsafe fn dealloc(&mut self, frame: Frame) -> Result<(), Error> {
+        if !self.allocated.remove(&frame) {
+            return Err(Error::NotAllocated);
+        }
+        self.free.insert(frame);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal9000::mem::{page::RangeError, Page};
+
+    fn params() -> MockParams {
+        MockParams::new()
+            .with_region(PAddr(0), 0x8000, RegionKind::Usable)
+            .with_region(PAddr(0x4000), 0x1000, RegionKind::Unusable)
+            .with_kernel(PAddr(0x1000)..PAddr(0x2000))
+    }
+
+    #[test]
+    fn allocates_usable_frames_lowest_first() {
+        let mut alloc = Allocator::from_params(&params());
+        assert_eq!(alloc.free_frames(), 6);
+        let frames: Vec<_> =
+            (0..6).map(|_| unsafe { alloc.alloc() }.unwrap()).collect();
+        let numbers: Vec<_> = frames.iter().map(|f| f.number()).collect();
+        assert_eq!(numbers, [0, 2, 3, 5, 6, 7]);
+        assert_eq!(unsafe { alloc.alloc() }, Err(Error::OutOfMemory));
+        assert_eq!(alloc.allocated_frames(), 6);
+
+        unsafe { alloc.dealloc(frames[2]) }.unwrap();
+        assert!(!alloc.is_allocated(&frames[2]));
+        assert_eq!(unsafe { alloc.alloc() }, Ok(frames[2]));
+    }
+
+    #[test]
+    fn ranges_use_the_default_methods() {
+        let mut alloc = Allocator::from_params(&params());
+        // Frame 0 is followed by the kernel, so it can't start a range.
+        assert_eq!(
+            unsafe { alloc.alloc_range(2) },
+            Err(RangeError::NotContiguous)
+        );
+        assert_eq!(alloc.allocated_frames(), 0);
+
+        let first = unsafe { alloc.alloc() }.unwrap();
+        let range = unsafe { alloc.alloc_range(2) }.unwrap();
+        assert_eq!(range.start().number(), 2);
+        assert_eq!(alloc.allocated_frames(), 3);
+        unsafe { alloc.dealloc_range(range) }.unwrap();
+        assert_eq!(alloc.allocated_frames(), 1);
+        assert!(alloc.is_allocated(&first));
+    }
+
+    #[test]
+    fn double_free_is_an_error() {
+        let mut alloc = Allocator::from_params(&params());
+        let frame = unsafe { alloc.alloc() }.unwrap();
+        unsafe {
+            assert_eq!(alloc.dealloc(frame), Ok(()));
+            assert_eq!(alloc.dealloc(frame), Err(Error::NotAllocated));
+            assert_eq!(
+                alloc.dealloc(Frame::from_number(1)),
+                Err(Error::NotAllocated)
+            );
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//
+// SOS: the Stupid Operating System
+//  by Eliza Weisman (eliza@elizas.website), and the SOS contributors.
+//
+//  Copyright (c) 2018 Eliza Weisman
+//  Released under the terms of the MIT license. See `LICENSE` in the root
+//  directory of this repository for more information.
+//
+//! # A hosted mock architecture for testing code written against HAL-9000
+//!
+//! Kernel code that is generic over `Architecture`, `BootParams`,
+//! `FrameAllocator` or `Mapper` can be instantiated with the types in this
+//! crate and run under `cargo test` on the host:
+//!
+//! + `Mock` is an `Architecture` with 4 KiB frames and 64-bit physical
+//!   addresses.
+//! + `Memory` simulates physical memory, backed by a `Vec`.
+//! + `MockParams` is a `BootParams` with a memory map and boot information
+//!   configured by the test.
+//! + `Allocator` is a `FrameAllocator` over the usable memory in a
+//!   `MockParams`, which checks for double frees.
+//! + `Mapper` is a `Mapper` which keeps its mappings in software.
+#![feature(step_trait)]
+
+#[macro_use]
+extern crate hal9000_derive;
+
+use hal9000::{
+    mem::{self, Address, Page, VAddr},
+    Architecture,
+};
+
+pub mod alloc;
+pub mod mapper;
+pub mod memory;
+pub mod params;
+
+pub use self::{
+    alloc::Allocator, mapper::Mapper, memory::Memory, params::MockParams,
+};
+
+/// The mock architecture.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Mock;
+
+/// A mock physical address.
+#[derive(Copy, Clone, Eq, Ord, PartialEq, PartialOrd, Hash, Address)]
+#[address_repr(u64)]
+#[repr(transparent)]
+pub struct PAddr(pub u64);
+
+/// A 4 KiB frame of mock physical memory.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Hash)]
+pub struct Frame(PAddr);
+
+/// A 4 KiB page of virtual memory.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct VirtualPage(VAddr);
+
+/// The size of frames and pages, in bytes.
+pub const PAGE_SIZE: usize = 4096;
+const PAGE_SHIFT: usize = 12;
+
+impl Architecture for Mock {
+    /// This architecture's physical address type.
+    type PAddr = PAddr;
+
+    /// This architecture's physical page type.
+    type Frame = Frame;
+
+    /// The name of the architecture (for logging, etc).
+    const NAME: &'static str = "mock";
+
+    const BITS: &'static str = "64";
+}
+
+// ===== impl PAddr =====
+
+/// Mock physical addresses are host addresses, so code which writes to
+/// physical memory, such as `Bitmap::from_params`, can be run over a host
+/// buffer by describing the buffer's address in a `MockParams`.
+///
+/// This is only sound for addresses inside such a buffer; `Memory` does not
+/// live at the addresses it simulates.
+impl mem::PhysicalAddress for PAddr {
+    fn as_mut_ptr<T>(&self) -> *mut T {
+        self.0 as usize as *mut T
+    }
+}
+
+// ===== impl Frame =====
+
+impl Frame {
+    /// Returns the frame starting at `addr`.
+    ///
+    /// # Panics
+    /// If `addr` is not page-aligned.
+    pub fn containing(addr: PAddr) -> Self {
+        assert!(
+            addr.is_page_aligned::<Self>(),
+            "frame address {:?} is not page-aligned",
+            addr
+        );
+        Frame(addr)
+    }
+
+    /// Returns the frame with the given number.
+    pub fn from_number(number: usize) -> Self {
+        Frame(PAddr((number as u64) << PAGE_SHIFT))
+    }
+}
+
+impl mem::Page for Frame {
+    const SHIFT: usize = PAGE_SHIFT;
+    const SIZE: usize = PAGE_SIZE;
+    type Address = PAddr;
+
+    fn from_addr_up(addr: PAddr) -> Self {
+        Frame(addr.align_up(PAGE_SIZE))
+    }
+
+    fn from_addr_down(addr: PAddr) -> Self {
+        Frame(addr.align_down(PAGE_SIZE))
+    }
+
+    fn base_address(&self) -> PAddr {
+        self.0
+    }
+
+    fn end_address(&self) -> PAddr {
+        self.0.offset(PAGE_SIZE)
+    }
+
+    fn number(&self) -> usize {
+        (self.0).0 as usize >> PAGE_SHIFT
+    }
+}
+
+// ===== impl VirtualPage =====
+
+impl VirtualPage {
+    /// Returns the page with the given number.
+    pub fn from_number(number: usize) -> Self {
+        VirtualPage(VAddr(number << PAGE_SHIFT))
+    }
+}
+
+impl mem::Page for VirtualPage {
+    const SHIFT: usize = PAGE_SHIFT;
+    const SIZE: usize = PAGE_SIZE;
+    type Address = VAddr;
+
+    fn from_addr_up(addr: VAddr) -> Self {
+        VirtualPage(addr.align_up(PAGE_SIZE))
+    }
+
+    fn from_addr_down(addr: VAddr) -> Self {
+        VirtualPage(addr.align_down(PAGE_SIZE))
+    }
+
+    fn base_address(&self) -> VAddr {
+        self.0
+    }
+
+    fn end_address(&self) -> VAddr {
+        self.0.offset(PAGE_SIZE)
+    }
+
+    fn number(&self) -> usize {
+        self.0.as_usize() >> PAGE_SHIFT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal9000::{
+        mem::{
+            alloc::Bitmap,
+            map::RegionKind,
+            page::{FrameAllocator, Mapper as _, TableUpdate},
+        },
+        params::BootParams,
+    };
+
+    /// A frame of host memory, aligned like a mock frame.
+    #[repr(C, align(4096))]
+    struct HostFrame([u8; PAGE_SIZE]);
+
+    /// An example of kernel code that is generic over HAL-9000's traits:
+    /// maps a fresh frame at `page` and writes `value` into it.
+    fn map_and_write<M, A>(
+        mapper: &mut M,
+        alloc: &mut A,
+        memory: &mut Memory,
+        page: M::Virtual,
+        flags: M::Flags,
+        value: u64,
+    ) -> Result<(), M::Error>
+    where
+        M: hal9000::mem::page::Mapper<
+            Arch = Mock,
+            Physical = Frame,
+            PAddr = PAddr,
+        >,
+        A: FrameAllocator<Frame = Frame>,
+    {
+        let vaddr = page.base_address();
+        unsafe { mapper.map_to_any(page, flags, alloc)?.commit() };
+        let paddr = mapper.translate(vaddr).expect("page was just mapped");
+        memory.write_u64(paddr, value).expect("frame is in memory");
+        Ok(())
+    }
+
+    #[test]
+    fn generic_kernel_code_runs_on_the_host() {
+        let params = MockParams::new()
+            .with_region(PAddr(0), 0x1_0000, RegionKind::Usable)
+            .with_kernel(PAddr(0)..PAddr(0x2000));
+        let mut memory = Memory::for_params(&params);
+        let mut alloc = Allocator::from_params(&params);
+        let mut mapper = Mapper::new();
+        assert_eq!(params.kernel_frames().len(), 2);
+        assert_eq!(alloc.free_frames(), 14);
+
+        let page = VirtualPage::from_number(0x400);
+        map_and_write(
+            &mut mapper,
+            &mut alloc,
+            &mut memory,
+            page,
+            mapper::Flags::WRITABLE,
+            0xdead_beef,
+        )
+        .unwrap();
+
+        let frame = mapper.translate_page(page).unwrap();
+        assert_eq!(frame, Frame::from_number(2));
+        assert_eq!(memory.read_u64(frame.base_address()), Ok(0xdead_beef));
+        assert_eq!(alloc.free_frames(), 13);
+    }
+
+    #[test]
+    fn bitmap_from_params() {
+        let mut memory = (0..64)
+            .map(|_| HostFrame([0; PAGE_SIZE]))
+            .collect::<Vec<_>>();
+        let base = PAddr(memory.as_mut_ptr() as u64);
+        let frame = |n: usize| Frame::containing(base.offset(n * PAGE_SIZE));
+        let params = MockParams::new()
+            .with_region(base, 32 * PAGE_SIZE, RegionKind::Usable)
+            .with_region(
+                base.offset(32 * PAGE_SIZE),
+                8 * PAGE_SIZE,
+                RegionKind::Unusable,
+            )
+            .with_region(
+                base.offset(40 * PAGE_SIZE),
+                24 * PAGE_SIZE,
+                RegionKind::Usable,
+            )
+            .with_kernel(
+                base.offset(4 * PAGE_SIZE)..base.offset(8 * PAGE_SIZE),
+            );
+
+        let mut bitmap = unsafe { Bitmap::<Frame>::from_params(&params) }
+            .expect("memory map has room for the bitmap");
+        assert_eq!(bitmap.frames(), 64);
+        // The bitmap itself is stored in the first frame, below the kernel.
+        assert!(!bitmap.is_free(&frame(0)));
+        assert!((1..4).all(|n| bitmap.is_free(&frame(n))));
+        assert!((4..8).all(|n| !bitmap.is_free(&frame(n))));
+        assert!((8..32).all(|n| bitmap.is_free(&frame(n))));
+        assert!((32..40).all(|n| !bitmap.is_free(&frame(n))));
+        assert!((40..64).all(|n| bitmap.is_free(&frame(n))));
+        assert_eq!(bitmap.free_frames(), 64 - 1 - 4 - 8);
+        assert_eq!(unsafe { bitmap.alloc() }, Ok(frame(1)));
+    }
+}
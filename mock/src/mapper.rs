@@ -0,0 +1,371 @@
+//! A software page table.
+use crate::{Frame, Mock, PAddr, VirtualPage, PAGE_SIZE};
+use hal9000::mem::{
+    page::{self, FrameAllocator, Permissions, TableUpdate},
+    Address, Page, VAddr,
+};
+use std::collections::BTreeMap;
+
+/// A `Mapper` which keeps its mappings in a `BTreeMap`.
+///
+/// No intermediate tables are simulated, so mapping a page to a given frame
+/// never allocates. Frames allocated by `map_to_any` are owned by the mapper
+/// and returned to the allocator when their page is unmapped; frames passed
+/// to `map` or `identity_map` belong to the caller and are not.
+#[derive(Clone, Debug, Default)]
+pub struct Mapper {
+    pages: BTreeMap<VirtualPage, Mapping>,
+}
+
+/// The flags on a page mapped by a `Mapper`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Flags {
+    /// Whether the page may be written to.
+    pub writable: bool,
+    /// Whether the page is accessible from user mode.
+    pub user: bool,
+    /// Whether code on the page may be executed.
+    pub executable: bool,
+}
+
+/// A page table update returned by a `Mapper`.
+///
+/// The update has already been applied when it is returned; committing it
+/// returns the page whose mapping changed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[must_use = "page table updates must be committed"]
+pub struct Update {
+    page: VirtualPage,
+}
+
+/// Errors returned by a `Mapper`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The page is already mapped.
+    AlreadyMapped,
+    /// The page is not mapped.
+    NotMapped,
+    /// The frame allocator could not allocate or free a frame.
+    Alloc,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Mapping {
+    frame: Frame,
+    flags: Flags,
+    owned: bool,
+}
+
+// ===== impl Mapper =====
+
+impl Mapper {
+    /// Returns a mapper with no pages mapped.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the flags on `page`, if it is mapped.
+    pub fn flags(&self, page: VirtualPage) -> Option<Flags> {
+        self.pages.get(&page).map(|mapping| mapping.flags)
+    }
+
+    /// Returns the number of mapped pages.
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns `true` if no pages are mapped.
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// Returns an iterator over the mapped pages, in ascending order, and the
+    /// frames they map to.
+    pub fn mappings<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (VirtualPage, Frame, Flags)> + 'a {
+        self.pages
+            .iter()
+            .map(|(&page, mapping)| (page, mapping.frame, mapping.flags))
+    }
+
+    fn insert(
+        &mut self,
+        page: VirtualPage,
+        mapping: Mapping,
+    ) -> Result<Update, Error> {
+        if self.pages.contains_key(&page) {
+            return Err(Error::AlreadyMapped);
+        }
+        self.pages.insert(page, mapping);
+        Ok(Update { page })
+    }
+}
+
+impl page::Mapper for Mapper {
+    type Arch = Mock;
+    type Virtual = VirtualPage;
+    type Flags = Flags;
+    type Update = Update;
+    type Error = Error;
+
+    fn translate(&self, vaddr: VAddr) -> Option<PAddr> {
+        let page = VirtualPage::from_addr_down(vaddr);
+        let offset = vaddr.as_usize() % PAGE_SIZE;
+        self.translate_page(page)
+            .map(|frame| frame.base_address().offset(offset))
+    }
+
+    fn translate_page(&self, page: VirtualPage) -> Option<Frame> {
+        self.pages.get(&page).map(|mapping| mapping.frame)
+    }
+
+    fn map<A>(
+        &mut self,
+        page: VirtualPage,
+        frame: Frame,
+        flags: Flags,
+        _alloc: &mut A,
+    ) -> Result<Update, Error>
+    where
+        A: FrameAllocator<Frame = Frame>,
+    {
+        self.insert(
+            page,
+            Mapping {
+                frame,
+                flags,
+                owned: false,
+            },
+        )
+    }
+
+    fn identity_map<A>(
+        &mut self,
+        frame: Frame,
+        flags: Flags,
+        alloc: &mut A,
+    ) -> Result<Update, Error>
+    where
+        A: FrameAllocator<Frame = Frame>,
+    {
+        let page = VirtualPage::from_number(frame.number());
+        self.map(page, frame, flags, alloc)
+    }
+
+    fn map_to_any<A>(
+        &mut self,
+        page: VirtualPage,
+        flags: Flags,
+        alloc: &mut A,
+    ) -> Result<Update, Error>
+    where
+        A: FrameAllocator<Frame = Frame>,
+    {
+        if self.pages.contains_key(&page) {
+            return Err(Error::AlreadyMapped);
+        }
+        let frame = unsafe { alloc.alloc() }.map_err(|_| Error::Alloc)?;
+        self.insert(
+            page,
+            Mapping {
+                frame,
+                flags,
+                owned: true,
+            },
+        )
+    }
+
+    fn unmap<A>(
+        &mut self,
+        page: VirtualPage,
+        alloc: &mut A,
+    ) -> Result<Update, Error>
+    where
+        A: FrameAllocator<Frame = Frame>,
+    {
+        let mapping = self.pages.remove(&page).ok_or(Error::NotMapped)?;
+        if mapping.owned {
+            unsafe { alloc.dealloc(mapping.frame) }
+                .map_err(|_| Error::Alloc)?;
+        }
+        Ok(Update { page })
+    }
+
+    fn set_flags(
+        &mut self,
+        page: VirtualPage,
+        flags: Flags,
+    ) -> Result<Update, Error> {
+        let mapping = self.pages.get_mut(&page).ok_or(Error::NotMapped)?;
+        mapping.flags = flags;
+        Ok(Update { page })
+    }
+}
+
+// ===== impl Flags =====
+
+impl Flags {
+    /// A read-only, kernel-only, non-executable page.
+    pub const READ_ONLY: Flags = Flags {
+        writable: false,
+        user: false,
+        executable: false,
+    };
+
+    /// A writable, kernel-only, non-executable page.
+    pub const WRITABLE: Flags = Flags {
+        writable: true,
+        user: false,
+        executable: false,
+    };
+}
+
+impl From<Permissions> for Flags {
+    /// Pages are always readable, and the global bit and cache policy are
+    /// ignored.
+    fn from(permissions: Permissions) -> Self {
+        Flags {
+            writable: permissions.write,
+            user: permissions.user,
+            executable: permissions.execute,
+        }
+    }
+}
+
+// ===== impl Update =====
+
+impl Update {
+    /// Returns the page whose mapping changed.
+    pub fn page(&self) -> VirtualPage {
+        self.page
+    }
+}
+
+impl TableUpdate for Update {
+    type Item = VirtualPage;
+
+    unsafe fn commit(self) -> VirtualPage {
+        self.page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Allocator;
+    use hal9000::mem::page::Mapper as _;
+
+    fn alloc() -> Allocator {
+        let mut alloc = Allocator::new();
+        for number in 0x10..0x20 {
+            alloc.add_frame(Frame::from_number(number));
+        }
+        alloc
+    }
+
+    #[test]
+    fn map_and_translate() {
+        let mut alloc = alloc();
+        let mut mapper = Mapper::new();
+        let page = VirtualPage::from_number(0x1234);
+        let frame = Frame::from_number(0x42);
+        let update = mapper
+            .map(page, frame, Flags::READ_ONLY, &mut alloc)
+            .unwrap();
+        assert_eq!(unsafe { update.commit() }, page);
+
+        assert_eq!(mapper.translate_page(page), Some(frame));
+        assert_eq!(mapper.translate(VAddr(0x0123_4567)), Some(PAddr(0x42_567)));
+        assert_eq!(mapper.translate(VAddr(0x0123_5000)), None);
+        assert_eq!(
+            mapper.map(page, frame, Flags::READ_ONLY, &mut alloc),
+            Err(Error::AlreadyMapped)
+        );
+        assert_eq!(alloc.free_frames(), 16);
+    }
+
+    #[test]
+    fn identity_map() {
+        let mut alloc = alloc();
+        let mut mapper = Mapper::new();
+        let frame = Frame::from_number(0xb8);
+        let _ = mapper
+            .identity_map(frame, Flags::WRITABLE, &mut alloc)
+            .unwrap();
+        assert_eq!(mapper.translate(VAddr(0xb8_010)), Some(PAddr(0xb8_010)));
+    }
+
+    #[test]
+    fn set_flags() {
+        let mut alloc = alloc();
+        let mut mapper = Mapper::new();
+        let page = VirtualPage::from_number(1);
+        assert_eq!(
+            mapper.set_flags(page, Flags::WRITABLE),
+            Err(Error::NotMapped)
+        );
+        let _ = mapper
+            .map_to_any(page, Flags::READ_ONLY, &mut alloc)
+            .unwrap();
+        let _ = mapper.set_flags(page, Flags::WRITABLE).unwrap();
+        assert_eq!(mapper.flags(page), Some(Flags::WRITABLE));
+    }
+
+    #[test]
+    fn unmap_frees_only_owned_frames() {
+        let mut alloc = alloc();
+        let mut mapper = Mapper::new();
+        let owned = VirtualPage::from_number(1);
+        let borrowed = VirtualPage::from_number(2);
+        let _ = mapper
+            .map_to_any(owned, Flags::WRITABLE, &mut alloc)
+            .unwrap();
+        let _ = mapper
+            .map(
+                borrowed,
+                Frame::from_number(0x100),
+                Flags::WRITABLE,
+                &mut alloc,
+            )
+            .unwrap();
+        assert_eq!(mapper.len(), 2);
+        assert_eq!(alloc.allocated_frames(), 1);
+
+        let _ = mapper.unmap(owned, &mut alloc).unwrap();
+        let _ = mapper.unmap(borrowed, &mut alloc).unwrap();
+        assert!(mapper.is_empty());
+        assert_eq!(alloc.allocated_frames(), 0);
+        assert_eq!(mapper.unmap(owned, &mut alloc), Err(Error::NotMapped));
+    }
+
+    #[test]
+    fn flags_from_permissions() {
+        assert_eq!(Flags::from(Permissions::READ), Flags::READ_ONLY);
+        assert_eq!(Flags::from(Permissions::READ_WRITE), Flags::WRITABLE);
+        let user_code = Permissions {
+            user: true,
+            ..Permissions::READ_EXECUTE
+        };
+        assert_eq!(
+            Flags::from(user_code),
+            Flags {
+                writable: false,
+                user: true,
+                executable: true,
+            }
+        );
+    }
+
+    #[test]
+    fn out_of_memory() {
+        let mut alloc = Allocator::new();
+        let mut mapper = Mapper::new();
+        let page = VirtualPage::from_number(1);
+        assert_eq!(
+            mapper.map_to_any(page, Flags::WRITABLE, &mut alloc),
+            Err(Error::Alloc)
+        );
+        assert!(mapper.is_empty());
+    }
+}
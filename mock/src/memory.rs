@@ -0,0 +1,148 @@
+//! Simulated physical memory.
+use crate::{Frame, MockParams, PAddr, PAGE_SIZE};
+use hal9000::{
+    mem::{map::Region, Page},
+    params::BootParams,
+};
+
+/// A simulated region of physical memory, backed by a `Vec`.
+///
+/// The memory starts at `base` and is zero-filled when it is created.
+/// Accesses outside of it fail with `Error::OutOfBounds`, rather than
+/// panicking, so that tests can check that code stays inside the memory it
+/// was given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Memory {
+    base: PAddr,
+    bytes: Vec<u8>,
+}
+
+/// Errors returned when accessing simulated memory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The access was not entirely inside the simulated memory.
+    OutOfBounds,
+}
+
+impl Memory {
+    /// Returns `size` bytes of simulated memory starting at `base`.
+    pub fn new(base: PAddr, size: usize) -> Self {
+        Memory {
+            base,
+            bytes: vec![0; size],
+        }
+    }
+
+    /// Returns simulated memory covering every region in `params`' memory
+    /// map, from address 0 up to the end of the highest region.
+    pub fn for_params(params: &MockParams) -> Self {
+        let end = params
+            .mem_map()
+            .map(|region| region.end_address().0)
+            .max()
+            .unwrap_or(0);
+        Memory::new(PAddr(0), end as usize)
+    }
+
+    /// Returns the address where the simulated memory starts.
+    #[inline]
+    pub fn base(&self) -> PAddr {
+        self.base
+    }
+
+    /// Returns the size of the simulated memory in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns the `len` bytes starting at `addr`.
+    pub fn slice(&self, addr: PAddr, len: usize) -> Result<&[u8], Error> {
+        let start = self.offset(addr, len)?;
+        Ok(&self.bytes[start..start + len])
+    }
+
+    /// Returns the `len` bytes starting at `addr`, mutably.
+    pub fn slice_mut(
+        &mut self,
+        addr: PAddr,
+        len: usize,
+    ) -> Result<&mut [u8], Error> {
+        let start = self.offset(addr, len)?;
+        Ok(&mut self.bytes[start..start + len])
+    }
+
+    /// Returns the contents of `frame`.
+    pub fn frame(&self, frame: &Frame) -> Result<&[u8], Error> {
+        self.slice(frame.base_address(), PAGE_SIZE)
+    }
+
+    /// Returns the contents of `frame`, mutably.
+    pub fn frame_mut(&mut self, frame: &Frame) -> Result<&mut [u8], Error> {
+        self.slice_mut(frame.base_address(), PAGE_SIZE)
+    }
+
+    /// Copies the bytes starting at `addr` into `buf`.
+    pub fn read(&self, addr: PAddr, buf: &mut [u8]) -> Result<(), Error> {
+        buf.copy_from_slice(self.slice(addr, buf.len())?);
+        Ok(())
+    }
+
+    /// Copies `buf` into memory starting at `addr`.
+    pub fn write(&mut self, addr: PAddr, buf: &[u8]) -> Result<(), Error> {
+        self.slice_mut(addr, buf.len())?.copy_from_slice(buf);
+        Ok(())
+    }
+
+    /// Reads a little-endian `u64` from `addr`.
+    pub fn read_u64(&self, addr: PAddr) -> Result<u64, Error> {
+        let bytes = self.slice(addr, 8)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |n, &byte| n << 8 | u64::from(byte)))
+    }
+
+    /// Writes `value` to `addr` as a little-endian `u64`.
+    pub fn write_u64(&mut self, addr: PAddr, value: u64) -> Result<(), Error> {
+        let bytes = self.slice_mut(addr, 8)?;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = (value >> (i * 8)) as u8;
+        }
+        Ok(())
+    }
+
+    /// Returns the offset into `bytes` of the `len` bytes at `addr`, if they
+    /// are all inside the simulated memory.
+    fn offset(&self, addr: PAddr, len: usize) -> Result<usize, Error> {
+        if addr < self.base {
+            return Err(Error::OutOfBounds);
+        }
+        let start = (addr.0 - self.base.0) as usize;
+        match start.checked_add(len) {
+            Some(end) if end <= self.bytes.len() => Ok(start),
+            _ => Err(Error::OutOfBounds),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_are_checked() {
+        let mut memory = Memory::new(PAddr(0x1000), 0x2000);
+        memory
+            .write_u64(PAddr(0x1ff8), 0x0102_0304_0506_0708)
+            .unwrap();
+        assert_eq!(memory.read_u64(PAddr(0x1ff8)), Ok(0x0102_0304_0506_0708));
+        assert_eq!(memory.slice(PAddr(0x1ff8), 1), Ok(&[0x08][..]));
+
+        assert_eq!(memory.read_u64(PAddr(0xff8)), Err(Error::OutOfBounds));
+        assert_eq!(memory.write_u64(PAddr(0x2ffc), 0), Err(Error::OutOfBounds));
+        let frame = Frame::from_number(2);
+        assert_eq!(memory.frame(&frame).map(<[u8]>::len), Ok(PAGE_SIZE));
+        assert!(memory.frame(&Frame::from_number(3)).is_err());
+    }
+}
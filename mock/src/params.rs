@@ -0,0 +1,243 @@
+//! Fake boot parameters.
+use crate::{Frame, Mock, PAddr};
+use hal9000::{
+    mem::{
+        map::{imp::GenericRegion, RegionKind},
+        page,
+    },
+    params::{BootParams, BootloaderInfo, Framebuffer, Module},
+};
+use std::{ops, vec};
+
+/// Boot parameters configured by a test.
+///
+/// A `MockParams` starts out with an empty memory map, an empty kernel image
+/// at address 0, and no other boot information; the `with_*` methods add
+/// to it.
+///
+/// # Examples
+/// ```
+/// use hal9000::{mem::map::RegionKind, params::BootParams};
+/// use hal9000_mock::{MockParams, PAddr};
+///
+/// let params = MockParams::new()
+///     .with_region(PAddr(0), 0x10_0000, RegionKind::Usable)
+///     .with_kernel(PAddr(0x1000)..PAddr(0x5000))
+///     .with_command_line("console=ttyS0");
+/// assert_eq!(params.kernel_frames().len(), 4);
+/// assert_eq!(params.command_line(), Some("console=ttyS0"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockParams {
+    regions: Vec<GenericRegion<PAddr>>,
+    kernel: ops::Range<PAddr>,
+    command_line: Option<String>,
+    modules: Vec<(ops::Range<PAddr>, String)>,
+    framebuffer: Option<Framebuffer<PAddr>>,
+    rsdp: Option<PAddr>,
+    device_tree: Option<PAddr>,
+    bootloader: Option<MockBootloader>,
+}
+
+/// The bootloader reported by a `MockParams`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockBootloader {
+    name: String,
+    version: Option<String>,
+}
+
+// ===== impl MockParams =====
+
+impl MockParams {
+    /// Returns new, empty boot parameters.
+    pub fn new() -> Self {
+        MockParams {
+            regions: Vec::new(),
+            kernel: PAddr(0)..PAddr(0),
+            command_line: None,
+            modules: Vec::new(),
+            framebuffer: None,
+            rsdp: None,
+            device_tree: None,
+            bootloader: None,
+        }
+    }
+
+    /// Adds a region of `size` bytes starting at `base` to the memory map.
+    ///
+    /// Regions are reported in the order they were added, and may overlap,
+    /// just as a real firmware memory map might.
+    pub fn with_region(
+        mut self,
+        base: PAddr,
+        size: usize,
+        kind: RegionKind,
+    ) -> Self {
+        self.regions.push(GenericRegion {
+            base_address: base,
+            size,
+            kind,
+        });
+        self
+    }
+
+    /// Sets the memory occupied by the kernel image.
+    pub fn with_kernel(mut self, kernel: ops::Range<PAddr>) -> Self {
+        self.kernel = kernel;
+        self
+    }
+
+    /// Sets the kernel command line.
+    pub fn with_command_line(mut self, command_line: &str) -> Self {
+        self.command_line = Some(command_line.to_owned());
+        self
+    }
+
+    /// Adds a boot module occupying `range`.
+    pub fn with_module(
+        mut self,
+        range: ops::Range<PAddr>,
+        cmdline: &str,
+    ) -> Self {
+        self.modules.push((range, cmdline.to_owned()));
+        self
+    }
+
+    /// Sets the framebuffer.
+    pub fn with_framebuffer(mut self, framebuffer: Framebuffer<PAddr>) -> Self {
+        self.framebuffer = Some(framebuffer);
+        self
+    }
+
+    /// Sets the address of the ACPI RSDP.
+    pub fn with_rsdp(mut self, rsdp: PAddr) -> Self {
+        self.rsdp = Some(rsdp);
+        self
+    }
+
+    /// Sets the address of the flattened device tree.
+    pub fn with_device_tree(mut self, device_tree: PAddr) -> Self {
+        self.device_tree = Some(device_tree);
+        self
+    }
+
+    /// Sets the bootloader's name and version.
+    pub fn with_bootloader(
+        mut self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Self {
+        self.bootloader = Some(MockBootloader {
+            name: name.to_owned(),
+            version: version.map(str::to_owned),
+        });
+        self
+    }
+}
+
+impl BootParams for MockParams {
+    type Arch = Mock;
+    type MemRegion = GenericRegion<PAddr>;
+    type MemMap = vec::IntoIter<GenericRegion<PAddr>>;
+
+    fn kernel_base(&self) -> PAddr {
+        self.kernel.start
+    }
+
+    fn kernel_end(&self) -> PAddr {
+        self.kernel.end
+    }
+
+    fn mem_map(&self) -> Self::MemMap {
+        self.regions.clone().into_iter()
+    }
+
+    fn kernel_frames(&self) -> page::Range<Frame> {
+        page::Range::containing(self.kernel.clone())
+    }
+
+    fn command_line(&self) -> Option<&str> {
+        self.command_line.as_ref().map(String::as_str)
+    }
+
+    fn module(&self, index: usize) -> Option<Module<'_, PAddr>> {
+        self.modules
+            .get(index)
+            .map(|&(ref range, ref cmdline)| Module {
+                range: range.clone(),
+                cmdline,
+            })
+    }
+
+    fn framebuffer(&self) -> Option<Framebuffer<PAddr>> {
+        self.framebuffer
+    }
+
+    fn rsdp(&self) -> Option<PAddr> {
+        self.rsdp
+    }
+
+    fn device_tree(&self) -> Option<PAddr> {
+        self.device_tree
+    }
+
+    fn bootloader(&self) -> Option<&dyn BootloaderInfo> {
+        self.bootloader
+            .as_ref()
+            .map(|bootloader| bootloader as &dyn BootloaderInfo)
+    }
+}
+
+impl Default for MockParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ===== impl MockBootloader =====
+
+impl BootloaderInfo for MockBootloader {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> Option<&str> {
+        self.version.as_ref().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal9000::mem::map::Region;
+
+    #[test]
+    fn reports_what_it_was_given() {
+        let params = MockParams::new()
+            .with_region(PAddr(0), 0x9_f000, RegionKind::Usable)
+            .with_region(PAddr(0x10_0000), 0x70_0000, RegionKind::Usable)
+            .with_kernel(PAddr(0x10_0000)..PAddr(0x12_3456))
+            .with_module(PAddr(0x20_0000)..PAddr(0x28_0000), "initrd")
+            .with_module(PAddr(0x30_0000)..PAddr(0x30_1000), "")
+            .with_rsdp(PAddr(0xe_0000))
+            .with_bootloader("mockboot", Some("1.0"));
+
+        let regions: Vec<_> = params.mem_map().collect();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[1].base_address(), PAddr(0x10_0000));
+        assert_eq!(regions[1].kind(), RegionKind::Usable);
+
+        assert_eq!(params.kernel_frames().len(), 0x24);
+        assert_eq!(params.command_line(), None);
+        assert_eq!(params.modules().count(), 2);
+        assert_eq!(params.initrd(), Some(PAddr(0x20_0000)..PAddr(0x28_0000)));
+        assert_eq!(params.module(0).unwrap().cmdline, "initrd");
+        assert_eq!(params.rsdp(), Some(PAddr(0xe_0000)));
+        assert_eq!(params.device_tree(), None);
+        assert_eq!(params.framebuffer(), None);
+
+        let bootloader = params.bootloader().unwrap();
+        assert_eq!(bootloader.name(), "mockboot");
+        assert_eq!(bootloader.version(), Some("1.0"));
+    }
+}
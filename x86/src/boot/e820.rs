@@ -0,0 +1,332 @@
+//! E820 memory maps.
+//!
+//! The BIOS reports the physical memory map through `int 0x15, eax=0xe820`,
+//! one entry at a time. Each entry is a `u64` base address, a `u64` length and
+//! a `u32` type, optionally followed by a `u32` of ACPI 3.0 extended
+//! attributes. Linux-style boot protocols hand the kernel the same table in
+//! `boot_params`, always with 20-byte entries.
+use super::{read_u32, read_u64};
+use crate::x64::{PAddr, PhysicalPage, X86_64};
+use core::ops;
+use hal9000::{
+    mem::{
+        map::{Region, RegionKind},
+        page,
+    },
+    params::BootParams,
+};
+
+/// Size of an E820 entry without extended attributes.
+pub const ENTRY_SIZE: usize = 20;
+/// Size of an E820 entry with ACPI 3.0 extended attributes.
+pub const EXT_ENTRY_SIZE: usize = 24;
+
+/// Extended attribute bit indicating that an entry should be used.
+///
+/// Entries with this bit clear must be ignored.
+pub const ATTR_ENABLED: u32 = 1 << 0;
+/// Extended attribute bit indicating that an entry is non-volatile memory.
+pub const ATTR_NON_VOLATILE: u32 = 1 << 1;
+
+/// An entry in an E820 memory map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct E820Entry {
+    /// The base address of the memory region.
+    pub base: u64,
+    /// The length of the memory region in bytes.
+    pub len: u64,
+    /// The E820 type code of the memory region.
+    pub kind: u32,
+    /// The ACPI 3.0 extended attributes of the memory region.
+    ///
+    /// Entries read from a table without extended attributes have
+    /// `ATTR_ENABLED` set.
+    pub attributes: u32,
+}
+
+/// An iterator over the entries in a raw E820 table.
+///
+/// Entries whose extended attributes mark them as disabled, and entries of
+/// zero length, are skipped.
+#[derive(Clone, Debug)]
+pub struct E820Entries<'a> {
+    table: &'a [u8],
+    entry_size: usize,
+}
+
+/// Boot parameters built from a raw E820 table.
+///
+/// E820 only describes memory, so the location of the kernel must be
+/// supplied separately (e.g. from linker symbols).
+#[derive(Clone, Debug)]
+pub struct E820Params<'a> {
+    entries: E820Entries<'a>,
+    kernel: ops::Range<PAddr>,
+}
+
+/// Errors returned when reading an E820 table.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The entry size was smaller than `ENTRY_SIZE`.
+    BadEntrySize,
+    /// The table's length was not a multiple of the entry size.
+    BadLength,
+    /// The kernel's end address was below its base address.
+    BadKernelRange,
+}
+
+// ===== impl E820Entry =====
+
+impl E820Entry {
+    /// Usable RAM.
+    pub const USABLE: u32 = 1;
+    /// Reserved by the firmware.
+    pub const RESERVED: u32 = 2;
+    /// ACPI tables, reclaimable once they have been read.
+    pub const ACPI_RECLAIMABLE: u32 = 3;
+    /// ACPI non-volatile storage, which must be preserved across sleep.
+    pub const ACPI_NVS: u32 = 4;
+    /// Memory that the firmware found to be faulty.
+    pub const BAD_MEMORY: u32 = 5;
+
+    /// Returns `true` if this entry's extended attributes mark it as enabled.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.attributes & ATTR_ENABLED != 0
+    }
+
+    /// Returns `true` if this entry's extended attributes mark it as
+    /// non-volatile memory.
+    #[inline]
+    pub fn is_non_volatile(&self) -> bool {
+        self.attributes & ATTR_NON_VOLATILE != 0
+    }
+}
+
+impl Region for E820Entry {
+    type Addr = PAddr;
+
+    /// Returns the base address of the memory region.
+    fn base_address(&self) -> PAddr {
+        PAddr(self.base)
+    }
+
+    /// Returns the size in bytes of the memory region.
+    fn size(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns the kind of memory in this region.
+    ///
+    /// Non-volatile memory is never handed out as general-purpose RAM, even
+    /// if its type code says it is usable.
+    fn kind(&self) -> RegionKind {
+        match self.kind {
+            E820Entry::USABLE if !self.is_non_volatile() => RegionKind::Usable,
+            E820Entry::ACPI_RECLAIMABLE => RegionKind::AcpiReclaimable,
+            E820Entry::ACPI_NVS => RegionKind::AcpiNvs,
+            E820Entry::BAD_MEMORY => RegionKind::BadMemory,
+            _ => RegionKind::Unusable,
+        }
+    }
+}
+
+// ===== impl E820Entries =====
+
+impl<'a> E820Entries<'a> {
+    /// Reads entries of `entry_size` bytes from `table`.
+    ///
+    /// `entry_size` is usually `ENTRY_SIZE` or `EXT_ENTRY_SIZE`; larger
+    /// entries are accepted, and the bytes following the extended attributes
+    /// are ignored.
+    pub fn new(table: &'a [u8], entry_size: usize) -> Result<Self, Error> {
+        if entry_size < ENTRY_SIZE {
+            return Err(Error::BadEntrySize);
+        }
+        if table.len() % entry_size != 0 {
+            return Err(Error::BadLength);
+        }
+        Ok(E820Entries { table, entry_size })
+    }
+
+    fn read(&self) -> Option<E820Entry> {
+        let attributes = if self.entry_size >= EXT_ENTRY_SIZE {
+            read_u32(self.table, 20)?
+        } else {
+            ATTR_ENABLED
+        };
+        Some(E820Entry {
+            base: read_u64(self.table, 0)?,
+            len: read_u64(self.table, 8)?,
+            kind: read_u32(self.table, 16)?,
+            attributes,
+        })
+    }
+}
+
+impl<'a> Iterator for E820Entries<'a> {
+    type Item = E820Entry;
+
+    fn next(&mut self) -> Option<E820Entry> {
+        loop {
+            let entry = self.read()?;
+            self.table = &self.table[self.entry_size..];
+            if entry.is_enabled() && entry.len != 0 {
+                return Some(entry);
+            }
+        }
+    }
+}
+
+// ===== impl E820Params =====
+
+impl<'a> E820Params<'a> {
+    /// Returns boot parameters for a kernel loaded at `kernel`, with the
+    /// memory map read from `table`.
+    pub fn new(
+        table: &'a [u8],
+        entry_size: usize,
+        kernel: ops::Range<PAddr>,
+    ) -> Result<Self, Error> {
+        if kernel.end < kernel.start {
+            return Err(Error::BadKernelRange);
+        }
+        let entries = E820Entries::new(table, entry_size)?;
+        Ok(E820Params { entries, kernel })
+    }
+}
+
+impl<'a> BootParams for E820Params<'a> {
+    type Arch = X86_64;
+    type MemRegion = E820Entry;
+    type MemMap = E820Entries<'a>;
+
+    /// Returns the base address of the kernel memory region.
+    fn kernel_base(&self) -> PAddr {
+        self.kernel.start
+    }
+
+    /// Returns the end address of the kernel memory region.
+    fn kernel_end(&self) -> PAddr {
+        self.kernel.end
+    }
+
+    /// Returns the memory map.
+    fn mem_map(&self) -> Self::MemMap {
+        self.entries.clone()
+    }
+
+    /// Returns the range of frames containing the kernel binary.
+    fn kernel_frames(&self) -> page::Range<PhysicalPage> {
+        page::Range::containing(self.kernel.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::vec::Vec;
+    use super::*;
+    use crate::boot::tests::{push_u32, push_u64};
+
+    fn table(entries: &[(u64, u64, u32, u32)], extended: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for &(base, len, kind, attributes) in entries {
+            push_u64(&mut buf, base);
+            push_u64(&mut buf, len);
+            push_u32(&mut buf, kind);
+            if extended {
+                push_u32(&mut buf, attributes);
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn legacy_entries() {
+        let bytes = table(
+            &[
+                (0, 0x9fc00, 1, 0),
+                (0x9fc00, 0x400, 2, 0),
+                (0x10_0000, 0x7ee_0000, 1, 0),
+                (0x7fe_0000, 0x1_0000, 3, 0),
+                (0x7ff_0000, 0x1_0000, 4, 0),
+                (0x800_0000, 0, 1, 0),
+            ],
+            false,
+        );
+        let kinds: Vec<_> = E820Entries::new(&bytes, ENTRY_SIZE)
+            .unwrap()
+            .map(|entry| (entry.base_address(), entry.kind()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (PAddr(0), RegionKind::Usable),
+                (PAddr(0x9fc00), RegionKind::Unusable),
+                (PAddr(0x10_0000), RegionKind::Usable),
+                (PAddr(0x7fe_0000), RegionKind::AcpiReclaimable),
+                (PAddr(0x7ff_0000), RegionKind::AcpiNvs),
+            ]
+        );
+    }
+
+    #[test]
+    fn extended_attributes() {
+        let bytes = table(
+            &[
+                (0, 0x9fc00, 1, ATTR_ENABLED),
+                (0x10_0000, 0x10_0000, 1, 0),
+                (0x20_0000, 0x10_0000, 1, ATTR_ENABLED | ATTR_NON_VOLATILE),
+                (0x30_0000, 0x10_0000, 5, ATTR_ENABLED),
+            ],
+            true,
+        );
+        let kinds: Vec<_> = E820Entries::new(&bytes, EXT_ENTRY_SIZE)
+            .unwrap()
+            .map(|entry| (entry.base_address(), entry.kind()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (PAddr(0), RegionKind::Usable),
+                (PAddr(0x20_0000), RegionKind::Unusable),
+                (PAddr(0x30_0000), RegionKind::BadMemory),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        let bytes = table(&[(0, 0x1000, 1, 0)], false);
+        assert_eq!(
+            E820Entries::new(&bytes, 16).unwrap_err(),
+            Error::BadEntrySize
+        );
+        assert_eq!(
+            E820Entries::new(&bytes[..19], ENTRY_SIZE).unwrap_err(),
+            Error::BadLength
+        );
+        assert_eq!(
+            E820Params::new(&bytes, ENTRY_SIZE, PAddr(0x2000)..PAddr(0x1000))
+                .unwrap_err(),
+            Error::BadKernelRange
+        );
+    }
+
+    #[test]
+    fn boot_params() {
+        let bytes = table(&[(0x10_0000, 0x100_0000, 1, 0)], false);
+        let params = E820Params::new(
+            &bytes,
+            ENTRY_SIZE,
+            PAddr(0x10_0000)..PAddr(0x14_2000),
+        )
+        .unwrap();
+        assert_eq!(params.kernel_base(), PAddr(0x10_0000));
+        assert_eq!(params.kernel_end(), PAddr(0x14_2000));
+        assert_eq!(params.kernel_frames().len(), 0x42);
+        assert_eq!(params.mem_map().count(), 1);
+    }
+}
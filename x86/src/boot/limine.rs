@@ -0,0 +1,885 @@
+//! The Limine boot protocol.
+//!
+//! Rather than passing the kernel a single boot information structure, a
+//! Limine-compliant bootloader scans the kernel image for _requests_: structs
+//! beginning with a magic identifier, which the kernel places in statics. For
+//! each request it recognises, the bootloader fills in a pointer to the
+//! corresponding _response_ before jumping to the kernel's entry point.
+//!
+//! The kernel declares the requests it wants, for example:
+//!
+//! ```ignore
+//! #[used]
+//! static MEMMAP: Request<MemmapResponse> = Request::memmap();
+//! #[used]
+//! static KERNEL_ADDRESS: Request<KernelAddressResponse> =
+//!     Request::kernel_address();
+//! ```
+//!
+//! and then builds a `LimineBootParams` from the responses.
+use super::c_str;
+use crate::x64::{PAddr, PhysicalPage, X86_64};
+use core::{cell::UnsafeCell, fmt, ptr, slice};
+use hal9000::{
+    mem::{
+        map::{Region, RegionKind},
+        page, Address,
+    },
+    params::{self, BootParams, BootloaderInfo, ColorMask, PixelFormat},
+};
+
+/// A request for information from the bootloader.
+///
+/// The bootloader only finds requests which are placed in the kernel image,
+/// so these must be declared as `#[used]` statics.
+#[repr(C)]
+pub struct Request<R: 'static> {
+    id: [u64; 4],
+    revision: u64,
+    /// Written by the bootloader, even though requests are immutable
+    /// statics. The `UnsafeCell` makes that write sound, and keeps the
+    /// request out of read-only memory.
+    response: UnsafeCell<*const R>,
+}
+
+/// The bootloader's response to a `Request<BootloaderInfoResponse>`.
+#[repr(C)]
+pub struct BootloaderInfoResponse {
+    revision: u64,
+    name: *const u8,
+    version: *const u8,
+}
+
+/// The bootloader's response to a `Request<HhdmResponse>`.
+///
+/// The higher-half direct map (HHDM) is a mapping of all of physical memory,
+/// starting at `offset`, which the bootloader sets up for the kernel.
+#[repr(C)]
+pub struct HhdmResponse {
+    revision: u64,
+    offset: u64,
+}
+
+/// The bootloader's response to a `Request<MemmapResponse>`.
+#[repr(C)]
+pub struct MemmapResponse {
+    revision: u64,
+    entry_count: u64,
+    entries: *const *const MemmapEntry,
+}
+
+/// An entry in the Limine memory map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct MemmapEntry {
+    /// The base address of the memory region.
+    pub base: u64,
+    /// The length of the memory region in bytes.
+    pub length: u64,
+    /// The Limine type of the memory region.
+    pub kind: u64,
+}
+
+/// The bootloader's response to a `Request<KernelAddressResponse>`.
+#[repr(C)]
+pub struct KernelAddressResponse {
+    revision: u64,
+    physical_base: u64,
+    virtual_base: u64,
+}
+
+/// The bootloader's response to a `Request<KernelFileResponse>`.
+#[repr(C)]
+pub struct KernelFileResponse {
+    revision: u64,
+    kernel_file: *const File,
+}
+
+/// The bootloader's response to a `Request<ModuleResponse>`.
+#[repr(C)]
+pub struct ModuleResponse {
+    revision: u64,
+    module_count: u64,
+    modules: *const *const File,
+}
+
+/// A file loaded by the bootloader, such as a boot module.
+#[repr(C)]
+pub struct File {
+    revision: u64,
+    address: *const u8,
+    size: u64,
+    path: *const u8,
+    cmdline: *const u8,
+    media_type: u32,
+    _unused: u32,
+    tftp_ip: u32,
+    tftp_port: u32,
+    partition_index: u32,
+    mbr_disk_id: u32,
+    gpt_disk_uuid: [u8; 16],
+    gpt_part_uuid: [u8; 16],
+    part_uuid: [u8; 16],
+}
+
+/// The bootloader's response to a `Request<FramebufferResponse>`.
+#[repr(C)]
+pub struct FramebufferResponse {
+    revision: u64,
+    framebuffer_count: u64,
+    framebuffers: *const *const Framebuffer,
+}
+
+/// A framebuffer set up by the bootloader.
+#[derive(Debug)]
+#[repr(C)]
+pub struct Framebuffer {
+    address: *mut u8,
+    /// The width of the framebuffer in pixels.
+    pub width: u64,
+    /// The height of the framebuffer in pixels.
+    pub height: u64,
+    /// The number of bytes in each row of pixels.
+    pub pitch: u64,
+    /// The number of bits in each pixel.
+    pub bpp: u16,
+    /// The memory model of the framebuffer; 1 is RGB.
+    pub memory_model: u8,
+    pub red_mask_size: u8,
+    pub red_mask_shift: u8,
+    pub green_mask_size: u8,
+    pub green_mask_shift: u8,
+    pub blue_mask_size: u8,
+    pub blue_mask_shift: u8,
+    _unused: [u8; 7],
+    edid_size: u64,
+    edid: *const u8,
+}
+
+/// The bootloader's response to a `Request<RsdpResponse>`.
+#[repr(C)]
+pub struct RsdpResponse {
+    revision: u64,
+    address: *const u8,
+}
+
+/// The bootloader's response to a `Request<DtbResponse>`.
+#[repr(C)]
+pub struct DtbResponse {
+    revision: u64,
+    address: *const u8,
+}
+
+/// Boot parameters built from the bootloader's responses to Limine
+/// requests.
+///
+/// The memory map and kernel address responses are required; the others
+/// are optional, and are `None` if the kernel did not make the corresponding
+/// request or the bootloader did not answer it.
+#[derive(Copy, Clone)]
+pub struct LimineBootParams<'a> {
+    pub memmap: &'a MemmapResponse,
+    pub kernel_address: &'a KernelAddressResponse,
+    pub hhdm: Option<&'a HhdmResponse>,
+    pub modules: Option<&'a ModuleResponse>,
+    pub framebuffer: Option<&'a FramebufferResponse>,
+    pub rsdp: Option<&'a RsdpResponse>,
+    pub device_tree: Option<&'a DtbResponse>,
+    pub bootloader_info: Option<&'a BootloaderInfoResponse>,
+    pub kernel_file: Option<&'a KernelFileResponse>,
+}
+
+/// An iterator over the entries in the Limine memory map.
+#[derive(Clone, Debug)]
+pub struct MemoryMap<'a>(slice::Iter<'a, &'a MemmapEntry>);
+
+/// Returns the NUL-terminated string at `ptr`, if it is non-null and valid
+/// UTF-8.
+///
+/// # Unsafety
+///
+/// If `ptr` is non-null, it must point to a NUL-terminated string that lives
+/// for `'a`.
+unsafe fn c_str_ptr<'a>(ptr: *const u8) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    c_str(slice::from_raw_parts(ptr, len))
+}
+
+/// Returns the `len` pointers at `ptr` as a slice of references.
+///
+/// # Unsafety
+///
+/// If `ptr` is non-null, it must point to `len` valid pointers to `T` which
+/// live for `'a`.
+unsafe fn ptr_slice<'a, T>(ptr: *const *const T, len: u64) -> &'a [&'a T] {
+    if ptr.is_null() {
+        return &[];
+    }
+    slice::from_raw_parts(ptr as *const &T, len as usize)
+}
+
+// ===== impl Request =====
+
+impl<R: 'static> Request<R> {
+    /// Returns the bootloader's response to this request, if it answered it.
+    pub fn response(&self) -> Option<&'static R> {
+        // The bootloader writes the response pointer before the kernel runs,
+        // so the compiler can't see the write. Read it volatilely so that it
+        // isn't assumed to still be null.
+        let response = unsafe { ptr::read_volatile(self.response.get()) };
+        unsafe { response.as_ref() }
+    }
+}
+
+// Responses are only written by the bootloader, before the kernel runs, so
+// they can be shared once it does.
+unsafe impl<R: 'static> Sync for Request<R> {}
+
+macro_rules! requests {
+    ($($(#[$m:meta])* fn $name:ident() -> $resp:ty = [$a:expr, $b:expr];)+) => {
+        $(
+            impl Request<$resp> {
+                $(#[$m])*
+                pub const fn $name() -> Self {
+                    Request {
+                        // Every request ID starts with the same two words.
+                        id: [
+                            0xc7b1_dd30_df4c_8b88,
+                            0x0a82_e883_a194_f07b,
+                            $a,
+                            $b,
+                        ],
+                        revision: 0,
+                        response: UnsafeCell::new(ptr::null()),
+                    }
+                }
+            }
+        )+
+    }
+}
+
+requests! {
+    /// Returns a request for the bootloader's name and version.
+    fn bootloader_info() -> BootloaderInfoResponse =
+        [0xf550_38d8_e2a1_202f, 0x2794_26fc_f5f5_9740];
+    /// Returns a request for the higher-half direct map offset.
+    fn hhdm() -> HhdmResponse = [0x48dc_f1cb_8ad2_b852, 0x6398_4e95_9a98_244b];
+    /// Returns a request for the memory map.
+    fn memmap() -> MemmapResponse =
+        [0x67cf_3d9d_378a_806f, 0xe304_acdf_c50c_3c62];
+    /// Returns a request for the kernel's physical and virtual addresses.
+    fn kernel_address() -> KernelAddressResponse =
+        [0x71ba_7686_3cc5_5f63, 0xb264_4a48_c516_a487];
+    /// Returns a request for the kernel's executable file.
+    fn kernel_file() -> KernelFileResponse =
+        [0xad97_e90e_83f1_ed67, 0x31eb_5d1c_5ff2_3b69];
+    /// Returns a request for the boot modules.
+    fn modules() -> ModuleResponse =
+        [0x3e7e_2797_02be_32af, 0xca1c_4f3b_d128_0cee];
+    /// Returns a request for framebuffers.
+    fn framebuffer() -> FramebufferResponse =
+        [0x9d58_27dc_d881_dd75, 0xa314_8604_f6fa_b11b];
+    /// Returns a request for the ACPI RSDP.
+    fn rsdp() -> RsdpResponse = [0xc5e7_7b6b_397e_7b43, 0x2763_7845_accd_cf3c];
+    /// Returns a request for the flattened device tree.
+    fn device_tree() -> DtbResponse =
+        [0xb40d_db48_fb54_bac7, 0x5450_8149_3f81_ffb7];
+}
+
+// ===== impl BootloaderInfoResponse =====
+
+impl BootloaderInfoResponse {
+    /// Returns the bootloader's name.
+    pub fn name(&self) -> Option<&str> {
+        unsafe { c_str_ptr(self.name) }
+    }
+
+    /// Returns the bootloader's version.
+    pub fn version(&self) -> Option<&str> {
+        unsafe { c_str_ptr(self.version) }
+    }
+}
+
+// ===== impl HhdmResponse =====
+
+impl HhdmResponse {
+    /// Returns the virtual address at which the higher-half direct map
+    /// starts.
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+// ===== impl MemmapResponse =====
+
+impl MemmapResponse {
+    /// Returns the memory map entries.
+    pub fn entries(&self) -> &[&MemmapEntry] {
+        unsafe { ptr_slice(self.entries, self.entry_count) }
+    }
+}
+
+// ===== impl MemmapEntry =====
+
+impl MemmapEntry {
+    pub const USABLE: u64 = 0;
+    pub const RESERVED: u64 = 1;
+    pub const ACPI_RECLAIMABLE: u64 = 2;
+    pub const ACPI_NVS: u64 = 3;
+    pub const BAD_MEMORY: u64 = 4;
+    pub const BOOTLOADER_RECLAIMABLE: u64 = 5;
+    pub const KERNEL_AND_MODULES: u64 = 6;
+    pub const FRAMEBUFFER: u64 = 7;
+}
+
+impl Region for MemmapEntry {
+    type Addr = PAddr;
+
+    /// Returns the base address of the memory region.
+    fn base_address(&self) -> PAddr {
+        PAddr(self.base)
+    }
+
+    /// Returns the size in bytes of the memory region.
+    fn size(&self) -> usize {
+        self.length as usize
+    }
+
+    /// Returns the kind of memory in this region.
+    ///
+    /// Limine doesn't distinguish the kernel from the modules loaded
+    /// alongside it, so both are reported as `RegionKind::Kernel`, which is
+    /// never reclaimed.
+    fn kind(&self) -> RegionKind {
+        match self.kind {
+            MemmapEntry::USABLE => RegionKind::Usable,
+            MemmapEntry::ACPI_RECLAIMABLE => RegionKind::AcpiReclaimable,
+            MemmapEntry::ACPI_NVS => RegionKind::AcpiNvs,
+            MemmapEntry::BAD_MEMORY => RegionKind::BadMemory,
+            MemmapEntry::BOOTLOADER_RECLAIMABLE => {
+                RegionKind::BootloaderReclaimable
+            },
+            MemmapEntry::KERNEL_AND_MODULES => RegionKind::Kernel,
+            MemmapEntry::FRAMEBUFFER => RegionKind::Framebuffer,
+            _ => RegionKind::Unusable,
+        }
+    }
+}
+
+// ===== impl KernelAddressResponse =====
+
+impl KernelAddressResponse {
+    /// Returns the physical address the kernel was loaded at.
+    #[inline]
+    pub fn physical_base(&self) -> PAddr {
+        PAddr(self.physical_base)
+    }
+
+    /// Returns the virtual address the kernel was loaded at.
+    #[inline]
+    pub fn virtual_base(&self) -> u64 {
+        self.virtual_base
+    }
+}
+
+// ===== impl KernelFileResponse =====
+
+impl KernelFileResponse {
+    /// Returns the kernel's executable file.
+    pub fn file(&self) -> Option<&File> {
+        unsafe { self.kernel_file.as_ref() }
+    }
+}
+
+// ===== impl ModuleResponse =====
+
+impl ModuleResponse {
+    /// Returns the boot modules.
+    pub fn modules(&self) -> &[&File] {
+        unsafe { ptr_slice(self.modules, self.module_count) }
+    }
+}
+
+// ===== impl File =====
+
+impl File {
+    /// Returns the virtual address of the file's contents, in the
+    /// higher-half direct map.
+    #[inline]
+    pub fn address(&self) -> u64 {
+        self.address as u64
+    }
+
+    /// Returns the size of the file in bytes.
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the path the file was loaded from.
+    pub fn path(&self) -> &str {
+        unsafe { c_str_ptr(self.path) }.unwrap_or("")
+    }
+
+    /// Returns the file's command line.
+    pub fn cmdline(&self) -> &str {
+        unsafe { c_str_ptr(self.cmdline) }.unwrap_or("")
+    }
+}
+
+impl fmt::Debug for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("File")
+            .field("address", &self.address)
+            .field("size", &self.size)
+            .field("path", &self.path())
+            .field("cmdline", &self.cmdline())
+            .finish()
+    }
+}
+
+// ===== impl FramebufferResponse =====
+
+impl FramebufferResponse {
+    /// Returns the framebuffers.
+    pub fn framebuffers(&self) -> &[&Framebuffer] {
+        unsafe { ptr_slice(self.framebuffers, self.framebuffer_count) }
+    }
+}
+
+// ===== impl Framebuffer =====
+
+impl Framebuffer {
+    /// Returns the virtual address of the framebuffer, in the higher-half
+    /// direct map.
+    #[inline]
+    pub fn address(&self) -> u64 {
+        self.address as u64
+    }
+}
+
+// ===== impl RsdpResponse =====
+
+impl RsdpResponse {
+    /// Returns the address of the RSDP.
+    ///
+    /// Depending on the protocol revision, this is either a physical address
+    /// or a virtual address in the higher-half direct map.
+    #[inline]
+    pub fn address(&self) -> u64 {
+        self.address as u64
+    }
+}
+
+// ===== impl DtbResponse =====
+
+impl DtbResponse {
+    /// Returns the virtual address of the device tree blob, in the
+    /// higher-half direct map.
+    #[inline]
+    pub fn address(&self) -> u64 {
+        self.address as u64
+    }
+}
+
+// ===== impl LimineBootParams =====
+
+impl<'a> LimineBootParams<'a> {
+    /// Returns boot parameters with the required responses, and none of the
+    /// optional ones.
+    pub fn new(
+        memmap: &'a MemmapResponse,
+        kernel_address: &'a KernelAddressResponse,
+    ) -> Self {
+        LimineBootParams {
+            memmap,
+            kernel_address,
+            hhdm: None,
+            modules: None,
+            framebuffer: None,
+            rsdp: None,
+            device_tree: None,
+            bootloader_info: None,
+            kernel_file: None,
+        }
+    }
+
+    /// Returns the offset of the higher-half direct map, if the bootloader
+    /// provided one.
+    pub fn hhdm_offset(&self) -> Option<u64> {
+        self.hhdm.map(HhdmResponse::offset)
+    }
+
+    /// Returns the files loaded as boot modules.
+    ///
+    /// `BootParams::modules` returns the same modules in an
+    /// architecture-independent form.
+    pub fn module_files(&self) -> &'a [&'a File] {
+        self.modules.map(ModuleResponse::modules).unwrap_or(&[])
+    }
+
+    /// Returns the framebuffers.
+    pub fn framebuffers(&self) -> &'a [&'a Framebuffer] {
+        self.framebuffer
+            .map(FramebufferResponse::framebuffers)
+            .unwrap_or(&[])
+    }
+
+    /// Translates an address which the bootloader may have reported in the
+    /// higher-half direct map back into a physical address.
+    ///
+    /// Addresses below the start of the direct map are assumed to be
+    /// physical already.
+    fn to_physical(&self, address: u64) -> PAddr {
+        match self.hhdm_offset() {
+            Some(offset) if address >= offset => PAddr(address - offset),
+            _ => PAddr(address),
+        }
+    }
+}
+
+impl<'a> BootParams for LimineBootParams<'a> {
+    type Arch = X86_64;
+    type MemRegion = MemmapEntry;
+    type MemMap = MemoryMap<'a>;
+
+    /// Returns the base address of the kernel memory region.
+    fn kernel_base(&self) -> PAddr {
+        self.kernel_address.physical_base()
+    }
+
+    /// Returns the end address of the kernel memory region.
+    ///
+    /// Limine doesn't report the size of the loaded kernel, so this is the
+    /// end of the kernel-and-modules memory map entry that the kernel was
+    /// loaded into.
+    fn kernel_end(&self) -> PAddr {
+        let base = self.kernel_base();
+        self.memmap
+            .entries()
+            .iter()
+            .find(|entry| {
+                entry.kind == MemmapEntry::KERNEL_AND_MODULES
+                    && entry.base_address() <= base
+                    && base < entry.end_address()
+            })
+            .map(|entry| entry.end_address())
+            .unwrap_or(base)
+    }
+
+    /// Returns the memory map.
+    fn mem_map(&self) -> Self::MemMap {
+        MemoryMap(self.memmap.entries().iter())
+    }
+
+    /// Returns the range of frames containing the kernel binary.
+    fn kernel_frames(&self) -> page::Range<PhysicalPage> {
+        page::Range::containing(self.kernel_base()..self.kernel_end())
+    }
+
+    /// Returns the kernel command line.
+    ///
+    /// Limine passes this as the command line of the kernel's executable
+    /// file, so it is only available if the kernel file was requested.
+    fn command_line(&self) -> Option<&str> {
+        let file = self.kernel_file?.file()?;
+        unsafe { c_str_ptr(file.cmdline) }
+    }
+
+    fn module(&self, index: usize) -> Option<params::Module<'_, PAddr>> {
+        let module = self.module_files().get(index)?;
+        let start = self.to_physical(module.address());
+        let end = start.checked_add(module.size() as usize)?;
+        Some(params::Module {
+            range: start..end,
+            cmdline: module.cmdline(),
+        })
+    }
+
+    fn framebuffer(&self) -> Option<params::Framebuffer<PAddr>> {
+        let fb = self.framebuffers().first()?;
+        let mask = |size, shift| ColorMask { size, shift };
+        let format = match fb.memory_model {
+            1 => PixelFormat::Rgb {
+                red: mask(fb.red_mask_size, fb.red_mask_shift),
+                green: mask(fb.green_mask_size, fb.green_mask_shift),
+                blue: mask(fb.blue_mask_size, fb.blue_mask_shift),
+            },
+            _ => PixelFormat::Unknown,
+        };
+        Some(params::Framebuffer {
+            address: self.to_physical(fb.address()),
+            width: fb.width as u32,
+            height: fb.height as u32,
+            pitch: fb.pitch as u32,
+            bpp: fb.bpp as u8,
+            format,
+        })
+    }
+
+    fn rsdp(&self) -> Option<PAddr> {
+        self.rsdp.map(|rsdp| self.to_physical(rsdp.address()))
+    }
+
+    fn device_tree(&self) -> Option<PAddr> {
+        self.device_tree.map(|dtb| self.to_physical(dtb.address()))
+    }
+
+    fn bootloader(&self) -> Option<&dyn BootloaderInfo> {
+        Some(self)
+    }
+}
+
+impl<'a> BootloaderInfo for LimineBootParams<'a> {
+    fn name(&self) -> &str {
+        self.bootloader_info
+            .and_then(BootloaderInfoResponse::name)
+            .unwrap_or("limine")
+    }
+
+    fn version(&self) -> Option<&str> {
+        self.bootloader_info
+            .and_then(BootloaderInfoResponse::version)
+    }
+}
+
+// ===== impl MemoryMap =====
+
+impl<'a> Iterator for MemoryMap<'a> {
+    type Item = MemmapEntry;
+
+    fn next(&mut self) -> Option<MemmapEntry> {
+        self.0.next().map(|&entry| *entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::{boxed::Box, vec::Vec};
+    use super::*;
+    use hal9000::mem::Page;
+
+    const HHDM: u64 = 0xffff_8000_0000_0000;
+
+    fn leak<T: 'static>(value: T) -> &'static T {
+        Box::leak(Box::new(value))
+    }
+
+    fn leak_ptrs<T: 'static>(values: Vec<T>) -> *const *const T {
+        let ptrs: Vec<*const T> =
+            values.into_iter().map(|v| leak(v) as *const T).collect();
+        Box::leak(ptrs.into_boxed_slice()).as_ptr()
+    }
+
+    fn memmap(entries: &[(u64, u64, u64)]) -> &'static MemmapResponse {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|&(base, length, kind)| MemmapEntry { base, length, kind })
+            .collect();
+        leak(MemmapResponse {
+            revision: 0,
+            entry_count: entries.len() as u64,
+            entries: leak_ptrs(entries),
+        })
+    }
+
+    fn vec_of<T>(value: T) -> Vec<T> {
+        let mut vec = Vec::new();
+        vec.push(value);
+        vec
+    }
+
+    fn module(path: &'static [u8], cmdline: &'static [u8]) -> File {
+        File {
+            revision: 0,
+            address: (HHDM + 0x20_0000) as *const u8,
+            size: 0x1000,
+            path: path.as_ptr(),
+            cmdline: cmdline.as_ptr(),
+            media_type: 0,
+            _unused: 0,
+            tftp_ip: 0,
+            tftp_port: 0,
+            partition_index: 0,
+            mbr_disk_id: 0,
+            gpt_disk_uuid: [0; 16],
+            gpt_part_uuid: [0; 16],
+            part_uuid: [0; 16],
+        }
+    }
+
+    fn params() -> LimineBootParams<'static> {
+        let memmap = memmap(&[
+            (0, 0x9f000, MemmapEntry::USABLE),
+            (0x9f000, 0x1000, MemmapEntry::RESERVED),
+            (0x10_0000, 0x4_3000, MemmapEntry::KERNEL_AND_MODULES),
+            (0x14_3000, 0x1_0000, MemmapEntry::BOOTLOADER_RECLAIMABLE),
+            (0x15_3000, 0x7e_ad00, MemmapEntry::USABLE),
+            (0xfd00_0000, 0x30_0000, MemmapEntry::FRAMEBUFFER),
+        ]);
+        let kernel_address = leak(KernelAddressResponse {
+            revision: 0,
+            physical_base: 0x10_0000,
+            virtual_base: 0xffff_ffff_8000_0000,
+        });
+        let mut params = LimineBootParams::new(memmap, kernel_address);
+        params.hhdm = Some(leak(HhdmResponse {
+            revision: 0,
+            offset: HHDM,
+        }));
+        params.modules = Some(leak(ModuleResponse {
+            revision: 0,
+            module_count: 1,
+            modules: leak_ptrs(vec_of(module(b"/boot/initrd\0", b"ro\0"))),
+        }));
+        params.rsdp = Some(leak(RsdpResponse {
+            revision: 0,
+            address: (HHDM + 0xe_0000) as *const u8,
+        }));
+        params.bootloader_info = Some(leak(BootloaderInfoResponse {
+            revision: 0,
+            name: b"Limine\0".as_ptr(),
+            version: b"7.0.0\0".as_ptr(),
+        }));
+        params
+    }
+
+    #[test]
+    fn request_ids() {
+        let request = Request::<MemmapResponse>::memmap();
+        assert_eq!(request.id[0], 0xc7b1_dd30_df4c_8b88);
+        assert_eq!(request.id[3], 0xe304_acdf_c50c_3c62);
+        assert!(request.response().is_none());
+    }
+
+    #[test]
+    fn memory_map() {
+        let params = params();
+        let kinds: Vec<_> = params.mem_map().map(|e| e.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                RegionKind::Usable,
+                RegionKind::Unusable,
+                RegionKind::Kernel,
+                RegionKind::BootloaderReclaimable,
+                RegionKind::Usable,
+                RegionKind::Framebuffer,
+            ]
+        );
+    }
+
+    #[test]
+    fn kernel_region() {
+        let params = params();
+        assert_eq!(params.kernel_base(), PAddr(0x10_0000));
+        assert_eq!(params.kernel_end(), PAddr(0x14_3000));
+        let frames = params.kernel_frames();
+        assert_eq!(frames.len(), 0x43);
+        assert_eq!(frames.start().base_address(), PAddr(0x10_0000));
+    }
+
+    #[test]
+    fn optional_responses() {
+        let params = params();
+        assert_eq!(params.hhdm_offset(), Some(HHDM));
+        assert_eq!(params.rsdp(), Some(PAddr(0xe_0000)));
+        assert!(params.framebuffers().is_empty());
+
+        let modules = params.module_files();
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].path(), "/boot/initrd");
+        assert_eq!(modules[0].cmdline(), "ro");
+        assert_eq!(BootloaderInfo::name(&params), "Limine");
+
+        let bare = LimineBootParams::new(params.memmap, params.kernel_address);
+        assert!(bare.module_files().is_empty());
+        assert_eq!(bare.rsdp(), None);
+        assert_eq!(BootloaderInfo::name(&bare), "limine");
+    }
+
+    #[test]
+    fn boot_params_extras() {
+        let mut params = params();
+        assert_eq!(params.command_line(), None);
+        assert_eq!(params.device_tree(), None);
+        params.kernel_file = Some(leak(KernelFileResponse {
+            revision: 0,
+            kernel_file: leak(module(b"/boot/kernel\0", b"quiet\0")),
+        }));
+        params.framebuffer = Some(leak(FramebufferResponse {
+            revision: 0,
+            framebuffer_count: 1,
+            framebuffers: leak_ptrs(vec_of(Framebuffer {
+                address: (HHDM + 0xfd00_0000) as *mut u8,
+                width: 1280,
+                height: 800,
+                pitch: 5120,
+                bpp: 32,
+                memory_model: 1,
+                red_mask_size: 8,
+                red_mask_shift: 16,
+                green_mask_size: 8,
+                green_mask_shift: 8,
+                blue_mask_size: 8,
+                blue_mask_shift: 0,
+                _unused: [0; 7],
+                edid_size: 0,
+                edid: ptr::null(),
+            })),
+        }));
+
+        params.device_tree = Some(leak(DtbResponse {
+            revision: 0,
+            address: (HHDM + 0x7fe_0000) as *const u8,
+        }));
+
+        assert_eq!(params.command_line(), Some("quiet"));
+        assert_eq!(params.initrd(), Some(PAddr(0x20_0000)..PAddr(0x20_1000)));
+        assert_eq!(params.modules().next().unwrap().cmdline, "ro");
+        assert_eq!(params.device_tree(), Some(PAddr(0x7fe_0000)));
+
+        let fb = params.framebuffer().unwrap();
+        assert_eq!(fb.address, PAddr(0xfd00_0000));
+        assert_eq!(
+            (fb.width, fb.height, fb.pitch, fb.bpp),
+            (1280, 800, 5120, 32)
+        );
+        match fb.format {
+            PixelFormat::Rgb { red, .. } => {
+                assert_eq!(red, ColorMask { size: 8, shift: 16 })
+            },
+            format => panic!("unexpected pixel format {:?}", format),
+        }
+
+        let bootloader = params.bootloader().unwrap();
+        assert_eq!(bootloader.name(), "Limine");
+        assert_eq!(bootloader.version(), Some("7.0.0"));
+    }
+
+    #[test]
+    fn module_past_end_of_memory() {
+        let mut params = params();
+        let mut file = module(b"/boot/initrd\0", b"\0");
+        file.size = ::core::u64::MAX;
+        params.modules = Some(leak(ModuleResponse {
+            revision: 0,
+            module_count: 1,
+            modules: leak_ptrs(vec_of(file)),
+        }));
+        assert!(params.module(0).is_none());
+    }
+}
@@ -0,0 +1,55 @@
+//! Boot protocols.
+//!
+//! Each submodule parses the information handed to the kernel by a
+//! particular bootloader or firmware interface, and exposes it through
+//! `hal9000::params::BootParams`.
+pub mod e820;
+pub mod limine;
+pub mod multiboot2;
+
+/// Reads a little-endian `u16` from `bytes` at `offset`.
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    let b = bytes.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from(b[0]) | u16::from(b[1]) << 8)
+}
+
+/// Reads a little-endian `u32` from `bytes` at `offset`.
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let lo = read_u16(bytes, offset)?;
+    let hi = read_u16(bytes, offset.checked_add(2)?)?;
+    Some(u32::from(lo) | u32::from(hi) << 16)
+}
+
+/// Reads a little-endian `u64` from `bytes` at `offset`.
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let lo = read_u32(bytes, offset)?;
+    let hi = read_u32(bytes, offset.checked_add(4)?)?;
+    Some(u64::from(lo) | u64::from(hi) << 32)
+}
+
+/// Returns the string in `bytes` up to the first NUL byte, if it is valid
+/// UTF-8.
+pub(crate) fn c_str(bytes: &[u8]) -> Option<&str> {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    ::core::str::from_utf8(&bytes[..len]).ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use self::std::vec::Vec;
+
+    /// Appends `n` to `buf` in little-endian byte order.
+    pub(crate) fn push_u32(buf: &mut Vec<u8>, n: u32) {
+        for i in 0..4 {
+            buf.push((n >> (i * 8)) as u8);
+        }
+    }
+
+    /// Appends `n` to `buf` in little-endian byte order.
+    pub(crate) fn push_u64(buf: &mut Vec<u8>, n: u64) {
+        push_u32(buf, n as u32);
+        push_u32(buf, (n >> 32) as u32);
+    }
+}
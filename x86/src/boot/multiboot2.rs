@@ -0,0 +1,698 @@
+//! Multiboot2 boot information.
+//!
+//! A Multiboot2-compliant bootloader (such as GRUB 2) passes the kernel the
+//! physical address of a boot information structure in `ebx`. The structure
+//! is a `u32` total size and a reserved `u32`, followed by a list of tags,
+//! each of which is 8-byte aligned and starts with a `u32` type and a `u32`
+//! size. The list is terminated by a tag of type 0.
+//!
+//! `BootInfo` parses this structure from a byte slice, so it can be tested
+//! against byte blobs on the host as well as used in the kernel.
+use super::{c_str, read_u32, read_u64};
+use crate::x64::{PAddr, PhysicalPage, X86_64};
+use hal9000::{
+    mem::{
+        map::{Region, RegionKind},
+        page, Address,
+    },
+    params::{
+        self, BootParams, BootloaderInfo, ColorMask, Framebuffer, PixelFormat,
+    },
+    util::Align,
+};
+
+/// Tag type of the boot command line.
+pub const TAG_CMDLINE: u32 = 1;
+/// Tag type of the bootloader name.
+pub const TAG_BOOTLOADER_NAME: u32 = 2;
+/// Tag type of a boot module.
+pub const TAG_MODULE: u32 = 3;
+/// Tag type of the memory map.
+pub const TAG_MMAP: u32 = 6;
+/// Tag type of the kernel's ELF section headers.
+pub const TAG_ELF_SECTIONS: u32 = 9;
+/// Tag type of the framebuffer description.
+pub const TAG_FRAMEBUFFER: u32 = 8;
+/// Tag type of a copy of the ACPI 1.0 RSDP.
+pub const TAG_ACPI_OLD: u32 = 14;
+/// Tag type of a copy of the ACPI 2.0+ RSDP.
+pub const TAG_ACPI_NEW: u32 = 15;
+
+const TAG_END: u32 = 0;
+
+/// Section flag indicating that an ELF section occupies memory.
+const SHF_ALLOC: u64 = 0x2;
+
+/// Parsed Multiboot2 boot information.
+#[derive(Copy, Clone, Debug)]
+pub struct BootInfo<'a> {
+    bytes: &'a [u8],
+    /// The physical address of the boot information, if it is known.
+    paddr: Option<PAddr>,
+}
+
+/// Errors returned when parsing Multiboot2 boot information.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The boot information is shorter than its fixed-size header.
+    TooShort,
+    /// The total size in the header is larger than the boot information
+    /// provided, or too small to hold the header.
+    BadSize,
+}
+
+/// A tag in the boot information structure.
+#[derive(Copy, Clone, Debug)]
+pub struct Tag<'a> {
+    /// The tag's type.
+    pub kind: u32,
+    /// The tag's contents, not including the type and size fields.
+    pub data: &'a [u8],
+}
+
+/// An iterator over the tags in the boot information structure.
+#[derive(Clone, Debug)]
+pub struct Tags<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+/// A boot module loaded by the bootloader.
+#[derive(Copy, Clone, Debug)]
+pub struct Module<'a> {
+    /// The physical address where the module starts.
+    pub start: PAddr,
+    /// The physical address where the module ends.
+    pub end: PAddr,
+    /// The module's command line.
+    pub cmdline: &'a str,
+}
+
+/// An iterator over the boot modules.
+#[derive(Clone, Debug)]
+pub struct Modules<'a>(Tags<'a>);
+
+/// An entry in the Multiboot2 memory map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryArea {
+    base: u64,
+    len: u64,
+    kind: u32,
+}
+
+/// An iterator over the entries in the Multiboot2 memory map.
+#[derive(Clone, Debug)]
+pub struct MemoryAreas<'a> {
+    entries: &'a [u8],
+    entry_size: usize,
+}
+
+/// An ELF section header from the kernel image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ElfSection {
+    /// The section type.
+    pub kind: u32,
+    /// The section flags.
+    pub flags: u64,
+    /// The address of the section in memory.
+    pub addr: u64,
+    /// The size of the section in bytes.
+    pub size: u64,
+}
+
+/// An iterator over the kernel's ELF section headers.
+#[derive(Clone, Debug)]
+pub struct ElfSections<'a> {
+    headers: &'a [u8],
+    entry_size: usize,
+}
+
+// ===== impl BootInfo =====
+
+impl<'a> BootInfo<'a> {
+    /// Parses the boot information structure at the start of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        let size = read_u32(bytes, 0).ok_or(Error::TooShort)? as usize;
+        if size < 8 || size > bytes.len() {
+            return Err(Error::BadSize);
+        }
+        Ok(BootInfo {
+            bytes: &bytes[..size],
+            paddr: None,
+        })
+    }
+
+    /// Parses the boot information structure at `addr`.
+    ///
+    /// # Unsafety
+    ///
+    /// `addr` must point to a valid Multiboot2 boot information structure,
+    /// such as the one whose address the bootloader passes in `ebx`, and the
+    /// structure must not be overwritten while the returned `BootInfo`
+    /// exists. `addr` must also be the structure's physical address, as it is
+    /// when the bootloader jumps to the kernel with paging disabled or
+    /// identity-mapped.
+    pub unsafe fn from_addr(addr: usize) -> Result<Self, Error> {
+        let size = *(addr as *const u32) as usize;
+        let bytes = ::core::slice::from_raw_parts(addr as *const u8, size);
+        let mut info = Self::new(bytes)?;
+        info.paddr = Some(PAddr(addr as u64));
+        Ok(info)
+    }
+
+    /// Returns an iterator over every tag in the boot information.
+    pub fn tags(&self) -> Tags<'a> {
+        Tags {
+            bytes: self.bytes,
+            offset: 8,
+        }
+    }
+
+    /// Returns the first tag of the given type, if there is one.
+    pub fn tag(&self, kind: u32) -> Option<Tag<'a>> {
+        self.tags().find(|tag| tag.kind == kind)
+    }
+
+    /// Returns the name of the bootloader, if it provided one.
+    pub fn bootloader_name(&self) -> Option<&'a str> {
+        self.tag(TAG_BOOTLOADER_NAME)
+            .and_then(|tag| c_str(tag.data))
+    }
+
+    /// Returns an iterator over the module tags.
+    ///
+    /// `BootParams::modules` returns the same modules in an
+    /// architecture-independent form.
+    pub fn module_tags(&self) -> Modules<'a> {
+        Modules(self.tags())
+    }
+
+    /// Returns the framebuffer described by the framebuffer tag, if there
+    /// is one.
+    pub fn framebuffer_info(&self) -> Option<Framebuffer<PAddr>> {
+        let data = self.tag(TAG_FRAMEBUFFER)?.data;
+        let byte = |offset: usize| data.get(offset).cloned();
+        let mask = |offset: usize| {
+            Some(ColorMask {
+                shift: byte(offset)?,
+                size: byte(offset + 1)?,
+            })
+        };
+        let format = match byte(21)? {
+            0 => PixelFormat::Indexed,
+            1 => PixelFormat::Rgb {
+                red: mask(24)?,
+                green: mask(26)?,
+                blue: mask(28)?,
+            },
+            2 => PixelFormat::Text,
+            _ => PixelFormat::Unknown,
+        };
+        Some(Framebuffer {
+            address: PAddr(read_u64(data, 0)?),
+            pitch: read_u32(data, 8)?,
+            width: read_u32(data, 12)?,
+            height: read_u32(data, 16)?,
+            bpp: byte(20)?,
+            format,
+        })
+    }
+
+    /// Returns the physical address of the bootloader's copy of the ACPI
+    /// RSDP, preferring the ACPI 2.0+ version if both are present.
+    ///
+    /// Multiboot2 copies the RSDP into the boot information rather than
+    /// giving its address, so this is only known if the `BootInfo` was
+    /// created with `from_addr`.
+    pub fn rsdp_addr(&self) -> Option<PAddr> {
+        let tag = self.tag(TAG_ACPI_NEW).or_else(|| self.tag(TAG_ACPI_OLD))?;
+        let offset = tag.data.as_ptr() as usize - self.bytes.as_ptr() as usize;
+        self.paddr?.checked_add(offset)
+    }
+
+    /// Returns an iterator over the memory map.
+    ///
+    /// If the bootloader did not provide a memory map, the iterator is empty.
+    pub fn memory_areas(&self) -> MemoryAreas<'a> {
+        let empty = MemoryAreas {
+            entries: &[],
+            entry_size: 24,
+        };
+        let tag = match self.tag(TAG_MMAP) {
+            Some(tag) => tag,
+            None => return empty,
+        };
+        match (read_u32(tag.data, 0), tag.data.get(8..)) {
+            // Each entry is at least a `u64` base, a `u64` length and a
+            // `u32` type.
+            (Some(entry_size), Some(entries)) if entry_size >= 20 => {
+                MemoryAreas {
+                    entries,
+                    entry_size: entry_size as usize,
+                }
+            },
+            _ => empty,
+        }
+    }
+
+    /// Returns an iterator over the kernel's ELF section headers, if the
+    /// bootloader provided them.
+    pub fn elf_sections(&self) -> Option<ElfSections<'a>> {
+        let tag = self.tag(TAG_ELF_SECTIONS)?;
+        // The spec describes these as `u16`s, but GRUB (and everything else
+        // in practice) uses `u32`s.
+        let num = read_u32(tag.data, 0)? as usize;
+        let entry_size = read_u32(tag.data, 4)? as usize;
+        if entry_size < ElfSections::ELF32_SIZE {
+            return None;
+        }
+        let len = num.checked_mul(entry_size)?;
+        let headers = tag.data.get(12..12usize.checked_add(len)?)?;
+        Some(ElfSections {
+            headers,
+            entry_size,
+        })
+    }
+
+    /// Returns the lowest and highest addresses occupied by the kernel's
+    /// allocated ELF sections.
+    ///
+    /// These are the addresses in the section headers, so this assumes the
+    /// kernel is linked at the physical address it is loaded at.
+    fn kernel_bounds(&self) -> (u64, u64) {
+        let mut sections = match self.elf_sections() {
+            Some(sections) => sections.filter(ElfSection::is_allocated),
+            None => return (0, 0),
+        };
+        let first = match sections.next() {
+            Some(section) => section,
+            None => return (0, 0),
+        };
+        sections.fold((first.addr, first.end()), |(base, end), section| {
+            let section_end = section.end();
+            (
+                if section.addr < base {
+                    section.addr
+                } else {
+                    base
+                },
+                if section_end > end { section_end } else { end },
+            )
+        })
+    }
+}
+
+impl<'a> BootParams for BootInfo<'a> {
+    type Arch = X86_64;
+    type MemRegion = MemoryArea;
+    type MemMap = MemoryAreas<'a>;
+
+    /// Returns the base address of the kernel memory region.
+    ///
+    /// This is the lowest address of an allocated section in the ELF
+    /// section headers tag, or 0 if the bootloader didn't provide one.
+    fn kernel_base(&self) -> PAddr {
+        PAddr(self.kernel_bounds().0)
+    }
+
+    /// Returns the end address of the kernel memory region.
+    fn kernel_end(&self) -> PAddr {
+        PAddr(self.kernel_bounds().1)
+    }
+
+    /// Returns the memory map.
+    fn mem_map(&self) -> Self::MemMap {
+        self.memory_areas()
+    }
+
+    /// Returns the range of frames containing the kernel binary.
+    fn kernel_frames(&self) -> page::Range<PhysicalPage> {
+        let (base, end) = self.kernel_bounds();
+        page::Range::containing(PAddr(base)..PAddr(end))
+    }
+
+    fn command_line(&self) -> Option<&str> {
+        self.tag(TAG_CMDLINE).and_then(|tag| c_str(tag.data))
+    }
+
+    fn module(&self, index: usize) -> Option<params::Module<'_, PAddr>> {
+        self.module_tags().nth(index).map(|module| params::Module {
+            range: module.start..module.end,
+            cmdline: module.cmdline,
+        })
+    }
+
+    fn framebuffer(&self) -> Option<Framebuffer<PAddr>> {
+        self.framebuffer_info()
+    }
+
+    fn rsdp(&self) -> Option<PAddr> {
+        self.rsdp_addr()
+    }
+
+    fn bootloader(&self) -> Option<&dyn BootloaderInfo> {
+        Some(self)
+    }
+}
+
+impl<'a> BootloaderInfo for BootInfo<'a> {
+    fn name(&self) -> &str {
+        self.bootloader_name().unwrap_or("multiboot2")
+    }
+}
+
+// ===== impl Tags =====
+
+impl<'a> Iterator for Tags<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Tag<'a>> {
+        let kind = read_u32(self.bytes, self.offset)?;
+        let size = read_u32(self.bytes, self.offset + 4)? as usize;
+        let end = self.offset.checked_add(size)?;
+        if kind == TAG_END || size < 8 || end > self.bytes.len() {
+            // Don't keep walking a malformed list.
+            self.offset = self.bytes.len();
+            return None;
+        }
+        let data = &self.bytes[self.offset + 8..end];
+        self.offset = end.align_up(8);
+        Some(Tag { kind, data })
+    }
+}
+
+// ===== impl Modules =====
+
+impl<'a> Iterator for Modules<'a> {
+    type Item = Module<'a>;
+
+    fn next(&mut self) -> Option<Module<'a>> {
+        loop {
+            let tag = self.0.next()?;
+            if tag.kind != TAG_MODULE {
+                continue;
+            }
+            let start = read_u32(tag.data, 0)?;
+            let end = read_u32(tag.data, 4)?;
+            return Some(Module {
+                start: PAddr(u64::from(start)),
+                end: PAddr(u64::from(end)),
+                cmdline: tag.data.get(8..).and_then(c_str).unwrap_or(""),
+            });
+        }
+    }
+}
+
+// ===== impl MemoryArea =====
+
+impl MemoryArea {
+    /// Returns the raw Multiboot2 type of this memory area.
+    #[inline]
+    pub fn raw_kind(&self) -> u32 {
+        self.kind
+    }
+}
+
+impl Region for MemoryArea {
+    type Addr = PAddr;
+
+    /// Returns the base address of the memory region.
+    fn base_address(&self) -> PAddr {
+        PAddr(self.base)
+    }
+
+    /// Returns the size in bytes of the memory region.
+    fn size(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns the kind of memory in this region.
+    fn kind(&self) -> RegionKind {
+        match self.kind {
+            1 => RegionKind::Usable,
+            3 => RegionKind::AcpiReclaimable,
+            4 => RegionKind::AcpiNvs,
+            5 => RegionKind::BadMemory,
+            _ => RegionKind::Unusable,
+        }
+    }
+}
+
+impl<'a> Iterator for MemoryAreas<'a> {
+    type Item = MemoryArea;
+
+    fn next(&mut self) -> Option<MemoryArea> {
+        if self.entries.len() < self.entry_size {
+            return None;
+        }
+        let area = MemoryArea {
+            base: read_u64(self.entries, 0)?,
+            len: read_u64(self.entries, 8)?,
+            kind: read_u32(self.entries, 16)?,
+        };
+        self.entries = &self.entries[self.entry_size..];
+        Some(area)
+    }
+}
+
+// ===== impl ElfSection =====
+
+impl ElfSection {
+    /// Returns `true` if this section occupies memory at runtime.
+    pub fn is_allocated(&self) -> bool {
+        self.flags & SHF_ALLOC != 0 && self.size != 0
+    }
+
+    /// Returns the address one past the end of this section.
+    pub fn end(&self) -> u64 {
+        self.addr.saturating_add(self.size)
+    }
+}
+
+impl<'a> ElfSections<'a> {
+    const ELF32_SIZE: usize = 40;
+    const ELF64_SIZE: usize = 64;
+}
+
+impl<'a> Iterator for ElfSections<'a> {
+    type Item = ElfSection;
+
+    fn next(&mut self) -> Option<ElfSection> {
+        if self.headers.len() < self.entry_size {
+            return None;
+        }
+        let h = self.headers;
+        let section = if self.entry_size >= Self::ELF64_SIZE {
+            ElfSection {
+                kind: read_u32(h, 4)?,
+                flags: read_u64(h, 8)?,
+                addr: read_u64(h, 16)?,
+                size: read_u64(h, 32)?,
+            }
+        } else {
+            ElfSection {
+                kind: read_u32(h, 4)?,
+                flags: u64::from(read_u32(h, 8)?),
+                addr: u64::from(read_u32(h, 12)?),
+                size: u64::from(read_u32(h, 20)?),
+            }
+        };
+        self.headers = &self.headers[self.entry_size..];
+        Some(section)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::{boxed::Box, vec::Vec};
+    use super::*;
+    use crate::boot::tests::{push_u32, push_u64};
+    use hal9000::mem::Page;
+
+    fn push_tag(buf: &mut Vec<u8>, kind: u32, data: &[u8]) {
+        push_u32(buf, kind);
+        push_u32(buf, 8 + data.len() as u32);
+        buf.extend_from_slice(data);
+        while buf.len() % 8 != 0 {
+            buf.push(0);
+        }
+    }
+
+    /// Builds a boot information structure like the one GRUB gives a kernel
+    /// loaded at 1 MiB.
+    fn boot_info() -> Vec<u8> {
+        let mut buf = Vec::new();
+        push_u32(&mut buf, 0); // total size; filled in below
+        push_u32(&mut buf, 0);
+
+        push_tag(&mut buf, TAG_CMDLINE, b"console=ttyS0 quiet\0");
+        push_tag(&mut buf, TAG_BOOTLOADER_NAME, b"GRUB 2.06\0");
+
+        let mut module = Vec::new();
+        push_u32(&mut module, 0x20_0000);
+        push_u32(&mut module, 0x21_0000);
+        module.extend_from_slice(b"initrd\0");
+        push_tag(&mut buf, TAG_MODULE, &module);
+
+        let mut mmap = Vec::new();
+        push_u32(&mut mmap, 24);
+        push_u32(&mut mmap, 0);
+        for &(base, len, kind) in &[
+            (0u64, 0x9fc00u64, 1u32),
+            (0x9fc00, 0x400, 2),
+            (0x10_0000, 0x7ee_0000, 1),
+            (0x7fe_0000, 0x2_0000, 3),
+        ] {
+            push_u64(&mut mmap, base);
+            push_u64(&mut mmap, len);
+            push_u32(&mut mmap, kind);
+            push_u32(&mut mmap, 0);
+        }
+        push_tag(&mut buf, TAG_MMAP, &mmap);
+
+        let mut elf = Vec::new();
+        push_u32(&mut elf, 3);
+        push_u32(&mut elf, 64);
+        push_u32(&mut elf, 0);
+        for &(flags, addr, size) in &[
+            (0u64, 0u64, 0u64),
+            (0x6, 0x10_0000, 0x5000),
+            (0x3, 0x10_5000, 0x1800),
+        ] {
+            push_u32(&mut elf, 0); // name
+            push_u32(&mut elf, 1); // type
+            push_u64(&mut elf, flags);
+            push_u64(&mut elf, addr);
+            push_u64(&mut elf, 0); // offset
+            push_u64(&mut elf, size);
+            elf.extend_from_slice(&[0; 24]);
+        }
+        push_tag(&mut buf, TAG_ELF_SECTIONS, &elf);
+
+        let mut fb = Vec::new();
+        push_u64(&mut fb, 0xfd00_0000);
+        push_u32(&mut fb, 4096);
+        push_u32(&mut fb, 1024);
+        push_u32(&mut fb, 768);
+        fb.extend_from_slice(&[32, 1, 0, 0, 16, 8, 8, 8, 0, 8]);
+        push_tag(&mut buf, TAG_FRAMEBUFFER, &fb);
+
+        push_tag(&mut buf, TAG_ACPI_OLD, b"RSD PTR \0\0\0\0\0\0\0\0\0\0\0\0");
+
+        push_tag(&mut buf, TAG_END, &[]);
+        let len = buf.len() as u32;
+        for (i, b) in buf[..4].iter_mut().enumerate() {
+            *b = (len >> (i * 8)) as u8;
+        }
+        buf
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        assert_eq!(BootInfo::new(&[1, 0]).unwrap_err(), Error::TooShort);
+        let bytes = [0x10, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(BootInfo::new(&bytes).unwrap_err(), Error::BadSize);
+    }
+
+    #[test]
+    fn strings_and_modules() {
+        let bytes = boot_info();
+        let info = BootInfo::new(&bytes).unwrap();
+        assert_eq!(info.command_line(), Some("console=ttyS0 quiet"));
+        assert_eq!(info.bootloader_name(), Some("GRUB 2.06"));
+
+        let mut modules = info.module_tags();
+        let module = modules.next().unwrap();
+        assert_eq!(module.start, PAddr(0x20_0000));
+        assert_eq!(module.end, PAddr(0x21_0000));
+        assert_eq!(module.cmdline, "initrd");
+        assert!(modules.next().is_none());
+    }
+
+    #[test]
+    fn memory_map() {
+        let bytes = boot_info();
+        let info = BootInfo::new(&bytes).unwrap();
+        let areas: Vec<_> = info
+            .mem_map()
+            .map(|area| (area.base_address(), area.size(), area.kind()))
+            .collect();
+        assert_eq!(
+            areas,
+            [
+                (PAddr(0), 0x9fc00, RegionKind::Usable),
+                (PAddr(0x9fc00), 0x400, RegionKind::Unusable),
+                (PAddr(0x10_0000), 0x7ee_0000, RegionKind::Usable),
+                (PAddr(0x7fe_0000), 0x2_0000, RegionKind::AcpiReclaimable),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_memory_map() {
+        let mut buf = Vec::new();
+        push_u32(&mut buf, 0);
+        push_u32(&mut buf, 0);
+        // The entry size, but not the entry version.
+        push_tag(&mut buf, TAG_MMAP, &[24, 0, 0, 0, 0, 0]);
+        push_tag(&mut buf, TAG_END, &[]);
+        let len = buf.len() as u32;
+        for (i, b) in buf[..4].iter_mut().enumerate() {
+            *b = (len >> (i * 8)) as u8;
+        }
+
+        let info = BootInfo::new(&buf).unwrap();
+        assert!(info.memory_areas().next().is_none());
+    }
+
+    #[test]
+    fn kernel_from_elf_sections() {
+        let bytes = boot_info();
+        let info = BootInfo::new(&bytes).unwrap();
+        assert_eq!(info.kernel_base(), PAddr(0x10_0000));
+        assert_eq!(info.kernel_end(), PAddr(0x10_6800));
+
+        let frames = info.kernel_frames();
+        assert_eq!(frames.len(), 7);
+        assert_eq!(frames.start().base_address(), PAddr(0x10_0000));
+    }
+
+    #[test]
+    fn boot_params_extras() {
+        let bytes = boot_info();
+        let info = BootInfo::new(&bytes).unwrap();
+        let params = &info;
+        assert_eq!(params.command_line(), Some("console=ttyS0 quiet"));
+        assert_eq!(params.initrd(), Some(PAddr(0x20_0000)..PAddr(0x21_0000)));
+        assert_eq!(params.modules().count(), 1);
+        assert_eq!(params.bootloader().unwrap().name(), "GRUB 2.06");
+
+        let fb = params.framebuffer().unwrap();
+        assert_eq!(fb.address, PAddr(0xfd00_0000));
+        assert_eq!(
+            (fb.width, fb.height, fb.pitch, fb.bpp),
+            (1024, 768, 4096, 32)
+        );
+        let mask = |shift, size| ColorMask { shift, size };
+        assert_eq!(
+            fb.format,
+            PixelFormat::Rgb {
+                red: mask(16, 8),
+                green: mask(8, 8),
+                blue: mask(0, 8),
+            }
+        );
+
+        // The RSDP's address is only known if we know where the boot
+        // information itself is.
+        assert_eq!(params.rsdp(), None);
+        let bytes: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+        let addr = bytes.as_ptr() as usize;
+        let info = unsafe { BootInfo::from_addr(addr) }.unwrap();
+        let rsdp = info.rsdp().unwrap();
+        let offset = rsdp.0 as usize - addr;
+        assert_eq!(&bytes[offset..offset + 8], b"RSD PTR ");
+    }
+}
@@ -7,5 +7,6 @@ extern crate hal9000;
 #[macro_use]
 extern crate hal9000_derive;
 
+pub mod boot;
 pub mod paging;
 pub mod x64;
@@ -1,19 +1,23 @@
 use hal9000::mem::{self, page::TableUpdate, Address, VAddr};
 
-use core::{marker::PhantomData, ops};
+use core::{iter, marker::PhantomData, ops};
 
 pub mod table;
 
+/// The size of a page.
 pub trait PageSize: Copy + Eq + PartialOrd + Ord {
-    const SIZE: usize;
+    /// The base 2 logarithm of the page size.
+    const SHIFT: usize;
+    /// The size of a page in bytes.
+    const SIZE: usize = 1 << Self::SHIFT;
 }
 
 pub type Virtual<S = Small> = Page<VAddr, S>;
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Small;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Page<A, S: PageSize> {
     start_addr: A,
     _sz: PhantomData<S>,
@@ -38,13 +42,55 @@ impl<S: PageSize> TableUpdate for FlushTlb<S> {
 
 // ===== impl Page =====
 
+impl<A, S> Page<A, S>
+where
+    A: Address + From<usize>,
+    S: PageSize,
+{
+    /// Returns the page with the given number.
+    ///
+    /// # Panics
+    /// If the page would start outside of the address space.
+    pub fn from_number(number: usize) -> Self {
+        let addr = number
+            .checked_mul(S::SIZE)
+            .expect("page number overflowed the address space");
+        Self {
+            start_addr: A::from(addr),
+            _sz: PhantomData,
+        }
+    }
+
+    /// Returns the page `n` pages after this one, or `None` if it would be
+    /// outside of the address space.
+    pub fn checked_add(&self, n: usize) -> Option<Self> {
+        let start_addr =
+            self.start_addr.checked_add(n.checked_mul(S::SIZE)?)?;
+        Some(Self {
+            start_addr,
+            _sz: PhantomData,
+        })
+    }
+
+    /// Returns the page `n` pages before this one, or `None` if it would be
+    /// outside of the address space.
+    pub fn checked_sub(&self, n: usize) -> Option<Self> {
+        let start_addr =
+            self.start_addr.checked_sub(n.checked_mul(S::SIZE)?)?;
+        Some(Self {
+            start_addr,
+            _sz: PhantomData,
+        })
+    }
+}
+
 impl<A, S> mem::Page for Page<A, S>
 where
-    A: Address + From<usize> + ops::Add<Output = A> + Copy,
+    A: Address + From<usize>,
     S: PageSize,
 {
     /// Page alignment.
-    const SHIFT: usize = 12;
+    const SHIFT: usize = S::SHIFT;
 
     /// The size of a page in bytes.
     const SIZE: usize = S::SIZE;
@@ -79,15 +125,182 @@ where
 
     /// Returns the end `Address` of this `Page`.
     fn end_address(&self) -> Self::Address {
-        self.start_addr + Self::Address::from(Self::SIZE)
+        self.start_addr.offset(Self::SIZE)
     }
 
     /// Return the page's number.
     fn number(&self) -> usize {
-        unimplemented!()
+        A::from(0).distance(self.start_addr) >> S::SHIFT
+    }
+}
+
+impl<A, S> ops::Add<usize> for Page<A, S>
+where
+    A: Address + From<usize>,
+    S: PageSize,
+{
+    type Output = Self;
+
+    /// Returns the page `n` pages after this one.
+    ///
+    /// # Panics
+    /// If the page would be outside of the address space.
+    fn add(self, n: usize) -> Self {
+        self.checked_add(n)
+            .expect("page overflowed the address space")
+    }
+}
+
+impl<A, S> ops::AddAssign<usize> for Page<A, S>
+where
+    A: Address + From<usize>,
+    S: PageSize,
+{
+    fn add_assign(&mut self, n: usize) {
+        *self = *self + n;
+    }
+}
+
+impl<A, S> ops::Sub<usize> for Page<A, S>
+where
+    A: Address + From<usize>,
+    S: PageSize,
+{
+    type Output = Self;
+
+    /// Returns the page `n` pages before this one.
+    ///
+    /// # Panics
+    /// If the page would be outside of the address space.
+    fn sub(self, n: usize) -> Self {
+        self.checked_sub(n)
+            .expect("page underflowed the address space")
+    }
+}
+
+impl<A, S> ops::SubAssign<usize> for Page<A, S>
+where
+    A: Address + From<usize>,
+    S: PageSize,
+{
+    fn sub_assign(&mut self, n: usize) {
+        *self = *self - n;
+    }
+}
+
+impl<A, S> ops::Sub for Page<A, S>
+where
+    A: Address + From<usize>,
+    S: PageSize,
+{
+    type Output = usize;
+
+    /// Returns the number of pages from `rhs` up to this page.
+    ///
+    /// # Panics
+    /// If `rhs` is after this page.
+    fn sub(self, rhs: Self) -> usize {
+        assert!(rhs <= self, "subtracted a page from an earlier page");
+        self.start_addr.distance(rhs.start_addr) >> S::SHIFT
+    }
+}
+
+impl<A, S> iter::Step for Page<A, S>
+where
+    A: Address + From<usize>,
+    S: PageSize,
+{
+    fn steps_between(start: &Self, end: &Self) -> Option<usize> {
+        if start < end {
+            Some(*end - *start)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Replaces this step with `1`, returning itself
+    fn replace_one(&mut self) -> Self {
+        *self = Self::from_number(1);
+        *self
+    }
+
+    /// Replaces this step with `0`, returning itself
+    fn replace_zero(&mut self) -> Self {
+        *self = Self::from_number(0);
+        *self
+    }
+
+    /// Adds one to this step, returning the result
+    fn add_one(&self) -> Self {
+        *self + 1
+    }
+
+    /// Subtracts one to this step, returning the result
+    fn sub_one(&self) -> Self {
+        *self - 1
+    }
+
+    /// Add an usize, returning None on overflow
+    fn add_usize(&self, n: usize) -> Option<Self> {
+        self.checked_add(n)
     }
 }
 
 impl PageSize for Small {
-    const SIZE: usize = 4096;
+    const SHIFT: usize = 12;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x64::{
+        page::{size::Large, Physical},
+        PAddr,
+    };
+    use hal9000::mem::Page as _;
+
+    #[test]
+    fn page_numbers() {
+        let page = Virtual::<Small>::from_number(0x123);
+        assert_eq!(page.base_address(), VAddr(0x12_3000));
+        assert_eq!(page.number(), 0x123);
+
+        let frame = Physical::<Large>::from_addr_down(PAddr(0x0065_4321));
+        assert_eq!(frame.number(), 3);
+        assert_eq!(frame, Physical::from_number(3));
+    }
+
+    #[test]
+    fn page_arithmetic() {
+        let start = Physical::<Small>::from_number(4);
+        let mut page = start + 3;
+        assert_eq!(page.base_address(), PAddr(0x7000));
+        assert_eq!(page - start, 3);
+        page -= 2;
+        assert_eq!(page.number(), 5);
+        page += 1;
+        assert_eq!(page - 6, Physical::from_number(0));
+
+        let large = Virtual::<Large>::from_number(1) + 1;
+        assert_eq!(large.base_address(), VAddr(0x40_0000));
+        assert_eq!(large.checked_sub(3), None);
+        assert_eq!(Virtual::<Small>::from_number(0).checked_sub(1), None);
+    }
+
+    #[test]
+    fn page_ranges() {
+        let start = Virtual::<Large>::from_number(2);
+        let end = start + 3;
+        let numbers = (start..end).map(|page| page.number());
+        assert!(numbers.eq(2..5));
+        assert_eq!((start..end).count(), end - start);
+        assert_eq!((end..start).count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn page_difference_panics_if_negative() {
+        let start = Virtual::<Small>::from_number(2);
+        let _ = start - (start + 1);
+    }
 }
@@ -1,5 +1,5 @@
 use super::{PageSize, Virtual};
-use core::{marker::PhantomData, ops};
+use core::{marker::PhantomData, ops, slice};
 use hal9000::mem::{Page, VAddr};
 
 pub const NUM_ENTRIES: usize = 512;
@@ -34,6 +34,21 @@ pub struct Table<E, L: Level> {
 
 // ===== impl Table =====
 
+impl<E, L: Level> Table<E, L> {
+    /// Returns an iterator over the entries in the table.
+    #[inline]
+    pub fn iter(&self) -> slice::Iter<'_, E> {
+        self.entries.iter()
+    }
+
+    /// Returns an iterator over mutable references to the entries in the
+    /// table.
+    #[inline]
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, E> {
+        self.entries.iter_mut()
+    }
+}
+
 impl<Entry, L, I> ops::Index<I> for Table<Entry, L>
 where
     L: Level + IndexedBy<I>,
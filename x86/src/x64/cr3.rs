@@ -0,0 +1,38 @@
+//! The CR3 register, which holds the physical address of the active PML4.
+use super::{
+    page::{entry::Entry, Physical},
+    PAddr,
+};
+use hal9000::mem::Page;
+
+/// Returns the frame of the active PML4.
+pub fn read() -> Physical {
+    let value: u64;
+    unsafe {
+        asm!( "mov $0, cr3"
+             : "=r" (value)
+             :
+             :
+             : "intel", "volatile" );
+    }
+    Physical::from_addr_down(PAddr(value & Entry::ADDR_MASK))
+}
+
+/// Loads the PML4 in `pml4`, flushing every non-global TLB entry.
+///
+/// # Unsafety
+/// The new tables must map all of the code and data in use, including the
+/// current stack, at the same addresses as the old ones.
+pub unsafe fn write(pml4: &Physical) {
+    asm!( "mov cr3, $0"
+         :
+         : "r" (pml4.base_address().0)
+         : "memory"
+         : "intel", "volatile" );
+}
+
+/// Flushes every non-global TLB entry, by reloading CR3 with its current
+/// value.
+pub fn flush_tlb() {
+    unsafe { write(&read()) }
+}
@@ -3,6 +3,7 @@ use hal9000::{
     Architecture,
 };
 
+pub mod cr3;
 pub mod page;
 pub use self::page::Physical as PhysicalPage;
 pub use hal9000::mem::VAddr;
@@ -27,3 +28,18 @@ impl Architecture for X86_64 {
 
     const BITS: &'static str = "64";
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paddr_checked_arithmetic() {
+        let top = PAddr(::core::u64::MAX - 0xfff);
+        assert_eq!(top.checked_add(0xfff), Some(PAddr(::core::u64::MAX)));
+        assert_eq!(top.checked_add(0x1000), None);
+        assert_eq!(PAddr(0x1000).checked_sub(0x1001), None);
+        assert_eq!(PAddr(0x1000).offset(0x1000), PAddr(0x2000));
+        assert_eq!(PAddr(0x5000).distance(PAddr(0x1000)), 0x4000);
+    }
+}
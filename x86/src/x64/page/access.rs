@@ -0,0 +1,170 @@
+//! Strategies for reaching page tables through virtual memory.
+//!
+//! The MMU finds page tables by their physical addresses, but the kernel
+//! can only use virtual ones. A `TableAccess` decides how an `ActivePml4`
+//! gets from the frame of a table to a pointer it can read and write:
+//!
+//! + `Recursive` uses a PML4 entry which points to the PML4 itself, so that
+//!   every table appears somewhere in the top 512 GiB of the address space.
+//!   It needs no other mappings, which suits kernels that set up their own
+//!   tables.
+//! + `PhysOffset` uses a mapping of all of physical memory at a fixed
+//!   offset, such as the higher-half direct map that Limine sets up.
+//! + `TemporaryPage` maps each table at a reserved page when it is needed.
+//!   It only needs a single PT entry, but it can only reach one table at a
+//!   time.
+use super::{
+    entry::Entry,
+    table::{recursive_addr, Pml4},
+};
+use crate::{
+    paging::table::{Level, Table},
+    x64::PAddr,
+};
+
+/// A way of reaching page tables through virtual memory.
+///
+/// # Unsafety
+/// `table` must return a pointer through which the table can be read and
+/// written, at least until the next call to `table`.
+pub unsafe trait TableAccess {
+    /// Returns a pointer to the table in the frame at `frame`.
+    ///
+    /// `path` holds the index of each entry followed from the PML4 to reach
+    /// the table, and is empty for the PML4 itself.
+    fn table<L: Level>(
+        &self,
+        frame: PAddr,
+        path: &[usize],
+    ) -> *mut Table<Entry, L>;
+
+    /// Finishes setting up a new PML4 in the frame at `frame`, whose kernel
+    /// half has been copied from the active tables.
+    ///
+    /// By default, this does nothing.
+    fn init_pml4(&self, frame: PAddr, pml4: &mut Table<Entry, Pml4>) {
+        let _ = (frame, pml4);
+    }
+}
+
+/// A `TableAccess` through which several tables can be borrowed at once.
+///
+/// # Unsafety
+/// The pointer returned by `table` must stay valid after other tables are
+/// reached, for as long as the access is.
+pub unsafe trait StableAccess: TableAccess {}
+
+/// A `TableAccess` which reaches any table from its frame alone.
+///
+/// Such an access can reach tables which aren't part of the active
+/// hierarchy, such as those of an `InactivePageTable`.
+///
+/// # Unsafety
+/// `table` must ignore its `path`, and return the table in `frame`.
+pub unsafe trait FrameAccess: TableAccess {}
+
+/// Reaches page tables through the recursive PML4 entry at `INDEX`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Recursive;
+
+/// Reaches page tables through a mapping of all of physical memory.
+#[derive(Copy, Clone, Debug)]
+pub struct PhysOffset {
+    offset: usize,
+}
+
+// ===== impl Recursive =====
+
+impl Recursive {
+    /// The PML4 entry which maps the PML4 itself.
+    pub const INDEX: usize = 0o777;
+
+    /// The virtual address of the PML4 through the recursive mapping.
+    pub const PML4_ADDR: usize = 0xffff_ffff_ffff_f000;
+}
+
+/// Tables are found by following `path` through the recursive mapping, so
+/// their frames are never used. This only reaches the tables of the active
+/// PML4.
+unsafe impl TableAccess for Recursive {
+    fn table<L: Level>(
+        &self,
+        _: PAddr,
+        path: &[usize],
+    ) -> *mut Table<Entry, L> {
+        path.iter().fold(Self::PML4_ADDR, |table, &index| {
+            recursive_addr(table, index)
+        }) as *mut _
+    }
+
+    /// Points the new PML4's recursive entry at itself, rather than at the
+    /// PML4 it was copied from.
+    fn init_pml4(&self, frame: PAddr, pml4: &mut Table<Entry, Pml4>) {
+        let flags = pml4[Self::INDEX].flags();
+        pml4[Self::INDEX] = Entry::new(frame, flags);
+    }
+}
+
+/// Every table has its own address in the recursive mapping.
+unsafe impl StableAccess for Recursive {}
+
+// ===== impl PhysOffset =====
+
+impl PhysOffset {
+    /// Returns access through physical memory mapped starting at the
+    /// virtual address `offset`.
+    ///
+    /// # Unsafety
+    /// Every page table frame must be mapped writable at `offset` plus its
+    /// physical address.
+    pub unsafe fn new(offset: usize) -> Self {
+        PhysOffset { offset }
+    }
+
+    /// Returns the virtual address that physical address 0 is mapped at.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+unsafe impl TableAccess for PhysOffset {
+    fn table<L: Level>(
+        &self,
+        frame: PAddr,
+        _: &[usize],
+    ) -> *mut Table<Entry, L> {
+        self.offset.wrapping_add(frame.0 as usize) as *mut _
+    }
+}
+
+unsafe impl StableAccess for PhysOffset {}
+
+unsafe impl FrameAccess for PhysOffset {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paging::table::IndexedBy;
+    use crate::x64::page::table::Pt;
+    use crate::x64::VAddr;
+
+    #[test]
+    fn recursive_tables() {
+        let pt = Recursive.table::<Pt>(PAddr(0), &[0, 1, 2]);
+        assert_eq!(pt as usize, 0xffff_ff80_0020_2000);
+        let pml4 = Recursive.table::<Pml4>(PAddr(0), &[]);
+        assert_eq!(pml4 as usize, Recursive::PML4_ADDR);
+        assert_eq!(
+            <Pml4 as IndexedBy<VAddr>>::index_of(VAddr(Recursive::PML4_ADDR)),
+            Recursive::INDEX
+        );
+    }
+
+    #[test]
+    fn phys_offset_tables() {
+        let access = unsafe { PhysOffset::new(0xffff_8000_0000_0000) };
+        let table = access.table::<Pt>(PAddr(0x1234_5000), &[1, 2, 3]);
+        assert_eq!(table as usize, 0xffff_8000_1234_5000);
+    }
+}
@@ -0,0 +1,502 @@
+//! Dumping the contents of a page table hierarchy, like Linux's `ptdump`.
+//!
+//! `Tables::mappings` walks every present entry, from the lowest virtual
+//! address up, and yields one `Mapping` for each run of leaf entries that
+//! map virtually and physically contiguous pages of the same size with the
+//! same flags. `Tables::dump` writes those runs out, one per line:
+//!
+//! ```text
+//! 0xffff800000000000-0xffff800000400000    2 x 2M -> 0x0 PageFlags(PRESENT)
+//! ```
+use super::{
+    entry::Entry,
+    size::Large,
+    table::{PageFlags, SHIFTS},
+};
+use crate::{
+    paging::{PageSize, Small},
+    x64::{PAddr, VAddr},
+};
+use core::{fmt, ops};
+
+/// Read access to a hierarchy of page tables.
+pub trait Tables {
+    /// Returns the entries of the PML4, if it can be read.
+    fn root(&self) -> Option<&[Entry]>;
+
+    /// Returns the entries of the table that `entry` points to, if it can be
+    /// read.
+    ///
+    /// `path` holds the index of each entry followed from the PML4 to reach
+    /// `entry`, ending with the index of `entry` itself.
+    fn table(&self, entry: &Entry, path: &[usize]) -> Option<&[Entry]>;
+
+    /// Returns an iterator over the merged mappings in the tables, in order
+    /// of virtual address.
+    fn mappings(&self) -> Mappings<'_, Self> {
+        Mappings::new(self)
+    }
+
+    /// Writes every merged mapping in the tables to `out`, one per line.
+    fn dump<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        for mapping in self.mappings() {
+            writeln!(out, "{}", mapping)?;
+        }
+        Ok(())
+    }
+}
+
+/// A run of virtual pages of the same size, mapped to contiguous physical
+/// memory with the same flags.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mapping {
+    /// The virtual addresses which are mapped.
+    pub virt: ops::Range<VAddr>,
+    /// The physical address that `virt.start` maps to.
+    pub phys: PAddr,
+    /// The size in bytes of each page in the run.
+    pub page_size: usize,
+    /// The effective flags of the run.
+    ///
+    /// These are the flags of the leaf entries, with `WRITABLE` and `USER`
+    /// cleared and `NO_EXECUTE` set if a table above them says so.
+    /// `ACCESSED` and `DIRTY` are left out so that they don't split runs.
+    /// Bit 7 is always reported as `PAT`: for 2 MiB and 1 GiB pages,
+    /// `HUGE` is left out, since `page_size` already says so, and the entry's
+    /// `PAT` bit is moved down to bit 7.
+    pub flags: PageFlags,
+}
+
+/// An iterator over the merged mappings in a page table hierarchy.
+///
+/// This is returned by `Tables::mappings`.
+pub struct Mappings<'t, T: ?Sized> {
+    tables: &'t T,
+    /// The table being read at each depth of the walk.
+    stack: [&'t [Entry]; 4],
+    /// The index of the next entry to read at each depth of the walk.
+    path: [usize; 4],
+    /// The flags accumulated from the entries above each depth.
+    upper: [PageFlags; 4],
+    depth: usize,
+    done: bool,
+    /// A leaf which was read, but couldn't be merged into the last mapping.
+    pending: Option<Mapping>,
+}
+
+/// The `WRITABLE` and `USER` bits, which must be set at every level.
+const ALL_LEVELS: PageFlags =
+    PageFlags::from_bits(PageFlags::WRITABLE.bits() | PageFlags::USER.bits());
+
+/// The bits that aren't reported in a `Mapping`'s flags.
+const IGNORED: PageFlags =
+    PageFlags::from_bits(PageFlags::ACCESSED.bits() | PageFlags::DIRTY.bits());
+
+// ===== impl Mapping =====
+
+impl Mapping {
+    /// Returns the number of pages in the run.
+    #[inline]
+    pub fn pages(&self) -> usize {
+        self.size() / self.page_size
+    }
+
+    /// Returns the number of bytes mapped.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.virt
+            .end
+            .as_usize()
+            .wrapping_sub(self.virt.start.as_usize())
+    }
+
+    /// Returns the physical address just past the end of the run.
+    #[inline]
+    pub fn phys_end(&self) -> PAddr {
+        PAddr(self.phys.0 + self.size() as u64)
+    }
+
+    /// Extends this run with `next`, if `next` continues it.
+    fn merge(&mut self, next: &Mapping) -> bool {
+        let continues = self.virt.end == next.virt.start
+            && self.phys_end() == next.phys
+            && self.page_size == next.page_size
+            && self.flags == next.flags;
+        if continues {
+            self.virt.end = next.virt.end;
+        }
+        continues
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let size = match self.page_size {
+            Small::SIZE => "4K",
+            Large::SIZE => "2M",
+            _ => "1G",
+        };
+        write!(
+            f,
+            "{:#018x}-{:#018x} {:>4} x {} -> {:#x} ",
+            self.virt.start.as_usize(),
+            self.virt.end.as_usize(),
+            self.pages(),
+            size,
+            self.phys.0,
+        )?;
+        self.flags.write_names(f, "PAT")
+    }
+}
+
+// ===== impl Mappings =====
+
+impl<'t, T: Tables + ?Sized> Mappings<'t, T> {
+    fn new(tables: &'t T) -> Self {
+        let root = tables.root();
+        Mappings {
+            tables,
+            stack: [root.unwrap_or(&[]); 4],
+            path: [0; 4],
+            upper: [ALL_LEVELS; 4],
+            depth: 0,
+            done: root.is_none(),
+            pending: None,
+        }
+    }
+
+    /// Returns the mapping for the next leaf entry, without merging it.
+    fn next_leaf(&mut self) -> Option<Mapping> {
+        while !self.done {
+            let depth = self.depth;
+            let index = self.path[depth];
+            let entry = match self.stack[depth].get(index) {
+                Some(entry) => *entry,
+                None => {
+                    // Finished this table; carry on in the one above it.
+                    if depth == 0 {
+                        self.done = true;
+                    } else {
+                        self.depth -= 1;
+                        self.path[depth - 1] += 1;
+                    }
+                    continue;
+                },
+            };
+
+            if !entry.is_present() {
+                self.path[depth] += 1;
+                continue;
+            }
+            if depth == 3 || (depth > 0 && entry.is_huge()) {
+                self.path[depth] += 1;
+                return Some(self.leaf(depth, index, entry));
+            }
+
+            // A `HUGE` PML4 entry is reserved, so it is skipped, just like a
+            // table that can't be read.
+            let next = if depth == 0 && entry.is_huge() {
+                None
+            } else {
+                self.tables.table(&entry, &self.path[..=depth])
+            };
+            match next {
+                Some(table) => {
+                    let flags = entry.flags();
+                    let upper = self.upper[depth];
+                    self.stack[depth + 1] = table;
+                    self.path[depth + 1] = 0;
+                    self.upper[depth + 1] = (upper & flags & ALL_LEVELS)
+                        | ((upper | flags) & PageFlags::NO_EXECUTE);
+                    self.depth += 1;
+                },
+                None => self.path[depth] += 1,
+            }
+        }
+        None
+    }
+
+    /// Returns the mapping for the leaf `entry` at `index` of the table at
+    /// `depth`.
+    fn leaf(&self, depth: usize, index: usize, entry: Entry) -> Mapping {
+        let page_size = 1 << SHIFTS[depth];
+        let start = self.path[..depth]
+            .iter()
+            .zip(SHIFTS.iter())
+            .fold(index << SHIFTS[depth], |addr, (&index, &shift)| {
+                addr | index << shift
+            });
+        // Sign-extend bit 47 to make the address canonical.
+        let start = ((start << 16) as isize >> 16) as usize;
+
+        let upper = self.upper[depth];
+        let mut flags = entry.flags() & !IGNORED;
+        flags = (flags & !ALL_LEVELS) | (flags & upper & ALL_LEVELS);
+        flags.insert(upper & PageFlags::NO_EXECUTE);
+        let mut phys = entry.addr();
+        if depth < 3 {
+            // Report the large page's `PAT` bit where a 4 KiB page keeps it.
+            flags.remove(PageFlags::HUGE);
+            if entry.bits() & Entry::HUGE_PAT != 0 {
+                flags.insert(PageFlags::PAT);
+            }
+            phys = PAddr(phys.0 & !Entry::HUGE_PAT);
+        }
+        Mapping {
+            virt: VAddr(start)..VAddr(start.wrapping_add(page_size)),
+            phys,
+            page_size,
+            flags,
+        }
+    }
+}
+
+impl<'t, T: Tables + ?Sized> Iterator for Mappings<'t, T> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Mapping> {
+        let mut mapping = match self.pending.take() {
+            Some(mapping) => mapping,
+            None => self.next_leaf()?,
+        };
+        while let Some(next) = self.next_leaf() {
+            if !mapping.merge(&next) {
+                self.pending = Some(next);
+                break;
+            }
+        }
+        Some(mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x64::page::{
+        sim::{PhysMemory, RawFrame, SimPageTable},
+        size::Huge,
+        table::{Pd, Pdpt, Pml4, Pt},
+        tests::TestAlloc,
+        Physical, Virtual,
+    };
+    use hal9000::mem::Page;
+    extern crate std;
+    use self::std::{iter, string::String, vec::Vec};
+
+    fn frames(n: usize) -> Vec<RawFrame> {
+        iter::repeat(RawFrame::ZERO).take(n).collect()
+    }
+
+    fn map<S: PageSize>(
+        sim: &mut SimPageTable,
+        alloc: &mut TestAlloc,
+        vaddr: usize,
+        paddr: u64,
+        flags: PageFlags,
+    ) {
+        let page = Virtual::<S>::from_addr_down(VAddr(vaddr));
+        let frame = Physical::<S>::from_addr_down(PAddr(paddr));
+        sim.map(page, frame, flags, alloc).unwrap();
+    }
+
+    #[test]
+    fn contiguous_pages_are_merged() {
+        let mut frames = frames(16);
+        let memory = PhysMemory::new(PAddr(0), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0), PAddr(0x1_0000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+        let rw = PageFlags::WRITABLE | PageFlags::NO_EXECUTE;
+
+        for i in 0..4 {
+            let offset = i * 0x1000;
+            map::<Small>(
+                &mut sim,
+                &mut alloc,
+                0x40_0000 + offset,
+                0x10_0000 + offset as u64,
+                rw,
+            );
+        }
+        // Physically discontiguous.
+        map::<Small>(&mut sim, &mut alloc, 0x40_4000, 0x20_0000, rw);
+        // Different flags.
+        map::<Small>(
+            &mut sim,
+            &mut alloc,
+            0x40_5000,
+            0x20_1000,
+            rw | PageFlags::USER,
+        );
+        for i in 0..2 {
+            let offset = i * 0x20_0000;
+            map::<Large>(
+                &mut sim,
+                &mut alloc,
+                0x8000_0000 + offset,
+                0x4000_0000 + offset as u64,
+                PageFlags::empty(),
+            );
+        }
+        map::<Huge>(
+            &mut sim,
+            &mut alloc,
+            0xffff_8000_0000_0000,
+            0,
+            PageFlags::WRITABLE,
+        );
+
+        let mappings = sim.mappings().collect::<Vec<_>>();
+        let small = PageFlags::PRESENT | rw;
+        assert_eq!(
+            mappings,
+            [
+                Mapping {
+                    virt: VAddr(0x40_0000)..VAddr(0x40_4000),
+                    phys: PAddr(0x10_0000),
+                    page_size: Small::SIZE,
+                    flags: small,
+                },
+                Mapping {
+                    virt: VAddr(0x40_4000)..VAddr(0x40_5000),
+                    phys: PAddr(0x20_0000),
+                    page_size: Small::SIZE,
+                    flags: small,
+                },
+                Mapping {
+                    virt: VAddr(0x40_5000)..VAddr(0x40_6000),
+                    phys: PAddr(0x20_1000),
+                    page_size: Small::SIZE,
+                    flags: small | PageFlags::USER,
+                },
+                Mapping {
+                    virt: VAddr(0x8000_0000)..VAddr(0x8040_0000),
+                    phys: PAddr(0x4000_0000),
+                    page_size: Large::SIZE,
+                    flags: PageFlags::PRESENT,
+                },
+                Mapping {
+                    virt: VAddr(0xffff_8000_0000_0000)
+                        ..VAddr(0xffff_8000_4000_0000),
+                    phys: PAddr(0),
+                    page_size: Huge::SIZE,
+                    flags: PageFlags::PRESENT | PageFlags::WRITABLE,
+                },
+            ]
+        );
+        assert_eq!(mappings[3].pages(), 2);
+        assert_eq!(mappings[3].phys_end(), PAddr(0x4040_0000));
+    }
+
+    #[test]
+    fn flags_are_restricted_by_upper_levels() {
+        let mut frames = frames(8);
+        let memory = PhysMemory::new(PAddr(0), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0), PAddr(0x8000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+        let flags = PageFlags::WRITABLE | PageFlags::USER;
+        map::<Small>(&mut sim, &mut alloc, 0x1000, 0x1000, flags);
+        map::<Small>(&mut sim, &mut alloc, 0x2000, 0x2000, flags);
+
+        // Make the PD entry read-only and no-execute, and mark the second
+        // page as accessed and dirty.
+        let pml4 = sim.memory().table::<Pml4>(sim.root()).unwrap()[0].addr();
+        let pdpt = sim.memory().table::<Pdpt>(pml4).unwrap()[0].addr();
+        let pd = sim.memory_mut().table_mut::<Pd>(pdpt).unwrap();
+        let pt = pd[0].addr();
+        pd[0] = Entry::new(
+            pt,
+            PageFlags::PRESENT | PageFlags::USER | PageFlags::NO_EXECUTE,
+        );
+        let pt = sim.memory_mut().table_mut::<Pt>(pt).unwrap();
+        pt[2].insert(PageFlags::ACCESSED | PageFlags::DIRTY);
+
+        let mappings = sim.mappings().collect::<Vec<_>>();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].virt, VAddr(0x1000)..VAddr(0x3000));
+        assert_eq!(
+            mappings[0].flags,
+            PageFlags::PRESENT | PageFlags::USER | PageFlags::NO_EXECUTE
+        );
+    }
+
+    #[test]
+    fn pat_is_reported() {
+        let mut frames = frames(8);
+        let memory = PhysMemory::new(PAddr(0), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0), PAddr(0x8000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+        for i in 0..2 {
+            let offset = i * 0x20_0000;
+            map::<Large>(
+                &mut sim,
+                &mut alloc,
+                0x20_0000 + offset,
+                0x20_0000 + offset as u64,
+                PageFlags::empty(),
+            );
+        }
+
+        map::<Small>(&mut sim, &mut alloc, 0x60_0000, 0x7000, PageFlags::PAT);
+
+        // Set the `PAT` bit of the second large page only.
+        let pml4 = sim.memory().table::<Pml4>(sim.root()).unwrap()[0].addr();
+        let pdpt = sim.memory().table::<Pdpt>(pml4).unwrap()[0].addr();
+        let pd = sim.memory_mut().table_mut::<Pd>(pdpt).unwrap();
+        pd[2] = Entry::new(
+            PAddr(0x40_0000 | Entry::HUGE_PAT),
+            PageFlags::PRESENT | PageFlags::HUGE,
+        );
+
+        let mappings = sim.mappings().collect::<Vec<_>>();
+        assert_eq!(mappings.len(), 3);
+        assert_eq!(mappings[0].flags, PageFlags::PRESENT);
+        assert_eq!(mappings[1].phys, PAddr(0x40_0000));
+        assert_eq!(mappings[1].flags, PageFlags::PRESENT | PageFlags::PAT);
+
+        let mut out = String::new();
+        sim.dump(&mut out).unwrap();
+        assert_eq!(
+            out,
+            "0x0000000000200000-0x0000000000400000    1 x 2M -> 0x200000 \
+             PageFlags(PRESENT)\n\
+             0x0000000000400000-0x0000000000600000    1 x 2M -> 0x400000 \
+             PageFlags(PRESENT | PAT)\n\
+             0x0000000000600000-0x0000000000601000    1 x 4K -> 0x7000 \
+             PageFlags(PRESENT | PAT)\n"
+        );
+    }
+
+    #[test]
+    fn dump_writes_one_line_per_mapping() {
+        let mut frames = frames(8);
+        let memory = PhysMemory::new(PAddr(0), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0), PAddr(0x8000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+        let mut out = String::new();
+        sim.dump(&mut out).unwrap();
+        assert_eq!(out, "");
+
+        map::<Large>(
+            &mut sim,
+            &mut alloc,
+            0x20_0000,
+            0x20_0000,
+            PageFlags::WRITABLE,
+        );
+        map::<Small>(
+            &mut sim,
+            &mut alloc,
+            0x40_0000,
+            0x7000,
+            PageFlags::empty(),
+        );
+        sim.dump(&mut out).unwrap();
+        assert_eq!(
+            out,
+            "0x0000000000200000-0x0000000000400000    1 x 2M -> 0x200000 \
+             PageFlags(PRESENT | WRITABLE)\n\
+             0x0000000000400000-0x0000000000401000    1 x 4K -> 0x7000 \
+             PageFlags(PRESENT)\n"
+        );
+    }
+}
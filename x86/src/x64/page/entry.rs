@@ -0,0 +1,154 @@
+//! x86_64 page table entries.
+use super::{table::PageFlags, Physical};
+use crate::x64::PAddr;
+use core::fmt;
+use hal9000::mem::Page;
+
+/// A 64-bit page table entry.
+///
+/// The same format is used at every level of the table hierarchy: an entry
+/// either points to the next table down, or, if it is a leaf, to the page
+/// it maps.
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Entry(u64);
+
+impl Entry {
+    /// The bits of the entry holding the physical address.
+    pub const ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+    /// In a 2 MiB or 1 GiB page entry, the bit of `ADDR_MASK` which is
+    /// actually the `PAT` bit.
+    pub const HUGE_PAT: u64 = 1 << 12;
+
+    /// Returns an entry pointing to `addr`, with the given flags.
+    ///
+    /// Bits of `addr` outside of `ADDR_MASK` are ignored.
+    pub const fn new(addr: PAddr, flags: PageFlags) -> Self {
+        Entry((addr.0 & Self::ADDR_MASK) | flags.bits())
+    }
+
+    /// Returns an entry with every bit clear.
+    pub const fn unused() -> Self {
+        Entry(0)
+    }
+
+    /// Returns the raw bits of the entry.
+    #[inline]
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns the entry's flags: every bit outside of `ADDR_MASK`.
+    #[inline]
+    pub fn flags(&self) -> PageFlags {
+        PageFlags::from_bits(self.0 & !Self::ADDR_MASK)
+    }
+
+    /// Replaces the entry's flags, keeping its address.
+    #[inline]
+    pub fn set_flags(&mut self, flags: PageFlags) {
+        *self = Entry::new(self.addr(), flags);
+    }
+
+    /// Sets the bits in `flags`, leaving the others unchanged.
+    #[inline]
+    pub fn insert(&mut self, flags: PageFlags) {
+        self.0 |= flags.bits();
+    }
+
+    /// Returns `true` if every bit of the entry is clear.
+    #[inline]
+    pub fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Clears every bit of the entry.
+    #[inline]
+    pub fn set_unused(&mut self) {
+        self.0 = 0;
+    }
+
+    /// Returns `true` if the entry is valid.
+    #[inline]
+    pub fn is_present(&self) -> bool {
+        self.flags().contains(PageFlags::PRESENT)
+    }
+
+    /// Returns `true` if the entry has the `HUGE` bit set.
+    ///
+    /// This only means that the entry maps a page in a PDPT or PD; in a PT,
+    /// the same bit selects the memory type instead.
+    #[inline]
+    pub fn is_huge(&self) -> bool {
+        self.flags().contains(PageFlags::HUGE)
+    }
+
+    /// Returns the physical address the entry points to.
+    #[inline]
+    pub fn addr(&self) -> PAddr {
+        PAddr(self.0 & Self::ADDR_MASK)
+    }
+
+    /// Returns the frame the entry points to, if the entry is present.
+    ///
+    /// For a 2 MiB or 1 GiB page, this is the first 4 KiB frame of the page.
+    pub fn frame(&self) -> Option<Physical> {
+        if !self.is_present() {
+            return None;
+        }
+        let addr = if self.is_huge() {
+            PAddr(self.addr().0 & !Self::HUGE_PAT)
+        } else {
+            self.addr()
+        };
+        Some(Physical::from_addr_down(addr))
+    }
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("addr", &self.addr())
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_and_flags() {
+        let flags = PageFlags::PRESENT | PageFlags::WRITABLE;
+        let mut entry = Entry::new(PAddr(0xdead_b000), flags);
+        assert_eq!(entry.addr(), PAddr(0xdead_b000));
+        assert_eq!(entry.flags(), flags);
+        assert_eq!(entry.frame().unwrap().base_address(), PAddr(0xdead_b000));
+        assert!(!entry.is_huge());
+
+        // Bits outside the address field don't leak into the address.
+        let entry2 = Entry::new(PAddr(0xfff0_0000_dead_bfff), flags);
+        assert_eq!(entry2.addr(), PAddr(0x0000_0000_dead_b000));
+
+        entry.set_flags(PageFlags::PRESENT | PageFlags::NO_EXECUTE);
+        assert_eq!(entry.addr(), PAddr(0xdead_b000));
+        assert!(entry.flags().contains(PageFlags::NO_EXECUTE));
+
+        assert!(!entry.is_unused());
+        entry.set_unused();
+        assert!(entry.is_unused());
+        assert!(entry.frame().is_none());
+    }
+
+    #[test]
+    fn huge_page_frame_ignores_pat() {
+        let entry = Entry::new(
+            PAddr(0x4000_1000),
+            PageFlags::PRESENT | PageFlags::HUGE,
+        );
+        assert!(entry.is_huge());
+        assert_eq!(entry.frame().unwrap().base_address(), PAddr(0x4000_0000));
+    }
+}
@@ -0,0 +1,559 @@
+//! Page tables which are not loaded in CR3.
+//!
+//! An `InactivePageTable` is a complete set of page tables for another
+//! address space, such as a process which isn't running. It is edited with
+//! `ActivePml4::with`, which points the active tables' `TableAccess` at it so
+//! that the usual `Mapper` methods operate on it, and it is loaded with
+//! `ActivePml4::switch`. While it is inactive, its own tables are reached
+//! through a `FrameAccess`: usually a `TemporaryPage`, or a `PhysOffset` if
+//! all of physical memory is mapped.
+//!
+//! Every `InactivePageTable` shares the kernel half of the address space
+//! (PML4 entries `KERNEL_START` and up) with the tables it was created from,
+//! so the kernel stays mapped whichever address space is loaded. Tables in
+//! the kernel half which are created after an address space will not be
+//! seen by it.
+use super::{
+    access::{FrameAccess, PhysOffset, Recursive, TableAccess},
+    entry::Entry,
+    table::{ActivePml4, Error, MapSize, PageFlags, Pml4, Pt, SHIFTS},
+    Physical, Virtual,
+};
+use crate::{
+    paging::{
+        table::{IndexedBy, Level, Table, NUM_ENTRIES},
+        FlushTlb, Small,
+    },
+    x64::{cr3, PAddr, VAddr},
+};
+use core::{
+    mem::ManuallyDrop,
+    ops,
+    ptr::{self, NonNull},
+};
+use hal9000::mem::{
+    page::{self, FrameAllocator, TableUpdate},
+    Address, Page,
+};
+
+/// The index of the first PML4 entry in the kernel half of the address
+/// space.
+pub const KERNEL_START: usize = 256;
+
+/// A virtual page reserved for temporarily mapping frames, so that page
+/// tables which aren't otherwise reachable can be read and written.
+///
+/// The `TemporaryPage` keeps a pointer to the PT entry which maps its page,
+/// so that it can map frames without going through an `ActivePml4`. That PT
+/// is in the kernel half, and so it is shared by every address space.
+///
+/// A `TemporaryPage` is also a `TableAccess`, for kernels which have neither
+/// a recursive mapping nor a mapping of all physical memory. It can only
+/// reach one table at a time.
+pub struct TemporaryPage {
+    page: Virtual,
+    /// The PT entry which maps `page`.
+    entry: NonNull<Entry>,
+}
+
+/// The page tables of an address space which is not currently active.
+///
+/// The tables are reached through `T`, and the frames of the tables are
+/// returned to the allocator when the `InactivePageTable` is dropped.
+pub struct InactivePageTable<A, T = TemporaryPage>
+where
+    A: FrameAllocator<Frame = Physical>,
+    T: FrameAccess,
+{
+    pml4: Physical,
+    access: T,
+    alloc: A,
+}
+
+/// The flags of the entries that `TemporaryPage` and the recursive mapping
+/// use to reach page tables.
+const TABLE: PageFlags = PageFlags::from_bits(
+    PageFlags::PRESENT.bits()
+        | PageFlags::WRITABLE.bits()
+        | PageFlags::NO_EXECUTE.bits(),
+);
+
+// ===== impl TemporaryPage =====
+
+impl TemporaryPage {
+    /// Reserves `page` for temporary mappings, allocating the tables above
+    /// its PT entry from `alloc` if they don't exist.
+    ///
+    /// # Unsafety
+    /// Nothing else may map or unmap `page`, or free the tables above it,
+    /// while the `TemporaryPage` exists. `active` must reach its PT through
+    /// a mapping which stays in place, so it can't itself be using a
+    /// `TemporaryPage`; use `from_entry` in that case.
+    ///
+    /// # Panics
+    /// If `page` is not in the kernel half of the address space.
+    pub unsafe fn new<T, A>(
+        page: Virtual,
+        active: &mut ActivePml4<T>,
+        alloc: &mut A,
+    ) -> Result<Self, Error>
+    where
+        T: TableAccess,
+        A: FrameAllocator<Frame = Physical>,
+    {
+        let vaddr = page.base_address();
+        assert!(
+            <Pml4 as IndexedBy<VAddr>>::index_of(vaddr) >= KERNEL_START,
+            "temporary page {:?} is not in the kernel half",
+            vaddr
+        );
+        let slot = active.create(vaddr, Small::DEPTH, alloc)?;
+        if active.entry(&slot).is_present() {
+            return Err(Error::AlreadyMapped);
+        }
+        Ok(Self::from_entry(
+            page,
+            NonNull::new_unchecked(active.entry_ptr(&slot)),
+        ))
+    }
+
+    /// Returns a `TemporaryPage` for `page`, which is mapped by the PT entry
+    /// that `entry` points to.
+    ///
+    /// # Unsafety
+    /// `entry` must stay valid and writable for as long as the
+    /// `TemporaryPage` exists, and nothing else may use it.
+    pub unsafe fn from_entry(page: Virtual, entry: NonNull<Entry>) -> Self {
+        TemporaryPage { page, entry }
+    }
+
+    /// Returns the page which frames are mapped at.
+    #[inline]
+    pub fn page(&self) -> &Virtual {
+        &self.page
+    }
+
+    /// Maps the frame at `addr` at the temporary page, and returns it as an
+    /// `L`-level page table.
+    ///
+    /// Whatever was mapped there before is unmapped.
+    fn map_table<L: Level>(&mut self, addr: PAddr) -> &mut Table<Entry, L> {
+        unsafe { &mut *self.table(addr, &[]) }
+    }
+
+    /// Unmaps whatever is mapped at the temporary page.
+    fn unmap(&mut self) {
+        unsafe {
+            self.entry.as_mut().set_unused();
+            FlushTlb { page: self.page }.commit();
+        }
+    }
+}
+
+impl Drop for TemporaryPage {
+    /// Unmaps whatever is mapped at the temporary page, so that it doesn't
+    /// keep a table reachable after the table is freed.
+    fn drop(&mut self) {
+        self.unmap();
+    }
+}
+
+/// Each table is mapped at the temporary page when it is reached, which
+/// unmaps the one reached before it.
+unsafe impl TableAccess for TemporaryPage {
+    fn table<L: Level>(
+        &self,
+        frame: PAddr,
+        _: &[usize],
+    ) -> *mut Table<Entry, L> {
+        let page = self.page;
+        unsafe {
+            self.entry.as_ptr().write(Entry::new(frame, TABLE));
+            FlushTlb { page }.commit();
+        }
+        page.base_address().as_usize() as *mut _
+    }
+}
+
+unsafe impl FrameAccess for TemporaryPage {}
+
+// ===== impl InactivePageTable =====
+
+impl<A, T> InactivePageTable<A, T>
+where
+    A: FrameAllocator<Frame = Physical>,
+    T: FrameAccess,
+{
+    /// Returns a new address space, with a PML4 allocated from `alloc`.
+    ///
+    /// The user half of the new address space is empty, and the kernel half
+    /// shares its tables with `active`. `access` is used to reach the tables
+    /// while they are inactive, and `alloc` to allocate and free them.
+    pub fn new<U: TableAccess>(
+        active: &ActivePml4<U>,
+        access: T,
+        mut alloc: A,
+    ) -> Result<Self, Error> {
+        let frame = unsafe { alloc.alloc() }.map_err(|_| Error::Alloc)?;
+        {
+            let addr = frame.base_address();
+            let old = active.access().table::<Pml4>(active.root(), &[]);
+            let old = unsafe { &*old };
+            let pml4 = unsafe { &mut *access.table::<Pml4>(addr, &[]) };
+            pml4.zero();
+            for index in KERNEL_START..NUM_ENTRIES {
+                pml4[index] = old[index];
+            }
+            active.access().init_pml4(addr, pml4);
+        }
+        Ok(InactivePageTable {
+            pml4: frame,
+            access,
+            alloc,
+        })
+    }
+
+    /// Returns the frame of the PML4, as it will be loaded into CR3.
+    #[inline]
+    pub fn pml4_frame(&self) -> &Physical {
+        &self.pml4
+    }
+
+    /// Returns the strategy used to reach the tables.
+    #[inline]
+    pub fn access(&self) -> &T {
+        &self.access
+    }
+
+    /// Returns the allocator that the tables are freed to.
+    #[inline]
+    pub fn alloc_mut(&mut self) -> &mut A {
+        &mut self.alloc
+    }
+
+    /// Frees the PML4, every table in the user half, and the frames that
+    /// `map_to_any` allocated for it, like dropping the `InactivePageTable`,
+    /// but returns any error from the allocator.
+    ///
+    /// If the allocator fails, the remaining tables are leaked.
+    pub fn free(self) -> Result<(), Error> {
+        let mut this = ManuallyDrop::new(self);
+        let result = this.free_tables();
+        unsafe {
+            ptr::drop_in_place(&mut this.access);
+            ptr::drop_in_place(&mut this.alloc);
+        }
+        result
+    }
+
+    /// Frees the PML4, every table in the user half and the frames they own,
+    /// stopping at the first error.
+    fn free_tables(&mut self) -> Result<(), Error> {
+        let pml4 = self.pml4.base_address();
+        self.free_below(pml4, 0, 0..KERNEL_START)?;
+        unsafe { self.alloc.dealloc(Physical::from_addr_down(pml4)) }
+            .map_err(|_| Error::Alloc)
+    }
+
+    /// Frees every table below `indices` of the table at `table`, which is
+    /// `depth` levels below the PML4, along with the frames of the `OWNED`
+    /// pages they map.
+    ///
+    /// The table is reached again for every entry, as freeing the tables
+    /// below it may reach them in its place, such as at a temporary page.
+    fn free_below(
+        &mut self,
+        table: PAddr,
+        depth: usize,
+        indices: ops::Range<usize>,
+    ) -> Result<(), Error> {
+        for index in indices {
+            let entry =
+                unsafe { (&*self.access.table::<Pt>(table, &[]))[index] };
+            if !entry.is_present() {
+                continue;
+            }
+            // In a PT entry, the `HUGE` bit is the `PAT` bit.
+            if depth == SHIFTS.len() - 1 || (depth > 0 && entry.is_huge()) {
+                if entry.flags().contains(PageFlags::OWNED) {
+                    let size = 1 << SHIFTS[depth];
+                    let start = entry.addr().align_down(size);
+                    let frames = page::Range::within(start..start.offset(size));
+                    unsafe { self.alloc.dealloc_range(frames) }
+                        .map_err(|_| Error::Alloc)?;
+                }
+                continue;
+            }
+            let next = entry.addr();
+            self.free_below(next, depth + 1, 0..NUM_ENTRIES)?;
+            unsafe { self.alloc.dealloc(Physical::from_addr_down(next)) }
+                .map_err(|_| Error::Alloc)?;
+        }
+        Ok(())
+    }
+}
+
+impl<A, T> Drop for InactivePageTable<A, T>
+where
+    A: FrameAllocator<Frame = Physical>,
+    T: FrameAccess,
+{
+    /// Frees the PML4, every table in the user half, and the frames that
+    /// `map_to_any` allocated for the user half.
+    ///
+    /// The tables in the kernel half are shared with other address spaces,
+    /// so they are not freed. Neither are frames which were mapped with
+    /// `map` or `identity_map`, as they belong to the caller.
+    ///
+    /// `drop` can't report errors from the allocator, so if it fails, the
+    /// remaining tables are silently leaked; use `free` to find out.
+    fn drop(&mut self) {
+        let _ = self.free_tables();
+    }
+}
+// ===== impl ActivePml4 =====
+
+impl ActivePml4<Recursive> {
+    /// Runs `f` with the recursive mapping pointed at `table`, so that the
+    /// `ActivePml4` passed to `f` edits `table` instead of the active tables.
+    ///
+    /// `f` is also passed `table`'s allocator. Everything else, such as the
+    /// kernel's code and stack, is still translated by the active tables
+    /// while `f` runs.
+    pub fn with<A, F, R>(&mut self, table: &mut InactivePageTable<A>, f: F) -> R
+    where
+        A: FrameAllocator<Frame = Physical>,
+        F: FnOnce(&mut Self, &mut A) -> R,
+    {
+        let active = self.root();
+        let inactive = table.pml4.base_address();
+        let pml4 = unsafe { &mut *self.access().table::<Pml4>(active, &[]) };
+        pml4[Self::RECURSIVE_INDEX] = Entry::new(inactive, TABLE);
+        cr3::flush_tlb();
+        self.set_root(inactive);
+
+        let result = f(self, &mut table.alloc);
+
+        // The recursive mapping no longer reaches the active PML4, but the
+        // temporary page does: it is in the kernel half, which `table`
+        // shares.
+        self.set_root(active);
+        table.access.map_table::<Pml4>(active)[Self::RECURSIVE_INDEX] =
+            Entry::new(active, TABLE);
+        table.access.unmap();
+        cr3::flush_tlb();
+        result
+    }
+}
+
+impl ActivePml4<PhysOffset> {
+    /// Runs `f` with the `ActivePml4` pointed at `table`, so that it edits
+    /// `table` instead of the active tables.
+    ///
+    /// `f` is also passed `table`'s allocator. As every table is reachable
+    /// through the physical memory mapping, nothing else needs to change.
+    pub fn with<A, T, F, R>(
+        &mut self,
+        table: &mut InactivePageTable<A, T>,
+        f: F,
+    ) -> R
+    where
+        A: FrameAllocator<Frame = Physical>,
+        T: FrameAccess,
+        F: FnOnce(&mut Self, &mut A) -> R,
+    {
+        let active = self.root();
+        self.set_root(table.pml4.base_address());
+        let result = f(self, &mut table.alloc);
+        self.set_root(active);
+        result
+    }
+}
+
+impl<U: TableAccess> ActivePml4<U> {
+    /// Loads `new` into CR3, and returns the tables which were active before
+    /// as an `InactivePageTable`, along with `new`'s allocator.
+    ///
+    /// The returned tables take over `new`'s `FrameAccess`, and are freed
+    /// into `old_alloc` when they are dropped.
+    ///
+    /// # Unsafety
+    /// `new` must map all of the code and data in use, including the current
+    /// stack. This holds if they are all in the kernel half.
+    ///
+    /// The tables which were active, and the frames they own, must have been
+    /// allocated from `old_alloc`. If they weren't allocated from any
+    /// allocator, such as if they were set up by the bootloader, the returned
+    /// `InactivePageTable` must be passed to `mem::forget` instead.
+    pub unsafe fn switch<A, B, T>(
+        &mut self,
+        new: InactivePageTable<A, T>,
+        old_alloc: B,
+    ) -> (InactivePageTable<B, T>, A)
+    where
+        A: FrameAllocator<Frame = Physical>,
+        B: FrameAllocator<Frame = Physical>,
+        T: FrameAccess,
+    {
+        let old = Physical::from_addr_down(self.root());
+        let new = ManuallyDrop::new(new);
+        cr3::write(&new.pml4);
+        self.set_root(new.pml4.base_address());
+        let old = InactivePageTable {
+            pml4: old,
+            access: ptr::read(&new.access),
+            alloc: old_alloc,
+        };
+        (old, ptr::read(&new.alloc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paging::PageSize;
+    use crate::x64::page::{
+        sim::{PhysMemory, RawFrame, SimPageTable},
+        size::{Huge, Large},
+        tests::TestAlloc,
+    };
+    use hal9000::mem::{page::Mapper, Address};
+    extern crate std;
+    use self::std::{iter, vec::Vec};
+
+    /// Lends a `TestAlloc` to an `InactivePageTable`, so that it can be
+    /// checked once the tables are dropped.
+    struct Lend<'a>(&'a mut TestAlloc);
+
+    unsafe impl<'a> FrameAllocator for Lend<'a> {
+        type Frame = Physical;
+        type Error = ();
+
+        unsafe fn alloc(&mut self) -> Result<Physical, ()> {
+            self.0.alloc()
+        }
+
+        unsafe fn dealloc(&mut self, frame: Physical) -> Result<(), ()> {
+            self.0.dealloc(frame)
+        }
+    }
+
+    #[test]
+    fn free_reports_errors() {
+        let base = PAddr(0x10_0000);
+        let mut frames =
+            iter::repeat(RawFrame::ZERO).take(8).collect::<Vec<_>>();
+        let offset =
+            (frames.as_mut_ptr() as usize).wrapping_sub(base.0 as usize);
+        let access = || unsafe { PhysOffset::new(offset) };
+        let mut alloc = TestAlloc::new(base, base.offset(8 * Small::SIZE));
+        let root = unsafe { alloc.alloc() }.unwrap().base_address();
+        let active = unsafe { ActivePml4::from_access(root, access()) };
+
+        let table = InactivePageTable::new(&active, access(), Lend(&mut alloc))
+            .unwrap();
+        assert_eq!(table.free(), Ok(()));
+        assert_eq!(alloc.allocated(), 1);
+
+        /// An allocator which can't take frames back.
+        struct NoDealloc<'a>(&'a mut TestAlloc);
+
+        unsafe impl<'a> FrameAllocator for NoDealloc<'a> {
+            type Frame = Physical;
+            type Error = ();
+
+            unsafe fn alloc(&mut self) -> Result<Physical, ()> {
+                self.0.alloc()
+            }
+
+            unsafe fn dealloc(&mut self, _: Physical) -> Result<(), ()> {
+                Err(())
+            }
+        }
+
+        let table =
+            InactivePageTable::new(&active, access(), NoDealloc(&mut alloc))
+                .unwrap();
+        assert_eq!(table.free(), Err(Error::Alloc));
+        assert_eq!(alloc.allocated(), 2);
+    }
+
+    #[test]
+    fn drop_frees_every_table() {
+        let base = PAddr(0x10_0000);
+        let frame = |n: usize| base.offset(n * Small::SIZE);
+        let mut frames =
+            iter::repeat(RawFrame::ZERO).take(32).collect::<Vec<_>>();
+        let offset =
+            (frames.as_mut_ptr() as usize).wrapping_sub(base.0 as usize);
+        let access = || unsafe { PhysOffset::new(offset) };
+        let rw = PageFlags::WRITABLE | PageFlags::NO_EXECUTE;
+
+        // The active tables, with a page in the kernel half.
+        let mut kernel_alloc = TestAlloc::new(frame(0), frame(8));
+        let root = unsafe { kernel_alloc.alloc() }.unwrap().base_address();
+        let mut active = unsafe { ActivePml4::from_access(root, access()) };
+        let kernel = Virtual::from_addr_down(VAddr(0xffff_8000_0000_0000));
+        let _ = active.map_to_any(kernel, rw, &mut kernel_alloc).unwrap();
+        let kernel_frames = kernel_alloc.allocated();
+
+        // The frames past `frame(32)` are only ever mapped, never written.
+        let mut alloc = TestAlloc::new(frame(8), PAddr(0x40_0000));
+        {
+            let mut table =
+                InactivePageTable::new(&active, access(), Lend(&mut alloc))
+                    .unwrap();
+            let user = Virtual::from_addr_down(VAddr(0x40_0000));
+            let large = Virtual::<Large>::from_addr_down(VAddr(0x20_0000));
+            let huge = Virtual::<Huge>::from_addr_down(VAddr(0x80_0000_0000));
+            let mmio = Physical::from_addr_down(PAddr(0xfee0_0000));
+            active.with(&mut table, |mapper, alloc| {
+                let _ = mapper.map_to_any(user, rw, alloc).unwrap();
+                let _ = mapper.identity_map(mmio, rw, alloc).unwrap();
+                let _ = mapper
+                    .map_sized(
+                        huge,
+                        Physical::from_addr_down(PAddr(0)),
+                        rw,
+                        alloc,
+                    )
+                    .unwrap();
+                let _ = mapper.map_to_any_sized(large, rw, alloc).unwrap();
+            });
+            assert_eq!(active.root(), root);
+            assert_eq!(active.translate(VAddr(0x40_0000)), None);
+
+            // The new tables map the user pages and share the kernel half.
+            let pml4 = table.pml4_frame().base_address();
+            let sim =
+                SimPageTable::new(PhysMemory::new(base, &mut frames), pml4);
+            assert!(sim.translate(VAddr(0x40_0000)).is_some());
+            assert_eq!(
+                sim.translate(VAddr(0xfee0_0000)),
+                Some(PAddr(0xfee0_0000))
+            );
+            assert_eq!(
+                sim.translate(VAddr(0x80_0000_1234)),
+                Some(PAddr(0x1234))
+            );
+            assert_eq!(
+                sim.translate(VAddr(0xffff_8000_0000_0000)),
+                active.translate(VAddr(0xffff_8000_0000_0000))
+            );
+            assert_eq!(sim.translate(VAddr(0x20_1234)), Some(PAddr(0x20_1234)));
+            // The user pages' frames, the PML4, and two each of PDPTs, PDs
+            // and PTs.
+            assert_eq!(table.alloc_mut().0.allocated(), 1 + 512 + 1 + 6);
+        }
+        assert_eq!(alloc.allocated(), 0);
+        assert_eq!(kernel_alloc.allocated(), kernel_frames);
+    }
+
+    #[test]
+    fn kernel_half() {
+        let index = |addr| <Pml4 as IndexedBy<VAddr>>::index_of(VAddr(addr));
+        assert_eq!(index(0x0000_7fff_ffff_ffff), KERNEL_START - 1);
+        assert_eq!(index(0xffff_8000_0000_0000), KERNEL_START);
+        assert_eq!(index(ActivePml4::PML4_ADDR), ActivePml4::RECURSIVE_INDEX);
+    }
+}
@@ -3,6 +3,11 @@ use crate::{
     x64::{PAddr, VAddr},
 };
 
+pub mod access;
+pub mod dump;
+pub mod entry;
+pub mod inactive;
+pub mod sim;
 pub mod table;
 
 pub type Physical<S = Small> = Page<PAddr, S>;
@@ -12,16 +17,76 @@ pub mod size {
     use super::PageSize;
     pub use crate::paging::Small;
 
-    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
     pub struct Large;
-    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
     pub struct Huge;
 
     impl PageSize for Large {
-        const SIZE: usize = Small::SIZE * 512;
+        const SHIFT: usize = Small::SHIFT + 9;
     }
 
     impl PageSize for Huge {
-        const SIZE: usize = Large::SIZE * 512;
+        const SHIFT: usize = Large::SHIFT + 9;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::Physical;
+    use crate::paging::{PageSize, Small};
+    use crate::x64::PAddr;
+    use hal9000::mem::{page::FrameAllocator, Address, Page};
+    extern crate std;
+    use self::std::vec::Vec;
+
+    /// A frame allocator for tests, which hands out the frames from `next`
+    /// up to `end`, reusing freed frames first.
+    pub(crate) struct TestAlloc {
+        next: PAddr,
+        end: PAddr,
+        free: Vec<PAddr>,
+        allocated: usize,
+    }
+
+    impl TestAlloc {
+        pub(crate) fn new(next: PAddr, end: PAddr) -> Self {
+            TestAlloc {
+                next,
+                end,
+                free: Vec::new(),
+                allocated: 0,
+            }
+        }
+
+        /// Returns the number of frames currently allocated.
+        pub(crate) fn allocated(&self) -> usize {
+            self.allocated
+        }
+    }
+
+    unsafe impl FrameAllocator for TestAlloc {
+        type Frame = Physical;
+        type Error = ();
+
+        unsafe fn alloc(&mut self) -> Result<Physical, ()> {
+            let addr = match self.free.pop() {
+                Some(addr) => addr,
+                None if self.next < self.end => {
+                    let addr = self.next;
+                    self.next = addr.offset(Small::SIZE);
+                    addr
+                },
+                None => return Err(()),
+            };
+            self.allocated += 1;
+            Ok(Physical::from_addr_down(addr))
+        }
+
+        unsafe fn dealloc(&mut self, frame: Physical) -> Result<(), ()> {
+            self.allocated -= 1;
+            self.free.push(frame.base_address());
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,675 @@
+//! A software model of the x86_64 MMU.
+//!
+//! `SimPageTable` builds and walks page tables stored in a buffer of
+//! simulated physical memory, rather than the tables pointed to by the live
+//! CR3, so that page table code can be checked on the host. Translations
+//! follow the same rules as the hardware with 4-level paging, `CR0.WP` and
+//! `EFER.NXE` set, and SMEP, SMAP and protection keys disabled:
+//!
+//! + a PS bit in a PDPT or PD entry ends the walk with a 1 GiB or 2 MiB page,
+//!   and is reserved in a PML4 entry;
+//! + a write needs `WRITABLE` at every level, even in supervisor mode;
+//! + a user-mode access needs `USER` at every level;
+//! + an instruction fetch is refused if any level sets `NO_EXECUTE`.
+use super::{
+    dump,
+    entry::Entry,
+    table::{PageFlags, Pd, Pdpt, Pml4, Pt, SHIFTS},
+    Physical, Virtual,
+};
+use crate::{
+    paging::{
+        table::{IndexedBy, Level, Table, NUM_ENTRIES},
+        PageSize, Small,
+    },
+    x64::{PAddr, VAddr},
+};
+use hal9000::mem::{page::FrameAllocator, Address, Page};
+
+/// One 4 KiB frame of simulated physical memory.
+#[derive(Clone)]
+#[repr(C, align(4096))]
+pub struct RawFrame(pub [u64; NUM_ENTRIES]);
+
+/// A contiguous run of simulated physical memory.
+pub struct PhysMemory<'a> {
+    base: PAddr,
+    frames: &'a mut [RawFrame],
+}
+
+/// Page tables in simulated physical memory.
+pub struct SimPageTable<'a> {
+    memory: PhysMemory<'a>,
+    root: PAddr,
+}
+
+/// The result of a successful page table walk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Translation {
+    /// The physical address the virtual address translates to.
+    pub paddr: PAddr,
+    /// The size in bytes of the page containing the address.
+    pub page_size: usize,
+    /// Whether every level of the walk allows writes.
+    pub writable: bool,
+    /// Whether every level of the walk allows user-mode accesses.
+    pub user: bool,
+    /// Whether no level of the walk forbids instruction fetches.
+    pub executable: bool,
+}
+
+/// The kind of memory access being translated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// The privilege level an access is made from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Privilege {
+    Supervisor,
+    User,
+}
+
+/// The reasons a translation can fail.
+///
+/// Apart from `OutOfBounds`, each of these would be a page fault on real
+/// hardware.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The address is not sign-extended from bit 47.
+    NonCanonical,
+    /// An entry on the walk is not present.
+    NotPresent,
+    /// An entry on the walk has a reserved bit set.
+    Reserved,
+    /// The page is mapped, but its permissions don't allow the access.
+    Protection,
+    /// A table on the walk is outside of the simulated memory.
+    OutOfBounds(PAddr),
+}
+
+/// Errors returned when building page tables.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The page is already mapped.
+    AlreadyMapped,
+    /// The page is inside a larger page which is already mapped.
+    HugePage,
+    /// The page size can't be mapped by a single entry.
+    BadPageSize,
+    /// The frame allocator couldn't provide a frame for a new table.
+    Alloc,
+    /// A table is outside of the simulated memory.
+    OutOfBounds(PAddr),
+}
+
+/// The position of an entry in simulated memory.
+#[derive(Copy, Clone)]
+struct Slot {
+    table: PAddr,
+    index: usize,
+}
+
+// ===== impl RawFrame =====
+
+impl RawFrame {
+    /// A frame filled with zeroes.
+    pub const ZERO: RawFrame = RawFrame([0; NUM_ENTRIES]);
+}
+
+// ===== impl PhysMemory =====
+
+impl<'a> PhysMemory<'a> {
+    /// Returns simulated physical memory made up of `frames`, the first of
+    /// which is at `base`.
+    ///
+    /// # Panics
+    /// If `base` is not page-aligned.
+    pub fn new(base: PAddr, frames: &'a mut [RawFrame]) -> Self {
+        assert!(
+            base.is_page_aligned::<Physical>(),
+            "simulated memory must start on a page boundary"
+        );
+        PhysMemory { base, frames }
+    }
+
+    /// Returns the address of the first byte of simulated memory.
+    #[inline]
+    pub fn base(&self) -> PAddr {
+        self.base
+    }
+
+    /// Returns the address just past the end of simulated memory.
+    #[inline]
+    pub fn end(&self) -> PAddr {
+        self.base.offset(self.frames.len() * Small::SIZE)
+    }
+
+    /// Returns the frame containing `addr`, if it is in simulated memory.
+    pub fn frame(&self, addr: PAddr) -> Option<&RawFrame> {
+        let i = self.frame_index(addr)?;
+        self.frames.get(i)
+    }
+
+    /// Returns the frame containing `addr` mutably, if it is in simulated
+    /// memory.
+    pub fn frame_mut(&mut self, addr: PAddr) -> Option<&mut RawFrame> {
+        let i = self.frame_index(addr)?;
+        self.frames.get_mut(i)
+    }
+
+    /// Returns the frame containing `addr` as an `L`-level page table.
+    pub fn table<L: Level>(&self, addr: PAddr) -> Option<&Table<Entry, L>> {
+        self.frame(addr).map(|frame| unsafe {
+            // Safe because a `Table` is a `repr(C)` array of 512
+            // `repr(transparent)` `u64`s, just like a `RawFrame`.
+            &*(frame as *const RawFrame as *const Table<Entry, L>)
+        })
+    }
+
+    /// Returns the frame containing `addr` as a mutable `L`-level page table.
+    pub fn table_mut<L: Level>(
+        &mut self,
+        addr: PAddr,
+    ) -> Option<&mut Table<Entry, L>> {
+        self.frame_mut(addr).map(|frame| unsafe {
+            &mut *(frame as *mut RawFrame as *mut Table<Entry, L>)
+        })
+    }
+
+    fn frame_index(&self, addr: PAddr) -> Option<usize> {
+        if addr < self.base {
+            return None;
+        }
+        Some(self.base.distance(addr) / Small::SIZE)
+    }
+
+    fn entry_mut(&mut self, slot: Slot) -> Option<&mut Entry> {
+        self.table_mut::<Pt>(slot.table)
+            .map(|table| &mut table[slot.index])
+    }
+}
+
+// ===== impl SimPageTable =====
+
+impl<'a> SimPageTable<'a> {
+    /// Returns a simulator for the tables whose PML4 is at `root`, as though
+    /// `root` had been loaded into CR3.
+    pub fn new(memory: PhysMemory<'a>, root: PAddr) -> Self {
+        SimPageTable { memory, root }
+    }
+
+    /// Returns a simulator for a new, empty PML4 allocated from `alloc`.
+    pub fn create<A>(
+        mut memory: PhysMemory<'a>,
+        alloc: &mut A,
+    ) -> Result<Self, Error>
+    where
+        A: FrameAllocator<Frame = Physical>,
+    {
+        let root = unsafe { alloc.alloc() }
+            .map_err(|_| Error::Alloc)?
+            .base_address();
+        *memory.frame_mut(root).ok_or(Error::OutOfBounds(root))? =
+            RawFrame::ZERO;
+        Ok(Self::new(memory, root))
+    }
+
+    /// Returns the physical address of the PML4, as it would appear in CR3.
+    #[inline]
+    pub fn root(&self) -> PAddr {
+        self.root
+    }
+
+    /// Returns the simulated physical memory.
+    #[inline]
+    pub fn memory(&self) -> &PhysMemory<'a> {
+        &self.memory
+    }
+
+    /// Returns the simulated physical memory mutably.
+    #[inline]
+    pub fn memory_mut(&mut self) -> &mut PhysMemory<'a> {
+        &mut self.memory
+    }
+
+    /// Maps `page` to `frame` with the given entry flags, allocating any
+    /// missing tables from `alloc`.
+    ///
+    /// `PRESENT`, and `HUGE` for 2 MiB and 1 GiB pages, are added to `flags`.
+    /// New intermediate tables are writable and user-accessible, so the
+    /// permissions of the page are decided by its leaf entry.
+    pub fn map<S, A>(
+        &mut self,
+        page: Virtual<S>,
+        frame: Physical<S>,
+        flags: PageFlags,
+        alloc: &mut A,
+    ) -> Result<(), Error>
+    where
+        S: PageSize,
+        A: FrameAllocator<Frame = Physical>,
+    {
+        let leaf = SHIFTS
+            .iter()
+            .skip(1)
+            .position(|&shift| 1 << shift == S::SIZE)
+            .ok_or(Error::BadPageSize)?
+            + 1;
+        let vaddr = page.base_address();
+
+        let mut table = self.root;
+        for depth in 0..leaf {
+            let (slot, entry) = self
+                .entry(depth, table, vaddr)
+                .ok_or(Error::OutOfBounds(table))?;
+            if !entry.is_present() {
+                let next = unsafe { alloc.alloc() }
+                    .map_err(|_| Error::Alloc)?
+                    .base_address();
+                *self
+                    .memory
+                    .frame_mut(next)
+                    .ok_or(Error::OutOfBounds(next))? = RawFrame::ZERO;
+                self.set_entry(
+                    slot,
+                    Entry::new(
+                        next,
+                        PageFlags::PRESENT
+                            | PageFlags::WRITABLE
+                            | PageFlags::USER,
+                    ),
+                )?;
+                table = next;
+            } else if depth > 0 && entry.is_huge() {
+                return Err(Error::HugePage);
+            } else {
+                table = entry.addr();
+            }
+        }
+
+        let (slot, entry) = self
+            .entry(leaf, table, vaddr)
+            .ok_or(Error::OutOfBounds(table))?;
+        if entry.is_present() {
+            return Err(Error::AlreadyMapped);
+        }
+        let huge = if leaf < SHIFTS.len() - 1 {
+            PageFlags::HUGE
+        } else {
+            PageFlags::empty()
+        };
+        self.set_entry(
+            slot,
+            Entry::new(frame.base_address(), flags | PageFlags::PRESENT | huge),
+        )
+    }
+
+    /// Walks the tables to translate `vaddr`, without checking permissions
+    /// or updating accessed and dirty bits.
+    pub fn walk(&self, vaddr: VAddr) -> Result<Translation, Fault> {
+        self.walk_slots(vaddr)
+            .map(|(translation, _, _)| translation)
+    }
+
+    /// Returns the physical address `vaddr` translates to, if it is mapped.
+    pub fn translate(&self, vaddr: VAddr) -> Option<PAddr> {
+        self.walk(vaddr).ok().map(|translation| translation.paddr)
+    }
+
+    /// Translates an access to `vaddr` as the MMU would, checking the
+    /// permissions of every level and setting the accessed bits on the walk,
+    /// and the dirty bit on the leaf entry of a write.
+    pub fn access(
+        &mut self,
+        vaddr: VAddr,
+        access: Access,
+        privilege: Privilege,
+    ) -> Result<Translation, Fault> {
+        let (translation, slots, len) = self.walk_slots(vaddr)?;
+        if !translation.allows(access, privilege) {
+            return Err(Fault::Protection);
+        }
+        for (depth, &slot) in slots[..len].iter().enumerate() {
+            let entry = self
+                .memory
+                .entry_mut(slot)
+                .ok_or(Fault::OutOfBounds(slot.table))?;
+            entry.insert(PageFlags::ACCESSED);
+            if depth == len - 1 && access == Access::Write {
+                entry.insert(PageFlags::DIRTY);
+            }
+        }
+        Ok(translation)
+    }
+
+    fn walk_slots(
+        &self,
+        vaddr: VAddr,
+    ) -> Result<(Translation, [Slot; 4], usize), Fault> {
+        let top = (vaddr.as_usize() as i64) >> 47;
+        if top != 0 && top != -1 {
+            return Err(Fault::NonCanonical);
+        }
+
+        let mut translation = Translation {
+            paddr: PAddr(0),
+            page_size: 0,
+            writable: true,
+            user: true,
+            executable: true,
+        };
+        let mut slots = [Slot {
+            table: self.root,
+            index: 0,
+        }; 4];
+        let mut table = self.root;
+        for (depth, &shift) in SHIFTS.iter().enumerate() {
+            let (slot, entry) = self
+                .entry(depth, table, vaddr)
+                .ok_or(Fault::OutOfBounds(table))?;
+            slots[depth] = slot;
+            if !entry.is_present() {
+                return Err(Fault::NotPresent);
+            }
+            let flags = entry.flags();
+            translation.writable &= flags.contains(PageFlags::WRITABLE);
+            translation.user &= flags.contains(PageFlags::USER);
+            translation.executable &= !flags.contains(PageFlags::NO_EXECUTE);
+
+            let is_leaf = depth == SHIFTS.len() - 1 || entry.is_huge();
+            if !is_leaf {
+                table = entry.addr();
+                continue;
+            }
+            if depth == 0 {
+                return Err(Fault::Reserved);
+            }
+            let offset_mask = (1u64 << shift) - 1;
+            let base = entry.addr().0;
+            if depth < SHIFTS.len() - 1
+                && base & offset_mask & !Entry::HUGE_PAT != 0
+            {
+                return Err(Fault::Reserved);
+            }
+            translation.paddr = PAddr(
+                (base & !offset_mask) | (vaddr.as_usize() as u64 & offset_mask),
+            );
+            translation.page_size = 1 << shift;
+            return Ok((translation, slots, depth + 1));
+        }
+        unreachable!("the walk always ends at the PT")
+    }
+
+    /// Returns the entry for `vaddr` in the table at `table`, which is at
+    /// `depth` levels below the PML4.
+    fn entry(
+        &self,
+        depth: usize,
+        table: PAddr,
+        vaddr: VAddr,
+    ) -> Option<(Slot, Entry)> {
+        match depth {
+            0 => self.entry_in::<Pml4>(table, vaddr),
+            1 => self.entry_in::<Pdpt>(table, vaddr),
+            2 => self.entry_in::<Pd>(table, vaddr),
+            _ => self.entry_in::<Pt>(table, vaddr),
+        }
+    }
+
+    fn entry_in<L: Level>(
+        &self,
+        table: PAddr,
+        vaddr: VAddr,
+    ) -> Option<(Slot, Entry)> {
+        let entry = self.memory.table::<L>(table)?[vaddr];
+        let index = <L as IndexedBy<VAddr>>::index_of(vaddr);
+        Some((Slot { table, index }, entry))
+    }
+
+    fn set_entry(&mut self, slot: Slot, entry: Entry) -> Result<(), Error> {
+        *self
+            .memory
+            .entry_mut(slot)
+            .ok_or(Error::OutOfBounds(slot.table))? = entry;
+        Ok(())
+    }
+}
+
+impl<'a> dump::Tables for SimPageTable<'a> {
+    fn root(&self) -> Option<&[Entry]> {
+        self.memory
+            .table::<Pml4>(self.root)
+            .map(|table| table.iter().as_slice())
+    }
+
+    fn table(&self, entry: &Entry, _: &[usize]) -> Option<&[Entry]> {
+        self.memory
+            .table::<Pt>(entry.addr())
+            .map(|table| table.iter().as_slice())
+    }
+}
+
+// ===== impl Translation =====
+
+impl Translation {
+    /// Returns `true` if the permissions of the translation allow `access`
+    /// from `privilege`.
+    pub fn allows(&self, access: Access, privilege: Privilege) -> bool {
+        if privilege == Privilege::User && !self.user {
+            return false;
+        }
+        match access {
+            Access::Read => true,
+            Access::Write => self.writable,
+            Access::Execute => self.executable,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x64::page::{size::*, tests::TestAlloc};
+    extern crate std;
+    use self::std::{iter, vec::Vec};
+
+    const MIB: u64 = 1 << 20;
+    const GIB: u64 = 1 << 30;
+
+    fn frames(n: usize) -> Vec<RawFrame> {
+        iter::repeat(RawFrame::ZERO).take(n).collect()
+    }
+
+    fn virt<S: PageSize>(addr: usize) -> Virtual<S> {
+        Virtual::from_addr_down(VAddr(addr))
+    }
+
+    fn phys<S: PageSize>(addr: u64) -> Physical<S> {
+        Physical::from_addr_down(PAddr(addr))
+    }
+
+    #[test]
+    fn map_and_translate_small_pages() {
+        let mut frames = frames(16);
+        let memory = PhysMemory::new(PAddr(0x10_0000), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0x10_0000), PAddr(0x11_0000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+        assert_eq!(sim.root(), PAddr(0x10_0000));
+
+        let page = || virt::<Small>(0xffff_8000_1234_5000);
+        sim.map(page(), phys(0x4000_0000), PageFlags::WRITABLE, &mut alloc)
+            .unwrap();
+        // One table at each of the three levels below the PML4.
+        assert_eq!(alloc.allocated(), 4);
+
+        let t = sim.walk(VAddr(0xffff_8000_1234_5678)).unwrap();
+        assert_eq!(t.paddr, PAddr(0x4000_0678));
+        assert_eq!(t.page_size, 4096);
+        assert!(t.writable && !t.user && t.executable);
+
+        assert_eq!(sim.translate(VAddr(0xffff_8000_1234_6000)), None);
+        assert_eq!(
+            sim.walk(VAddr(0x0000_8000_0000_0000)),
+            Err(Fault::NonCanonical)
+        );
+
+        // A neighbouring page reuses the existing tables.
+        sim.map(
+            virt::<Small>(0xffff_8000_1234_6000),
+            phys(0x5000_0000),
+            PageFlags::empty(),
+            &mut alloc,
+        )
+        .unwrap();
+        assert_eq!(alloc.allocated(), 4);
+        assert_eq!(
+            sim.map(page(), phys(0x6000_0000), PageFlags::empty(), &mut alloc),
+            Err(Error::AlreadyMapped)
+        );
+    }
+
+    #[test]
+    fn large_and_huge_pages() {
+        let mut frames = frames(8);
+        let memory = PhysMemory::new(PAddr(0), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0), PAddr(0x8000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+
+        sim.map(
+            virt::<Large>(0x20_0000),
+            phys(0x80_0000),
+            PageFlags::empty(),
+            &mut alloc,
+        )
+        .unwrap();
+        sim.map(
+            virt::<Huge>(0x40_0000_0000),
+            phys(GIB),
+            PageFlags::empty(),
+            &mut alloc,
+        )
+        .unwrap();
+
+        let t = sim.walk(VAddr(0x21_2345)).unwrap();
+        assert_eq!(t.paddr, PAddr(0x81_2345));
+        assert_eq!(t.page_size, 2 * MIB as usize);
+        let t = sim.walk(VAddr(0x40_1234_5678)).unwrap();
+        assert_eq!(t.paddr, PAddr(GIB + 0x1234_5678));
+        assert_eq!(t.page_size, GIB as usize);
+
+        assert_eq!(
+            sim.map(
+                virt::<Small>(0x20_1000),
+                phys(0),
+                PageFlags::empty(),
+                &mut alloc
+            ),
+            Err(Error::HugePage)
+        );
+    }
+
+    #[test]
+    fn reserved_bits() {
+        let mut frames = frames(8);
+        let memory = PhysMemory::new(PAddr(0), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0), PAddr(0x8000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+        sim.map(
+            virt::<Large>(0x20_0000),
+            phys(0x80_0000),
+            PageFlags::empty(),
+            &mut alloc,
+        )
+        .unwrap();
+
+        // The PAT bit is allowed in a large page entry...
+        let pd = sim.walk_slots(VAddr(0x20_0000)).unwrap().1[2];
+        let set_addr_bit = |sim: &mut SimPageTable, bit: u64| {
+            let entry = sim.memory.entry_mut(pd).unwrap();
+            *entry = Entry::new(PAddr(entry.addr().0 | bit), entry.flags());
+        };
+        set_addr_bit(&mut sim, Entry::HUGE_PAT);
+        assert_eq!(sim.translate(VAddr(0x20_0000)), Some(PAddr(0x80_0000)));
+        // ...but the bits above it are reserved.
+        set_addr_bit(&mut sim, 1 << 13);
+        assert_eq!(sim.walk(VAddr(0x20_0000)), Err(Fault::Reserved));
+
+        // PS is reserved in a PML4 entry.
+        let pml4 = sim.memory.table_mut::<Pml4>(sim.root()).unwrap();
+        pml4[0].insert(PageFlags::HUGE);
+        assert_eq!(sim.walk(VAddr(0x20_0000)), Err(Fault::Reserved));
+    }
+
+    #[test]
+    fn permissions_are_checked_at_every_level() {
+        let mut frames = frames(8);
+        let memory = PhysMemory::new(PAddr(0), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0), PAddr(0x8000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+        let vaddr = VAddr(0x40_0000);
+        sim.map(
+            virt::<Small>(vaddr.as_usize()),
+            phys(0x7000),
+            PageFlags::USER | PageFlags::NO_EXECUTE,
+            &mut alloc,
+        )
+        .unwrap();
+
+        let (user, kernel) = (Privilege::User, Privilege::Supervisor);
+        assert!(sim.access(vaddr, Access::Read, user).is_ok());
+        assert_eq!(
+            sim.access(vaddr, Access::Write, kernel),
+            Err(Fault::Protection)
+        );
+        assert_eq!(
+            sim.access(vaddr, Access::Execute, kernel),
+            Err(Fault::Protection)
+        );
+
+        // Clearing USER on the PML4 entry hides the page from user mode.
+        let pml4 = sim.memory.table_mut::<Pml4>(sim.root()).unwrap();
+        pml4[vaddr] = Entry::new(pml4[vaddr].addr(), PageFlags::PRESENT);
+        assert_eq!(
+            sim.access(vaddr, Access::Read, user),
+            Err(Fault::Protection)
+        );
+        assert!(sim.access(vaddr, Access::Read, kernel).is_ok());
+    }
+
+    #[test]
+    fn accessed_and_dirty_bits() {
+        let mut frames = frames(8);
+        let memory = PhysMemory::new(PAddr(0), &mut frames);
+        let mut alloc = TestAlloc::new(PAddr(0), PAddr(0x8000));
+        let mut sim = SimPageTable::create(memory, &mut alloc).unwrap();
+        let vaddr = VAddr(0x1000);
+        sim.map(
+            virt::<Small>(0x1000),
+            phys(0x7000),
+            PageFlags::WRITABLE,
+            &mut alloc,
+        )
+        .unwrap();
+
+        let leaf = |sim: &SimPageTable| {
+            let slot = sim.walk_slots(vaddr).unwrap().1[3];
+            sim.memory.table::<Pt>(slot.table).unwrap()[slot.index]
+        };
+        assert!(!leaf(&sim).flags().contains(PageFlags::ACCESSED));
+
+        sim.access(vaddr, Access::Read, Privilege::Supervisor)
+            .unwrap();
+        assert!(leaf(&sim).flags().contains(PageFlags::ACCESSED));
+        assert!(!leaf(&sim).flags().contains(PageFlags::DIRTY));
+        let pml4 = sim.memory.table::<Pml4>(sim.root()).unwrap();
+        assert!(pml4[vaddr].flags().contains(PageFlags::ACCESSED));
+
+        sim.access(vaddr, Access::Write, Privilege::Supervisor)
+            .unwrap();
+        assert!(leaf(&sim).flags().contains(PageFlags::DIRTY));
+    }
+}
@@ -1,28 +1,414 @@
 use crate::{
-    paging::FlushTlb,
-    x64::{page::*, X86_64},
+    paging::{
+        table::{IndexedBy, Level, Sublevel, Table},
+        FlushTlb,
+    },
+    x64::{
+        page::{
+            access::{Recursive, StableAccess, TableAccess},
+            dump,
+            entry::Entry,
+            *,
+        },
+        X86_64,
+    },
 };
-use hal9000::mem::page;
+use core::{fmt, ops};
+use hal9000::mem::{
+    page::{self, CachePolicy, Permissions},
+    Address, Page,
+};
+
+/// The top-level table, each entry of which covers 512 GiB.
+pub enum Pml4 {}
+
+/// The page directory pointer table, each entry of which covers 1 GiB.
+pub enum Pdpt {}
+
+/// The page directory, each entry of which covers 2 MiB.
+pub enum Pd {}
+
+/// The page table, each entry of which maps a 4 KiB page.
+pub enum Pt {}
 
 /// Struct representing the currently active PML4 instance.
 ///
 /// The `ActivePML4` is a `Unique` reference to a PML4-level page table. It's
 /// unique because, well, there can only be one active PML4 at a given time.
-pub struct ActivePml4 {
-    // TODO: implement
-//plm4: Unique<Table<Pml4Level>>
+///
+/// The page tables are reached through the `TableAccess` strategy `T`. By
+/// default, this is the recursive mapping: the PML4 entry at
+/// `RECURSIVE_INDEX` points to the PML4 itself, so every table in the
+/// hierarchy appears somewhere in the top 512 GiB of the address space.
+/// Kernels which have all of physical memory mapped can use `PhysOffset`
+/// instead, and those with neither can use a `TemporaryPage`.
+pub struct ActivePml4<T = Recursive> {
+    root: PAddr,
+    access: T,
 }
 
-pub struct PageFlags {
-    // TODO: implement
-}
+/// The flags of an x86_64 page table entry.
+///
+/// In a PT entry, the `PAT` bit shares its position with `HUGE`; the `Debug`
+/// output always calls it `HUGE`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct PageFlags(u64);
 
+/// Errors returned by `ActivePml4`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Error {
+    /// The frame allocator couldn't provide or take back a frame.
     Alloc,
-    // TODO: Implement
+    /// The page is already mapped.
+    AlreadyMapped,
+    /// The page is not mapped.
+    NotMapped,
+    /// The page is inside a 2 MiB or 1 GiB page.
+    HugePage,
+}
+
+/// A page size which is mapped by a single page table entry.
+///
+/// 4 KiB pages are mapped by PT entries, 2 MiB pages by PD entries, and
+/// 1 GiB pages by PDPT entries.
+pub trait MapSize: PageSize {
+    /// Whether the entries mapping pages of this size have the `HUGE` flag.
+    const HUGE: bool;
+
+    /// How many levels below the PML4 the entries mapping pages of this
+    /// size are.
+    const DEPTH: usize;
+}
+
+/// The position of an entry on the walk for a virtual address.
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Slot {
+    /// The frame of the table containing the entry.
+    table: PAddr,
+    /// The index of the entry at each level of the walk.
+    path: [usize; 4],
+    /// How many levels below the PML4 the table is.
+    depth: usize,
+}
+
+/// The `ADDR_SHIFT` of each level, from the top of the hierarchy down.
+pub(crate) const SHIFTS: [usize; 4] = [
+    Pml4::ADDR_SHIFT,
+    Pdpt::ADDR_SHIFT,
+    Pd::ADDR_SHIFT,
+    Pt::ADDR_SHIFT,
+];
+
+// ===== impl ActivePml4 =====
+
+impl ActivePml4<Recursive> {
+    /// The PML4 entry which maps the PML4 itself.
+    pub const RECURSIVE_INDEX: usize = Recursive::INDEX;
+
+    /// The virtual address of the PML4 through the recursive mapping.
+    pub const PML4_ADDR: usize = Recursive::PML4_ADDR;
+
+    /// Returns the currently active PML4.
+    ///
+    /// # Unsafety
+    /// The PML4 loaded in CR3 must map itself at `RECURSIVE_INDEX`, and
+    /// there must not be any other `ActivePml4` in existence.
+    pub unsafe fn new() -> Self {
+        let pml4 = &*(Self::PML4_ADDR as *const Table<Entry, Pml4>);
+        Self::from_access(pml4[Self::RECURSIVE_INDEX].addr(), Recursive)
+    }
+}
+
+impl<T: TableAccess> ActivePml4<T> {
+    /// Returns the page tables whose PML4 is in the frame at `root`, reached
+    /// through `access`.
+    ///
+    /// # Unsafety
+    /// `access` must be able to reach every table below `root`, and there
+    /// must not be any other `ActivePml4` for the same tables.
+    pub unsafe fn from_access(root: PAddr, access: T) -> Self {
+        ActivePml4 { root, access }
+    }
+
+    /// Returns the physical address of the PML4.
+    #[inline]
+    pub fn root(&self) -> PAddr {
+        self.root
+    }
+
+    /// Returns the strategy used to reach the page tables.
+    #[inline]
+    pub fn access(&self) -> &T {
+        &self.access
+    }
+
+    /// Sets the physical address of the PML4, after the tables have been
+    /// switched.
+    pub(super) fn set_root(&mut self, root: PAddr) {
+        self.root = root;
+    }
+
+    /// Returns the entry in `slot`.
+    pub(super) fn entry(&self, slot: &Slot) -> Entry {
+        unsafe { self.entry_ptr(slot).read() }
+    }
+
+    /// Replaces the entry in `slot` with `entry`.
+    pub(super) fn set_entry(&mut self, slot: &Slot, entry: Entry) {
+        unsafe { self.entry_ptr(slot).write(entry) }
+    }
+
+    /// Returns a pointer to the entry in `slot`.
+    ///
+    /// Depending on the `TableAccess`, the pointer may only be valid until
+    /// the next table is reached.
+    pub(super) fn entry_ptr(&self, slot: &Slot) -> *mut Entry {
+        let table = self.access.table::<Pt>(slot.table, slot.path());
+        unsafe { &mut (&mut *table)[slot.index()] }
+    }
+
+    /// Returns the slot at `depth` levels below the PML4 on the walk for
+    /// `vaddr`, if the tables above it exist.
+    pub(super) fn find(
+        &self,
+        vaddr: VAddr,
+        depth: usize,
+    ) -> Result<Slot, Error> {
+        let mut slot = Slot::root(self.root, vaddr);
+        while slot.depth < depth {
+            let entry = self.entry(&slot);
+            if !entry.is_present() {
+                return Err(Error::NotMapped);
+            }
+            if entry.is_huge() {
+                return Err(Error::HugePage);
+            }
+            slot = slot.next(entry.addr());
+        }
+        Ok(slot)
+    }
+
+    /// Returns the slot at `depth` levels below the PML4 on the walk for
+    /// `vaddr`, allocating and zeroing any missing tables above it.
+    ///
+    /// New tables are writable and user-accessible, so the permissions of a
+    /// page are decided by its leaf entry.
+    pub(super) fn create<A>(
+        &mut self,
+        vaddr: VAddr,
+        depth: usize,
+        alloc: &mut A,
+    ) -> Result<Slot, Error>
+    where
+        A: page::FrameAllocator<Frame = Physical>,
+    {
+        let mut slot = Slot::root(self.root, vaddr);
+        while slot.depth < depth {
+            let entry = self.entry(&slot);
+            if entry.is_present() {
+                if entry.is_huge() {
+                    return Err(Error::HugePage);
+                }
+                slot = slot.next(entry.addr());
+                continue;
+            }
+
+            let frame = unsafe { alloc.alloc() }
+                .map_err(|_| Error::Alloc)?
+                .base_address();
+            self.set_entry(
+                &slot,
+                Entry::new(
+                    frame,
+                    PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER,
+                ),
+            );
+            slot = slot.next(frame);
+            let table = self.access.table::<Pt>(frame, slot.path());
+            unsafe { (*table).zero() };
+        }
+        Ok(slot)
+    }
+
+    /// Translates a virtual page of size `S` to the physical frame it maps.
+    ///
+    /// Returns `None` unless `page` is mapped by a single entry of size `S`:
+    /// a 2 MiB page inside a 1 GiB mapping, or one made up of 4 KiB
+    /// mappings, is not translated. Use `translate` to resolve addresses
+    /// regardless of the size of the page which maps them.
+    pub fn translate_sized<S: MapSize>(
+        &self,
+        page: &Virtual<S>,
+    ) -> Option<Physical<S>> {
+        let slot = self.find(page.base_address(), S::DEPTH).ok()?;
+        let entry = self.entry(&slot);
+        if !is_leaf::<S>(&entry) {
+            return None;
+        }
+        Some(Physical::from_addr_down(entry.addr()))
+    }
+
+    /// Modifies the page tables so that `page` maps to `frame`, with a
+    /// single entry of size `S`.
+    ///
+    /// Any tables above that entry which don't exist yet are allocated from
+    /// `alloc`. 2 MiB and 1 GiB entries have the `HUGE` flag set, and the
+    /// `PAT` flag is moved to where those entries keep it.
+    pub fn map_sized<S, A>(
+        &mut self,
+        page: Virtual<S>,
+        frame: Physical<S>,
+        mut flags: PageFlags,
+        alloc: &mut A,
+    ) -> Result<FlushTlb<S>, Error>
+    where
+        S: MapSize,
+        A: page::FrameAllocator<Frame = Physical>,
+    {
+        flags.remove(PageFlags::OWNED);
+        self.map_leaf(page, frame, flags, alloc)
+    }
+
+    /// Identity maps `frame` with a single entry of size `S`.
+    pub fn identity_map_sized<S, A>(
+        &mut self,
+        frame: Physical<S>,
+        flags: PageFlags,
+        alloc: &mut A,
+    ) -> Result<FlushTlb<S>, Error>
+    where
+        S: MapSize,
+        A: page::FrameAllocator<Frame = Physical>,
+    {
+        let page =
+            Virtual::from_addr_down(VAddr(frame.base_address().0 as usize));
+        self.map_sized(page, frame, flags, alloc)
+    }
+
+    /// Maps `page` to any free, suitably aligned frame of size `S`.
+    ///
+    /// The frame is allocated as a contiguous range of 4 KiB frames, which
+    /// is returned to `alloc` if the page can't be mapped, or when it is
+    /// unmapped.
+    pub fn map_to_any_sized<S, A>(
+        &mut self,
+        page: Virtual<S>,
+        flags: PageFlags,
+        alloc: &mut A,
+    ) -> Result<FlushTlb<S>, Error>
+    where
+        S: MapSize,
+        A: page::FrameAllocator<Frame = Physical>,
+    {
+        let frames =
+            unsafe { alloc.alloc_aligned(S::SIZE / Small::SIZE, S::SIZE) }
+                .map_err(|_| Error::Alloc)?;
+        let frame = Physical::from_addr_down(frames.start_address());
+        let flags = flags | PageFlags::OWNED;
+        let result = self.map_leaf(page, frame, flags, alloc);
+        if result.is_err() {
+            let _ = unsafe { alloc.dealloc_range(frames) };
+        }
+        result
+    }
+
+    /// Unmaps `page`, which must be mapped by a single entry of size `S`.
+    ///
+    /// If the frames it mapped were allocated by `map_to_any`, they are
+    /// returned to `alloc`; frames passed to `map` belong to the caller, and
+    /// are left alone. If `alloc` can't take them back, the page stays
+    /// mapped. Page tables which become empty are not freed.
+    pub fn unmap_sized<S, A>(
+        &mut self,
+        page: Virtual<S>,
+        alloc: &mut A,
+    ) -> Result<FlushTlb<S>, Error>
+    where
+        S: MapSize,
+        A: page::FrameAllocator<Frame = Physical>,
+    {
+        let slot = self.find(page.base_address(), S::DEPTH)?;
+        let entry = self.entry(&slot);
+        if !is_leaf::<S>(&entry) {
+            return Err(Error::NotMapped);
+        }
+        // Free the frames before touching the entry, so that if that fails
+        // the page is still mapped and there's nothing to flush.
+        if entry.flags().contains(PageFlags::OWNED) {
+            let start = entry.addr().align_down(S::SIZE);
+            let frames = page::Range::within(start..start.offset(S::SIZE));
+            unsafe { alloc.dealloc_range(frames) }.map_err(|_| Error::Alloc)?;
+        }
+        self.set_entry(&slot, Entry::unused());
+        Ok(FlushTlb { page })
+    }
+
+    /// Updates the flags on `page`, which must be mapped by a single entry
+    /// of size `S`.
+    ///
+    /// Whether the page's frame is `OWNED` is kept as it was.
+    pub fn set_flags_sized<S: MapSize>(
+        &mut self,
+        page: Virtual<S>,
+        mut flags: PageFlags,
+    ) -> Result<FlushTlb<S>, Error> {
+        let slot = self.find(page.base_address(), S::DEPTH)?;
+        let entry = self.entry(&slot);
+        if !is_leaf::<S>(&entry) {
+            return Err(Error::NotMapped);
+        }
+        flags.remove(PageFlags::OWNED);
+        flags.insert(entry.flags() & PageFlags::OWNED);
+        let addr = entry.addr().align_down(S::SIZE);
+        self.set_entry(&slot, leaf::<S>(addr, flags));
+        Ok(FlushTlb { page })
+    }
+
+    /// Maps `page` to `frame` with exactly `flags`, including `OWNED`.
+    fn map_leaf<S, A>(
+        &mut self,
+        page: Virtual<S>,
+        frame: Physical<S>,
+        flags: PageFlags,
+        alloc: &mut A,
+    ) -> Result<FlushTlb<S>, Error>
+    where
+        S: MapSize,
+        A: page::FrameAllocator<Frame = Physical>,
+    {
+        let slot = self.create(page.base_address(), S::DEPTH, alloc)?;
+        if self.entry(&slot).is_present() {
+            return Err(Error::AlreadyMapped);
+        }
+        self.set_entry(&slot, leaf::<S>(frame.base_address(), flags));
+        Ok(FlushTlb { page })
+    }
 }
 
-impl page::Mapper for ActivePml4 {
+impl<T: StableAccess> ActivePml4<T> {
+    /// Returns the entries of the table in `frame`, reached by `path`.
+    fn entries(&self, frame: PAddr, path: &[usize]) -> &[Entry] {
+        let table = self.access.table::<Pt>(frame, path);
+        unsafe { &*table }.iter().as_slice()
+    }
+}
+
+/// Dumping the tables, through an access which can borrow them all at once.
+///
+/// Through the recursive mapping, the recursive entry itself is followed
+/// like any other, so the dump includes the page tables' own mappings at
+/// the top of the address space.
+impl<T: StableAccess> dump::Tables for ActivePml4<T> {
+    fn root(&self) -> Option<&[Entry]> {
+        Some(self.entries(self.root, &[]))
+    }
+
+    fn table(&self, entry: &Entry, path: &[usize]) -> Option<&[Entry]> {
+        Some(self.entries(entry.addr(), path))
+    }
+}
+
+impl<T: TableAccess> page::Mapper for ActivePml4<T> {
     type Arch = X86_64;
 
     type Virtual = Virtual;
@@ -42,13 +428,33 @@ impl page::Mapper for ActivePml4 {
     /// + `Some(PAddr)` containing the physical address corresponding to
     ///                 `vaddr`, if it is mapped.
     /// + `None`: if the address is not mapped.
-    fn translate(&self, _vaddr: VAddr) -> Option<Self::PAddr> {
-        unimplemented!()
+    fn translate(&self, vaddr: VAddr) -> Option<Self::PAddr> {
+        let mut slot = Slot::root(self.root, vaddr);
+        loop {
+            let entry = self.entry(&slot);
+            if !entry.is_present() {
+                return None;
+            }
+            if slot.depth == SHIFTS.len() - 1 || entry.is_huge() {
+                if slot.depth == 0 {
+                    // The `HUGE` bit is reserved in a PML4 entry.
+                    return None;
+                }
+                let size = 1 << SHIFTS[slot.depth];
+                let offset = vaddr.as_usize() % size;
+                return Some(entry.addr().align_down(size).offset(offset));
+            }
+            slot = slot.next(entry.addr());
+        }
     }
 
     /// Translates a virtual page to a physical frame.
-    fn translate_page(&self, _page: Self::Virtual) -> Option<Self::Physical> {
-        unimplemented!()
+    ///
+    /// If the page is inside a 2 MiB or 1 GiB page, this is the 4 KiB frame
+    /// within it.
+    fn translate_page(&self, page: Self::Virtual) -> Option<Self::Physical> {
+        self.translate(page.base_address())
+            .map(Physical::from_addr_down)
     }
 
     /// Modifies the page tables so that `page` maps to `frame`.
@@ -60,15 +466,15 @@ impl page::Mapper for ActivePml4 {
     /// + `alloc`: a memory allocator
     fn map<A>(
         &mut self,
-        _page: Self::Virtual,
-        _frame: Self::Physical,
-        _flags: Self::Flags,
-        _alloc: &mut A,
+        page: Self::Virtual,
+        frame: Self::Physical,
+        flags: Self::Flags,
+        alloc: &mut A,
     ) -> Result<Self::Update, Self::Error>
     where
         A: page::FrameAllocator<Frame = Self::Physical>,
     {
-        unimplemented!()
+        self.map_sized(page, frame, flags, alloc)
     }
 
     /// Identity maps a given `frame`.
@@ -79,14 +485,14 @@ impl page::Mapper for ActivePml4 {
     /// + `alloc`: a memory allocator
     fn identity_map<A>(
         &mut self,
-        _frame: Self::Physical,
-        _flags: Self::Flags,
-        _alloc: &mut A,
+        frame: Self::Physical,
+        flags: Self::Flags,
+        alloc: &mut A,
     ) -> Result<Self::Update, Self::Error>
     where
         A: page::FrameAllocator<Frame = Self::Physical>,
     {
-        unimplemented!()
+        self.identity_map_sized(frame, flags, alloc)
     }
 
     /// Maps the given `VirtualPage` to any free frame.
@@ -100,36 +506,888 @@ impl page::Mapper for ActivePml4 {
     /// + `alloc`: a memory allocator
     fn map_to_any<A>(
         &mut self,
-        _page: Self::Virtual,
-        _flags: Self::Flags,
-        _alloc: &mut A,
+        page: Self::Virtual,
+        flags: Self::Flags,
+        alloc: &mut A,
     ) -> Result<Self::Update, Self::Error>
     where
         A: page::FrameAllocator<Frame = Self::Physical>,
     {
-        unimplemented!()
+        let frame = unsafe { alloc.alloc() }.map_err(|_| Error::Alloc)?;
+        let addr = frame.base_address();
+        let flags = flags | PageFlags::OWNED;
+        let result = self.map_leaf(page, frame, flags, alloc);
+        if result.is_err() {
+            let _ = unsafe { alloc.dealloc(Physical::from_addr_down(addr)) };
+        }
+        result
     }
 
     /// Unmaps the given `VirtualPage`.
     ///
-    /// All freed frames are returned to the given `page::FrameAllocator`.
+    /// If its frame was allocated by `map_to_any`, it is returned to the
+    /// given `page::FrameAllocator`.
     fn unmap<A>(
         &mut self,
-        _page: Self::Virtual,
-        _alloc: &mut A,
+        page: Self::Virtual,
+        alloc: &mut A,
     ) -> Result<Self::Update, Self::Error>
     where
         A: page::FrameAllocator<Frame = Self::Physical>,
     {
-        unimplemented!()
+        self.unmap_sized(page, alloc)
     }
 
     /// Updates the flags on the given `page`.
     fn set_flags(
         &mut self,
-        _page: Self::Virtual,
-        _flags: Self::Flags,
+        page: Self::Virtual,
+        flags: Self::Flags,
     ) -> Result<Self::Update, Self::Error> {
-        unimplemented!()
+        self.set_flags_sized(page, flags)
+    }
+}
+
+// ===== impl PageFlags =====
+
+impl PageFlags {
+    /// The entry is valid.
+    pub const PRESENT: PageFlags = PageFlags(1 << 0);
+    /// Writes are allowed.
+    pub const WRITABLE: PageFlags = PageFlags(1 << 1);
+    /// User-mode accesses are allowed.
+    pub const USER: PageFlags = PageFlags(1 << 2);
+    /// Writes go straight to memory (selects the memory type with `NO_CACHE`
+    /// and `PAT`).
+    pub const WRITE_THROUGH: PageFlags = PageFlags(1 << 3);
+    /// Accesses are not cached (selects the memory type with
+    /// `WRITE_THROUGH` and `PAT`).
+    pub const NO_CACHE: PageFlags = PageFlags(1 << 4);
+    /// Set by the MMU when the entry is used for a translation.
+    pub const ACCESSED: PageFlags = PageFlags(1 << 5);
+    /// Set by the MMU when the page is written to.
+    pub const DIRTY: PageFlags = PageFlags(1 << 6);
+    /// In a PDPT or PD entry, the entry maps a 1 GiB or 2 MiB page rather
+    /// than pointing to another table.
+    pub const HUGE: PageFlags = PageFlags(1 << 7);
+    /// In a PT entry, selects the upper half of the PAT (with
+    /// `WRITE_THROUGH` and `NO_CACHE`).
+    ///
+    /// This is the same bit as `HUGE`; in a 2 MiB or 1 GiB page entry, the
+    /// PAT bit is bit 12 instead.
+    pub const PAT: PageFlags = PageFlags(1 << 7);
+    /// The translation is not flushed from the TLB when CR3 is written.
+    pub const GLOBAL: PageFlags = PageFlags(1 << 8);
+    /// Instruction fetches are not allowed.
+    pub const NO_EXECUTE: PageFlags = PageFlags(1 << 63);
+
+    /// The bits which are ignored by the MMU and free for the kernel to use:
+    /// bits 9 to 11 and 52 to 58.
+    pub const AVAILABLE: PageFlags = PageFlags(0b111 << 9 | 0x7f << 52);
+    /// Marks a page whose frame was allocated by `ActivePml4::map_to_any`,
+    /// so that unmapping it frees the frame.
+    ///
+    /// This is the highest available bit. `ActivePml4` ignores it in flags
+    /// passed to `map` and `set_flags`, so frames that the caller mapped
+    /// itself, such as MMIO or the kernel image, are never freed.
+    pub const OWNED: PageFlags = PageFlags(1 << 58);
+
+    /// Returns flags with no bits set.
+    pub const fn empty() -> Self {
+        PageFlags(0)
+    }
+
+    /// Returns flags with the given raw bits set.
+    #[inline]
+    pub const fn from_bits(bits: u64) -> Self {
+        PageFlags(bits)
+    }
+
+    /// Returns the raw bits.
+    #[inline]
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns `true` if no flags are set.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if every flag in `other` is set.
+    #[inline]
+    pub fn contains(&self, other: PageFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Sets the flags in `other`.
+    #[inline]
+    pub fn insert(&mut self, other: PageFlags) {
+        self.0 |= other.0;
+    }
+
+    /// Clears the flags in `other`.
+    #[inline]
+    pub fn remove(&mut self, other: PageFlags) {
+        self.0 &= !other.0;
+    }
+
+    /// Returns the ten available bits, packed together with bits 9 to 11 at
+    /// the bottom.
+    pub fn available(&self) -> u16 {
+        ((self.0 >> 9) & 0b111 | (self.0 >> 52 & 0x7f) << 3) as u16
+    }
+
+    /// Sets the available bits to `value`, packed as by `available`.
+    ///
+    /// Bits of `value` above the tenth are ignored.
+    pub fn set_available(&mut self, value: u16) {
+        let value = u64::from(value);
+        self.remove(Self::AVAILABLE);
+        self.0 |= (value & 0b111) << 9 | (value >> 3 & 0x7f) << 52;
+    }
+}
+
+impl From<Permissions> for PageFlags {
+    /// Converts architecture-independent permissions into the flags for a
+    /// 4 KiB page.
+    ///
+    /// x86_64 can't make a present page unreadable, so `read` is implied:
+    /// the flags always include `PRESENT`, even for permissions which allow
+    /// no access at all. A page which must not be accessed, such as a guard
+    /// page, should be left unmapped instead. The cache policies select PAT
+    /// entries 0 to 4, assuming the power-on PAT layout with entry 4
+    /// reprogrammed as write-combining; if it hasn't been, `WriteCombining`
+    /// pages are write-back.
+    fn from(permissions: Permissions) -> Self {
+        let mut flags = PageFlags::PRESENT;
+        if permissions.write {
+            flags.insert(PageFlags::WRITABLE);
+        }
+        if !permissions.execute {
+            flags.insert(PageFlags::NO_EXECUTE);
+        }
+        if permissions.user {
+            flags.insert(PageFlags::USER);
+        }
+        if permissions.global {
+            flags.insert(PageFlags::GLOBAL);
+        }
+        flags.insert(match permissions.cache {
+            CachePolicy::WriteBack => PageFlags::empty(),
+            CachePolicy::WriteThrough => PageFlags::WRITE_THROUGH,
+            CachePolicy::Uncached => {
+                PageFlags::NO_CACHE | PageFlags::WRITE_THROUGH
+            },
+            CachePolicy::WriteCombining => PageFlags::PAT,
+        });
+        flags
+    }
+}
+
+impl ops::BitOr for PageFlags {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        PageFlags(self.0 | rhs.0)
+    }
+}
+
+impl ops::BitOrAssign for PageFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl ops::BitAnd for PageFlags {
+    type Output = Self;
+    fn bitand(self, rhs: Self) -> Self {
+        PageFlags(self.0 & rhs.0)
+    }
+}
+
+impl ops::Not for PageFlags {
+    type Output = Self;
+    fn not(self) -> Self {
+        PageFlags(!self.0)
+    }
+}
+
+impl PageFlags {
+    /// Writes the names of the set flags to `f`, calling bit 7 `bit_7`.
+    ///
+    /// Bit 7 is `HUGE` in an entry, but `Mapping`s report it as `PAT`.
+    pub(super) fn write_names(
+        &self,
+        f: &mut fmt::Formatter,
+        bit_7: &str,
+    ) -> fmt::Result {
+        const NAMES: [(PageFlags, &str); 11] = [
+            (PageFlags::PRESENT, "PRESENT"),
+            (PageFlags::WRITABLE, "WRITABLE"),
+            (PageFlags::USER, "USER"),
+            (PageFlags::WRITE_THROUGH, "WRITE_THROUGH"),
+            (PageFlags::NO_CACHE, "NO_CACHE"),
+            (PageFlags::ACCESSED, "ACCESSED"),
+            (PageFlags::DIRTY, "DIRTY"),
+            (PageFlags::HUGE, "HUGE"),
+            (PageFlags::GLOBAL, "GLOBAL"),
+            (PageFlags::NO_EXECUTE, "NO_EXECUTE"),
+            (PageFlags::AVAILABLE, "AVAILABLE"),
+        ];
+        f.write_str("PageFlags(")?;
+        let mut rest = *self;
+        let mut first = true;
+        for &(flag, name) in NAMES.iter() {
+            if self.0 & flag.0 == 0 {
+                continue;
+            }
+            if !first {
+                f.write_str(" | ")?;
+            }
+            first = false;
+            if flag == PageFlags::AVAILABLE {
+                write!(f, "AVAILABLE({:#x})", self.available())?;
+            } else if flag == PageFlags::HUGE {
+                f.write_str(bit_7)?;
+            } else {
+                f.write_str(name)?;
+            }
+            rest.remove(flag);
+        }
+        if !rest.is_empty() {
+            if !first {
+                f.write_str(" | ")?;
+            }
+            write!(f, "{:#x}", rest.0)?;
+        } else if first {
+            f.write_str("empty")?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl fmt::Debug for PageFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_names(f, "HUGE")
+    }
+}
+
+// ===== impl MapSize =====
+
+impl MapSize for Small {
+    const HUGE: bool = false;
+    const DEPTH: usize = 3;
+}
+
+impl MapSize for size::Large {
+    const HUGE: bool = true;
+    const DEPTH: usize = 2;
+}
+
+impl MapSize for size::Huge {
+    const HUGE: bool = true;
+    const DEPTH: usize = 1;
+}
+
+// ===== impl Slot =====
+
+impl Slot {
+    /// Returns the slot of the PML4 entry on the walk for `vaddr`, in the
+    /// PML4 at `root`.
+    fn root(root: PAddr, vaddr: VAddr) -> Self {
+        let mut path = [0; 4];
+        for (index, &shift) in path.iter_mut().zip(SHIFTS.iter()) {
+            *index = (vaddr.as_usize() >> shift) & Pml4::INDEX_MASK;
+        }
+        Slot {
+            table: root,
+            path,
+            depth: 0,
+        }
+    }
+
+    /// Returns the slot one level down the walk, in the table at `table`.
+    fn next(&self, table: PAddr) -> Self {
+        Slot {
+            table,
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+
+    /// Returns the indices of the entries followed to reach the table.
+    fn path(&self) -> &[usize] {
+        &self.path[..self.depth]
+    }
+
+    /// Returns the index of the entry in its table.
+    fn index(&self) -> usize {
+        self.path[self.depth]
+    }
+}
+
+// ===== impl Level =====
+
+impl Level for Pml4 {
+    const ADDR_SHIFT: usize = 39;
+}
+
+impl Sublevel for Pml4 {
+    type Next = Pdpt;
+}
+
+impl Level for Pdpt {
+    const ADDR_SHIFT: usize = 30;
+}
+
+impl Sublevel for Pdpt {
+    type Next = Pd;
+}
+
+impl Level for Pd {
+    const ADDR_SHIFT: usize = 21;
+}
+
+impl Sublevel for Pd {
+    type Next = Pt;
+}
+
+impl Level for Pt {
+    const ADDR_SHIFT: usize = 12;
+}
+
+// ===== impl Table =====
+
+impl<L: Level> Table<Entry, L> {
+    /// Marks every entry in the table as unused.
+    pub fn zero(&mut self) {
+        for entry in self.iter_mut() {
+            entry.set_unused();
+        }
+    }
+
+    /// Returns `true` if every entry in the table is unused.
+    pub fn is_empty(&self) -> bool {
+        self.iter().all(Entry::is_unused)
+    }
+}
+
+/// Navigation to the next table down.
+///
+/// This is only implemented for levels with a `Sublevel::Next`, so walking
+/// past a PT is a compile-time error.
+///
+/// # Unsafety
+/// The next table is found by shifting the address of `self` through the
+/// recursive mapping, so these methods must only be called on tables which
+/// were themselves reached through it, such as the table at
+/// `ActivePml4::PML4_ADDR` and those below it. On any other table, such as
+/// one reached through `PhysOffset`, they dereference an unrelated address.
+impl<L: Sublevel> Table<Entry, L> {
+    /// Returns the table pointed to by entry `i`, if it is present and is
+    /// not a 2 MiB or 1 GiB page.
+    ///
+    /// # Unsafety
+    /// `self` must have been reached through the recursive mapping.
+    pub unsafe fn next_table<I>(&self, i: I) -> Option<&Table<Entry, L::Next>>
+    where
+        L: IndexedBy<I>,
+    {
+        self.recursive_addr(L::index_of(i))
+            .map(|addr| &*(addr as *const _))
+    }
+
+    /// Returns the table pointed to by entry `i` mutably, if it is present
+    /// and is not a 2 MiB or 1 GiB page.
+    ///
+    /// # Unsafety
+    /// `self` must have been reached through the recursive mapping.
+    pub unsafe fn next_table_mut<I>(
+        &mut self,
+        i: I,
+    ) -> Option<&mut Table<Entry, L::Next>>
+    where
+        L: IndexedBy<I>,
+    {
+        self.recursive_addr(L::index_of(i))
+            .map(|addr| &mut *(addr as *mut _))
+    }
+
+    /// Returns the table pointed to by entry `i`, allocating and zeroing a
+    /// new one from `alloc` if the entry is unused.
+    ///
+    /// New tables are writable and user-accessible, so the permissions of a
+    /// page are decided by its leaf entry.
+    ///
+    /// # Unsafety
+    /// `self` must have been reached through the recursive mapping.
+    pub unsafe fn next_table_create<I, A>(
+        &mut self,
+        i: I,
+        alloc: &mut A,
+    ) -> Result<&mut Table<Entry, L::Next>, Error>
+    where
+        L: IndexedBy<I>,
+        A: page::FrameAllocator<Frame = Physical>,
+    {
+        self.next_table_create_at(L::index_of(i), alloc)
+    }
+
+    unsafe fn next_table_create_at<A>(
+        &mut self,
+        index: usize,
+        alloc: &mut A,
+    ) -> Result<&mut Table<Entry, L::Next>, Error>
+    where
+        A: page::FrameAllocator<Frame = Physical>,
+    {
+        let entry = self[index];
+        if entry.is_present() {
+            if entry.is_huge() {
+                return Err(Error::HugePage);
+            }
+            return Ok(self.next_table_mut(index).expect("entry is present"));
+        }
+
+        let frame = alloc.alloc().map_err(|_| Error::Alloc)?;
+        self[index] = Entry::new(
+            frame.base_address(),
+            PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER,
+        );
+        let next = self.next_table_mut(index).expect("entry is present");
+        next.zero();
+        Ok(next)
+    }
+
+    /// Returns the virtual address of the table pointed to by entry `index`,
+    /// if there is one.
+    fn recursive_addr(&self, index: usize) -> Option<usize> {
+        let entry = self[index];
+        if !entry.is_present() || entry.is_huge() {
+            return None;
+        }
+        Some(recursive_addr(self as *const _ as usize, index))
+    }
+}
+
+/// Returns the virtual address, through the recursive mapping, of the table
+/// pointed to by entry `index` of the table at `table`.
+pub(super) fn recursive_addr(table: usize, index: usize) -> usize {
+    (table << 9) | (index << 12)
+}
+
+/// Returns a leaf entry mapping a page of size `S` at `addr` with `flags`.
+///
+/// 2 MiB and 1 GiB entries have the `HUGE` flag set, and their `PAT` bit
+/// moved to `Entry::HUGE_PAT`.
+fn leaf<S: MapSize>(addr: PAddr, mut flags: PageFlags) -> Entry {
+    flags.insert(PageFlags::PRESENT);
+    if !S::HUGE {
+        return Entry::new(addr, flags);
+    }
+    let pat = if flags.contains(PageFlags::PAT) {
+        Entry::HUGE_PAT
+    } else {
+        0
+    };
+    flags.insert(PageFlags::HUGE);
+    Entry::new(PAddr(addr.0 | pat), flags)
+}
+
+/// Returns `true` if `entry` maps a page of size `S`, rather than pointing
+/// to another table.
+fn is_leaf<S: MapSize>(entry: &Entry) -> bool {
+    // In a PT entry, the `HUGE` bit is the `PAT` bit.
+    entry.is_present() && (!S::HUGE || entry.is_huge())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x64::page::{
+        access::PhysOffset,
+        sim::{PhysMemory, RawFrame, SimPageTable},
+        tests::TestAlloc,
+    };
+    use hal9000::mem::page::{FrameAllocator, Mapper};
+    extern crate std;
+    use self::std::{format, iter, vec::Vec};
+
+    /// The physical address of the first simulated frame.
+    const SIM_BASE: PAddr = PAddr(0x10_0000);
+
+    fn sim_frames(n: usize) -> Vec<RawFrame> {
+        iter::repeat(RawFrame::ZERO).take(n).collect()
+    }
+
+    /// Returns a mapper over `frames`, which are at `SIM_BASE` and reached
+    /// through a `PhysOffset`, and an allocator for the rest of them.
+    fn sim_mapper(
+        frames: &mut [RawFrame],
+    ) -> (ActivePml4<PhysOffset>, TestAlloc) {
+        let end = SIM_BASE.offset(frames.len() * Small::SIZE);
+        let mut alloc = TestAlloc::new(SIM_BASE, end);
+        let root = unsafe { alloc.alloc() }.unwrap().base_address();
+        let offset =
+            (frames.as_mut_ptr() as usize).wrapping_sub(SIM_BASE.0 as usize);
+        let access = unsafe { PhysOffset::new(offset) };
+        (unsafe { ActivePml4::from_access(root, access) }, alloc)
+    }
+
+    #[test]
+    fn flags_from_permissions() {
+        let kernel_data = PageFlags::from(Permissions::READ_WRITE);
+        assert_eq!(
+            kernel_data,
+            PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::NO_EXECUTE
+        );
+        let kernel_text = PageFlags::from(Permissions {
+            global: true,
+            ..Permissions::READ_EXECUTE
+        });
+        assert_eq!(kernel_text, PageFlags::PRESENT | PageFlags::GLOBAL);
+        let framebuffer = PageFlags::from(Permissions {
+            user: true,
+            cache: CachePolicy::WriteCombining,
+            ..Permissions::READ_WRITE
+        });
+        assert!(framebuffer.contains(PageFlags::USER | PageFlags::PAT));
+        let mmio = PageFlags::from(Permissions {
+            cache: CachePolicy::Uncached,
+            ..Permissions::READ_WRITE
+        });
+        assert!(mmio.contains(PageFlags::NO_CACHE | PageFlags::WRITE_THROUGH));
+        let no_access = PageFlags::from(Permissions {
+            read: false,
+            ..Permissions::READ
+        });
+        assert_eq!(no_access, PageFlags::PRESENT | PageFlags::NO_EXECUTE);
+    }
+
+    #[test]
+    fn available_bits() {
+        let mut flags = PageFlags::PRESENT | PageFlags::NO_EXECUTE;
+        flags.set_available(0x3ff);
+        assert_eq!(flags.available(), 0x3ff);
+        assert_eq!(
+            flags & !PageFlags::AVAILABLE,
+            PageFlags::PRESENT | PageFlags::NO_EXECUTE
+        );
+        flags.set_available(0b101_0101);
+        assert_eq!(flags.available(), 0b101_0101);
+        assert_eq!(flags.bits() >> 9 & 0b111, 0b101);
+        assert_eq!(flags.bits() >> 52 & 0x7f, 0b1010);
+    }
+
+    #[test]
+    fn flags_debug() {
+        let flags =
+            PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::NO_EXECUTE;
+        assert_eq!(
+            format!("{:?}", flags),
+            "PageFlags(PRESENT | WRITABLE | NO_EXECUTE)"
+        );
+        assert_eq!(format!("{:?}", PageFlags::empty()), "PageFlags(empty)");
+        let mut odd = PageFlags::from_bits(1 << 12);
+        odd.set_available(1);
+        assert_eq!(format!("{:?}", odd), "PageFlags(AVAILABLE(0x1) | 0x1000)");
+    }
+
+    #[test]
+    fn level_indices() {
+        let vaddr = VAddr(0xffff_8123_4567_89ab);
+        assert_eq!(<Pml4 as IndexedBy<VAddr>>::index_of(vaddr), 258);
+        assert_eq!(<Pdpt as IndexedBy<VAddr>>::index_of(vaddr), 141);
+        assert_eq!(<Pd as IndexedBy<VAddr>>::index_of(vaddr), 43);
+        assert_eq!(<Pt as IndexedBy<VAddr>>::index_of(vaddr), 120);
+    }
+
+    #[test]
+    fn zero_and_is_empty() {
+        let mut frame = sim::RawFrame::ZERO;
+        let mut memory =
+            sim::PhysMemory::new(PAddr(0), core::slice::from_mut(&mut frame));
+        let table = memory.table_mut::<Pt>(PAddr(0)).unwrap();
+        assert!(table.is_empty());
+        table[3] = Entry::new(PAddr(0x1000), PageFlags::PRESENT);
+        assert!(!table.is_empty());
+        assert_eq!(table.iter().filter(|e| e.is_present()).count(), 1);
+        table.zero();
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn next_table_stops_at_leaves() {
+        let mut frame = sim::RawFrame::ZERO;
+        let mut memory =
+            sim::PhysMemory::new(PAddr(0), core::slice::from_mut(&mut frame));
+        let pd = memory.table_mut::<Pd>(PAddr(0)).unwrap();
+        pd[1] =
+            Entry::new(PAddr(0x20_0000), PageFlags::PRESENT | PageFlags::HUGE);
+        pd[2] = Entry::new(PAddr(0x5000), PageFlags::WRITABLE);
+        // Neither entry points to a table, so nothing is dereferenced.
+        unsafe {
+            assert!(pd.next_table(0usize).is_none());
+            assert!(pd.next_table(1usize).is_none());
+            assert!(pd.next_table_mut(2usize).is_none());
+            let mut alloc = TestAlloc::new(PAddr(0x1000), PAddr(0x2000));
+            let result = pd.next_table_create(1usize, &mut alloc);
+            assert_eq!(result.err(), Some(Error::HugePage));
+            assert_eq!(alloc.allocated(), 0);
+        }
+    }
+
+    #[test]
+    fn recursive_addresses() {
+        let pml4 = ActivePml4::PML4_ADDR;
+        assert_eq!(recursive_addr(pml4, ActivePml4::RECURSIVE_INDEX), pml4);
+        let pdpt = recursive_addr(pml4, 0);
+        assert_eq!(pdpt, 0xffff_ffff_ffe0_0000);
+        let pd = recursive_addr(pdpt, 1);
+        assert_eq!(pd, 0xffff_ffff_c000_1000);
+        let pt = recursive_addr(pd, 2);
+        assert_eq!(pt, 0xffff_ff80_0020_2000);
+
+        let high = recursive_addr(pml4, 256);
+        assert_eq!(high, 0xffff_ffff_fff0_0000);
+    }
+
+    #[test]
+    fn page_sizes() {
+        use self::size::{Huge, Large};
+        assert_eq!(Small::SIZE, 0x1000);
+        assert_eq!(Large::SIZE, 0x20_0000);
+        assert_eq!(Huge::SIZE, 0x4000_0000);
+        assert_eq!(<Physical<Large> as Page>::SHIFT, 21);
+        assert_eq!(<Virtual<Huge> as Page>::SHIFT, 30);
+
+        let page = Physical::<Large>::from_addr_down(PAddr(0x0034_5678));
+        assert_eq!(page.base_address(), PAddr(0x0020_0000));
+        assert_eq!(page.end_address(), PAddr(0x0040_0000));
+    }
+
+    #[test]
+    fn leaf_entries() {
+        use self::size::{Huge, Large};
+        let flags = PageFlags::WRITABLE | PageFlags::PAT;
+
+        let small = leaf::<Small>(PAddr(0x1000), flags);
+        assert_eq!(small.bits(), 0x1000 | 1 << 7 | 0b11);
+        assert!(is_leaf::<Small>(&small));
+
+        let large = leaf::<Large>(PAddr(0x20_0000), flags);
+        assert_eq!(large.bits() & Entry::HUGE_PAT, Entry::HUGE_PAT);
+        assert!(large.is_huge());
+        let frame = large.frame().map(|frame| frame.base_address());
+        assert_eq!(frame, Some(PAddr(0x20_0000)));
+        assert!(is_leaf::<Large>(&large));
+
+        let huge = leaf::<Huge>(PAddr(0x4000_0000), PageFlags::WRITABLE);
+        assert_eq!(huge.bits() & Entry::HUGE_PAT, 0);
+        assert_eq!(huge.addr(), PAddr(0x4000_0000));
+
+        let table = Entry::new(PAddr(0x5000), PageFlags::PRESENT);
+        assert!(!is_leaf::<Large>(&table));
+        assert!(!is_leaf::<Huge>(&Entry::unused()));
+    }
+
+    /// Walks the tables in `frames` from `root` with the simulated MMU.
+    fn sim_walk(
+        frames: &mut [RawFrame],
+        root: PAddr,
+        vaddr: usize,
+    ) -> Option<sim::Translation> {
+        let sim = SimPageTable::new(PhysMemory::new(SIM_BASE, frames), root);
+        sim.walk(VAddr(vaddr)).ok()
+    }
+
+    #[test]
+    fn mapper_matches_sim() {
+        let mut frames = sim_frames(16);
+        let (mut active, mut alloc) = sim_mapper(&mut frames);
+        let root = active.root();
+        let rw = PageFlags::WRITABLE | PageFlags::NO_EXECUTE;
+
+        let pages = [
+            (0x40_0000, 0x7_0000, rw),
+            (0x40_1000, 0x7_1000, PageFlags::USER),
+            (0x7fff_ffff_f000, 0x7_2000, rw | PageFlags::USER),
+            (0xffff_8000_0000_0000, 0x7_3000, PageFlags::NO_EXECUTE),
+        ];
+        for &(vaddr, paddr, flags) in &pages {
+            let page = Virtual::from_addr_down(VAddr(vaddr));
+            let frame = Physical::from_addr_down(PAddr(paddr));
+            let _ = active.map(page, frame, flags, &mut alloc).unwrap();
+        }
+        for &(vaddr, paddr, flags) in &pages {
+            let vaddr = vaddr + 0x123;
+            let walked = sim_walk(&mut frames, root, vaddr).unwrap();
+            assert_eq!(walked.paddr, PAddr(paddr + 0x123));
+            assert_eq!(active.translate(VAddr(vaddr)), Some(walked.paddr));
+            assert_eq!(walked.writable, flags.contains(PageFlags::WRITABLE));
+            assert_eq!(walked.user, flags.contains(PageFlags::USER));
+            assert_eq!(
+                walked.executable,
+                !flags.contains(PageFlags::NO_EXECUTE)
+            );
+        }
+        assert!(sim_walk(&mut frames, root, 0x40_2000).is_none());
+        assert_eq!(active.translate(VAddr(0x40_2000)), None);
+
+        let page = Virtual::from_addr_down(VAddr(0x40_0000));
+        let _ = active.set_flags(page, PageFlags::USER).unwrap();
+        let walked = sim_walk(&mut frames, root, 0x40_0000).unwrap();
+        assert!(walked.user && walked.executable && !walked.writable);
+
+        let _ = active.unmap(page, &mut alloc).unwrap();
+        assert!(sim_walk(&mut frames, root, 0x40_0000).is_none());
+        assert_eq!(active.translate(VAddr(0x40_0000)), None);
+        let walked = sim_walk(&mut frames, root, 0x40_1000).unwrap();
+        assert_eq!(walked.paddr, PAddr(0x7_1000));
+        assert_eq!(
+            active.unmap(page, &mut alloc).err(),
+            Some(Error::NotMapped)
+        );
+    }
+
+    #[test]
+    fn unmap_keeps_caller_frames() {
+        let mut frames = sim_frames(8);
+        let (mut active, mut alloc) = sim_mapper(&mut frames);
+        let rw = PageFlags::WRITABLE | PageFlags::NO_EXECUTE;
+
+        // An MMIO frame, which didn't come from `alloc`. Asking for `OWNED`
+        // doesn't make it so.
+        let mmio = Physical::from_addr_down(PAddr(0xfee0_0000));
+        let _ = active
+            .identity_map(mmio, rw | PageFlags::OWNED, &mut alloc)
+            .unwrap();
+        let tables = alloc.allocated();
+        let page = Virtual::from_addr_down(VAddr(0xfee0_0000));
+        let _ = active.unmap(page, &mut alloc).unwrap();
+        assert_eq!(active.translate(VAddr(0xfee0_0000)), None);
+        assert_eq!(alloc.allocated(), tables);
+
+        // A frame from `map_to_any` stays owned when its flags change.
+        let page = Virtual::from_addr_down(VAddr(0xfee0_1000));
+        let _ = active.map_to_any(page, rw, &mut alloc).unwrap();
+        assert_eq!(alloc.allocated(), tables + 1);
+        let _ = active.set_flags(page, PageFlags::NO_EXECUTE).unwrap();
+        let _ = active.unmap(page, &mut alloc).unwrap();
+        assert_eq!(alloc.allocated(), tables);
+    }
+
+    /// An allocator which can't take frames back.
+    struct NoDealloc(TestAlloc);
+
+    unsafe impl FrameAllocator for NoDealloc {
+        type Frame = Physical;
+        type Error = ();
+
+        unsafe fn alloc(&mut self) -> Result<Physical, ()> {
+            self.0.alloc()
+        }
+
+        unsafe fn dealloc(&mut self, _: Physical) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn failed_unmap_keeps_page() {
+        let mut frames = sim_frames(8);
+        let (mut active, alloc) = sim_mapper(&mut frames);
+        let mut alloc = NoDealloc(alloc);
+        let page = Virtual::from_addr_down(VAddr(0x1000));
+        let _ = active
+            .map_to_any(page, PageFlags::WRITABLE, &mut alloc)
+            .unwrap();
+        let paddr = active.translate(VAddr(0x1000));
+        assert!(paddr.is_some());
+
+        let result = active.unmap(page, &mut alloc);
+        assert_eq!(result.err(), Some(Error::Alloc));
+        assert_eq!(active.translate(VAddr(0x1000)), paddr);
+
+        // A 2 MiB page of the caller's never reaches the allocator at all.
+        let large = Virtual::<size::Large>::from_addr_down(VAddr(0x20_0000));
+        let frame = Physical::from_addr_down(PAddr(0x4000_0000));
+        let flags = PageFlags::WRITABLE;
+        let _ = active.map_sized(large, frame, flags, &mut alloc).unwrap();
+        let _ = active.unmap_sized(large, &mut alloc).unwrap();
+        assert_eq!(active.translate(VAddr(0x20_0000)), None);
+    }
+
+    #[test]
+    fn phys_offset_mapper() {
+        use self::size::{Huge, Large};
+
+        let mut frames = sim_frames(16);
+        let (mut active, mut alloc) = sim_mapper(&mut frames);
+        let (base, root) = (SIM_BASE, active.root());
+        let rw = PageFlags::WRITABLE | PageFlags::NO_EXECUTE;
+
+        let small = Virtual::<Small>::from_addr_down(VAddr(0x1000));
+        let frame = Physical::<Small>::from_addr_down(PAddr(0x7_0000));
+        let _ = active.map_sized(small, frame, rw, &mut alloc).unwrap();
+        let large = Virtual::<Large>::from_addr_down(VAddr(0x20_0000));
+        let frame = Physical::<Large>::from_addr_down(PAddr(0x40_0000));
+        let _ = active
+            .map_sized(large, frame, rw | PageFlags::PAT, &mut alloc)
+            .unwrap();
+        let huge = Virtual::<Huge>::from_addr_down(VAddr(0x40_0000_0000));
+        let frame = Physical::<Huge>::from_addr_down(PAddr(0x8000_0000));
+        let _ = active.map_sized(huge, frame, rw, &mut alloc).unwrap();
+        // The PML4, one PDPT, one PD and one PT.
+        assert_eq!(alloc.allocated(), 4);
+
+        assert_eq!(active.translate(VAddr(0x1234)), Some(PAddr(0x7_0234)));
+        assert_eq!(active.translate(VAddr(0x21_2345)), Some(PAddr(0x41_2345)));
+        assert_eq!(
+            active.translate(VAddr(0x40_1234_5678)),
+            Some(PAddr(0x9234_5678))
+        );
+        assert_eq!(active.translate(VAddr(0x2000)), None);
+        let frame = active.translate_sized(&large).map(|f| f.base_address());
+        assert_eq!(frame, Some(PAddr(0x40_0000)));
+        assert!(active
+            .translate_sized(&Virtual::<Large>::from_addr_down(VAddr(0)))
+            .is_none());
+
+        let inside = Virtual::<Small>::from_addr_down(VAddr(0x40_0000_1000));
+        let result = active.map_to_any(inside, rw, &mut alloc);
+        assert_eq!(result.err(), Some(Error::HugePage));
+        let frame = Physical::<Small>::from_addr_down(PAddr(0x7_0000));
+        let result = active.map_sized(small, frame, rw, &mut alloc);
+        assert_eq!(result.err(), Some(Error::AlreadyMapped));
+        assert_eq!(alloc.allocated(), 4);
+
+        let page = Virtual::<Small>::from_addr_down(VAddr(0x3000));
+        let _ = active.map_to_any(page, rw, &mut alloc).unwrap();
+        assert_eq!(alloc.allocated(), 5);
+        let _ = active.unmap(page, &mut alloc).unwrap();
+        assert_eq!(alloc.allocated(), 4);
+        assert_eq!(active.translate(VAddr(0x3000)), None);
+
+        // The tables were written through the offset, so they're the ones
+        // the simulated MMU walks.
+        let sim = SimPageTable::new(PhysMemory::new(base, &mut frames), root);
+        for &vaddr in &[0x1234, 0x21_2345, 0x40_1234_5678] {
+            let vaddr = VAddr(vaddr);
+            let walked = sim.walk(vaddr).ok().map(|t| t.paddr);
+            assert_eq!(walked, active.translate(vaddr));
+        }
+        let mappings = dump::Tables::mappings(&active).collect::<Vec<_>>();
+        assert_eq!(mappings, dump::Tables::mappings(&sim).collect::<Vec<_>>());
+        assert_eq!(mappings.len(), 3);
+        assert_eq!(mappings[1].page_size, Large::SIZE);
+        // In a PD entry, bit 7 is `HUGE`, so the `PAT` flag has to have been
+        // moved to `HUGE_PAT`.
+        let slot = active.find(VAddr(0x20_0000), 2).unwrap();
+        let bits = active.entry(&slot).bits();
+        assert_eq!(bits & Entry::HUGE_PAT, Entry::HUGE_PAT);
+        let slot = active.find(VAddr(0x40_0000_0000), 1).unwrap();
+        assert_eq!(active.entry(&slot).bits() & Entry::HUGE_PAT, 0);
     }
 }